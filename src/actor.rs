@@ -0,0 +1,184 @@
+//! A cache owned by a dedicated thread and driven over a channel.
+//!
+//! Only the actor thread ever touches the cache, so there is no lock to
+//! contend on; clients send commands through a bounded queue and wait for the
+//! reply. A full queue blocks senders until the actor catches up.
+
+use std::hash::{BuildHasher, Hash};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::Duration;
+
+use crate::eviction::{EvictionPolicy, Sieve};
+use crate::sieve::{DefaultHashBuilder, SieveCache};
+use crate::types::{CacheError, CacheStats};
+
+type Reply<T> = mpsc::Sender<Result<T, CacheError>>;
+type Job<K, V, P, S> = Box<dyn FnOnce(&mut SieveCache<K, V, P, S>) + Send>;
+
+enum Command<K, V, P, S> {
+    Get(K, Reply<Option<V>>),
+    Peek(K, Reply<Option<V>>),
+    Add(K, V, Option<Duration>, Reply<bool>),
+    Remove(K, Reply<Option<V>>),
+    Run(Job<K, V, P, S>),
+}
+
+/// A cheap, cloneable handle to a cache running on its own thread, created
+/// with [`SieveCache::spawn_actor`].
+///
+/// The thread exits once every handle has been dropped. Keys are passed by
+/// value since they have to cross the channel.
+///
+/// ```
+/// let cache = nitro::SieveCache::new(100)?.spawn_actor(64);
+/// cache.add("answer", 42)?;
+///
+/// let client = cache.clone();
+/// let found = std::thread::spawn(move || client.get("answer")).join().unwrap()?;
+/// assert_eq!(found, Some(42));
+/// # Ok::<(), nitro::CacheError>(())
+/// ```
+pub struct CacheHandle<K, V, P = Sieve<K>, S = DefaultHashBuilder> {
+    commands: SyncSender<Command<K, V, P, S>>,
+}
+
+impl<K, V, P, S> Clone for CacheHandle<K, V, P, S> {
+    fn clone(&self) -> Self {
+        CacheHandle {
+            commands: self.commands.clone(),
+        }
+    }
+}
+
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+    P: EvictionPolicy<K> + Send + 'static,
+    S: BuildHasher + Send + 'static,
+{
+    /// Moves the cache onto a new thread and returns a handle to it.
+    ///
+    /// Up to `queue` commands can wait for the actor before senders block; a
+    /// queue of zero makes every call wait until the actor picks it up.
+    pub fn spawn_actor(self, queue: usize) -> CacheHandle<K, V, P, S> {
+        let (commands, received) = mpsc::sync_channel(queue);
+        thread::Builder::new()
+            .name("nitro-cache".to_string())
+            .spawn(move || run(self, received))
+            .expect("failed to spawn cache thread");
+        CacheHandle { commands }
+    }
+}
+
+fn run<K, V, P, S>(mut cache: SieveCache<K, V, P, S>, commands: Receiver<Command<K, V, P, S>>)
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    // A client that gave up waiting has dropped its reply channel, so send
+    // errors are ignored
+    for command in commands {
+        match command {
+            Command::Get(key, reply) => {
+                let _ = reply.send(cache.get(&key));
+            }
+            Command::Peek(key, reply) => {
+                let _ = reply.send(cache.peek(&key));
+            }
+            Command::Add(key, value, ttl, reply) => {
+                let added = match ttl {
+                    Some(ttl) => cache.add_with_ttl(key, value, ttl),
+                    None => cache.add(key, value),
+                };
+                let _ = reply.send(added);
+            }
+            Command::Remove(key, reply) => {
+                let _ = reply.send(cache.remove(&key));
+            }
+            Command::Run(job) => job(&mut cache),
+        }
+    }
+}
+
+impl<K, V, P, S> CacheHandle<K, V, P, S>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+    P: EvictionPolicy<K> + Send + 'static,
+    S: BuildHasher + Send + 'static,
+{
+    /// See [`SieveCache::get`].
+    pub fn get(&self, key: K) -> Result<Option<V>, CacheError> {
+        self.call(|reply| Command::Get(key, reply))
+    }
+
+    /// See [`SieveCache::peek`].
+    pub fn peek(&self, key: K) -> Result<Option<V>, CacheError> {
+        self.call(|reply| Command::Peek(key, reply))
+    }
+
+    /// See [`SieveCache::add`].
+    pub fn add(&self, key: K, value: V) -> Result<bool, CacheError> {
+        self.call(|reply| Command::Add(key, value, None, reply))
+    }
+
+    /// See [`SieveCache::add_with_ttl`].
+    pub fn add_with_ttl(&self, key: K, value: V, ttl: Duration) -> Result<bool, CacheError> {
+        self.call(|reply| Command::Add(key, value, Some(ttl), reply))
+    }
+
+    /// See [`SieveCache::remove`].
+    pub fn remove(&self, key: K) -> Result<Option<V>, CacheError> {
+        self.call(|reply| Command::Remove(key, reply))
+    }
+
+    pub fn len(&self) -> Result<usize, CacheError> {
+        self.with(|cache| cache.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool, CacheError> {
+        self.with(|cache| cache.is_empty())
+    }
+
+    pub fn purge(&self) -> Result<(), CacheError> {
+        self.with(|cache| cache.purge())
+    }
+
+    /// See [`SieveCache::stats`].
+    pub fn get_stats(&self) -> Result<CacheStats, CacheError> {
+        self.with(|cache| cache.stats())
+    }
+
+    /// Runs `f` on the actor thread and waits for its result, for anything
+    /// the methods above do not cover.
+    pub fn with<R, F>(&self, f: F) -> Result<R, CacheError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut SieveCache<K, V, P, S>) -> R + Send + 'static,
+    {
+        self.call(|reply| {
+            Command::Run(Box::new(move |cache| {
+                let _ = reply.send(Ok(f(cache)));
+            }))
+        })
+    }
+
+    fn call<T>(
+        &self,
+        command: impl FnOnce(Reply<T>) -> Command<K, V, P, S>,
+    ) -> Result<T, CacheError> {
+        let (reply, response) = mpsc::channel();
+        self.commands.send(command(reply)).map_err(|_| stopped())?;
+        response.recv().map_err(|_| stopped())?
+    }
+}
+
+// The actor only stops early if it panicked, which is this mode's
+// equivalent of a poisoned lock
+fn stopped() -> CacheError {
+    CacheError::LockError("cache thread has stopped".to_string())
+}
@@ -0,0 +1,101 @@
+//! TinyLFU admission.
+//!
+//! When the cache is full, a new key only gets in if it has been requested
+//! more often than the entry the eviction policy picked to make room. Request
+//! counts are estimated with a count-min sketch that is periodically halved,
+//! so the filter tracks recent popularity in a few bits per cached entry.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::sieve::DefaultHashBuilder;
+
+const DEPTH: usize = 4;
+// Odd multipliers that spread one hash into independent row indexes
+const SEEDS: [u64; DEPTH] = [
+    0x9e37_79b9_7f4a_7c15,
+    0xc2b2_ae3d_27d4_eb4f,
+    0x1656_67b1_9e37_79f9,
+    0xd6e8_feb8_6659_fd93,
+];
+// Counters saturate here, which is plenty to tell popular keys from rare ones
+const MAX_COUNT: u8 = 15;
+// Counts are halved after this many increments per unit of capacity
+const SAMPLE_FACTOR: usize = 10;
+
+/// A frequency sketch that decides whether new keys are admitted into a full
+/// cache, to keep one-hit wonders from displacing valuable entries.
+///
+/// Works with any [`EvictionPolicy`](crate::EvictionPolicy): the policy still
+/// picks the victim, the filter only vetoes replacing it. Install it with
+/// [`SieveCacheBuilder::admission_filter`](crate::SieveCacheBuilder::admission_filter).
+#[derive(Debug, Clone)]
+pub struct TinyLfu {
+    counters: Vec<u8>,
+    mask: usize,
+    additions: usize,
+    sample_size: usize,
+    hasher: DefaultHashBuilder,
+}
+
+impl TinyLfu {
+    /// Creates a sketch sized for a cache of `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        let width = capacity.max(16).next_power_of_two();
+        TinyLfu {
+            counters: vec![0; width * DEPTH],
+            mask: width - 1,
+            additions: 0,
+            sample_size: width.saturating_mul(SAMPLE_FACTOR),
+            hasher: DefaultHashBuilder::default(),
+        }
+    }
+
+    /// Estimated number of recent requests for `key`.
+    pub fn frequency<K: Hash + ?Sized>(&self, key: &K) -> u8 {
+        self.slots(key)
+            .map(|slot| self.counters[slot])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Records a request for `key`.
+    pub fn increment<K: Hash + ?Sized>(&mut self, key: &K) {
+        let slots: Vec<usize> = self.slots(key).collect();
+        // Conservative update: only raise the counters holding the minimum
+        let min = slots.iter().map(|&slot| self.counters[slot]).min();
+        if min == Some(MAX_COUNT) {
+            return;
+        }
+        for slot in slots {
+            if Some(self.counters[slot]) == min {
+                self.counters[slot] += 1;
+            }
+        }
+        self.additions += 1;
+        if self.additions >= self.sample_size {
+            self.age();
+        }
+    }
+
+    /// Whether `candidate` should replace `victim`.
+    pub fn admit<K: Hash + ?Sized>(&self, candidate: &K, victim: &K) -> bool {
+        self.frequency(candidate) > self.frequency(victim)
+    }
+
+    // Halves every counter so old popularity fades
+    fn age(&mut self) {
+        for counter in &mut self.counters {
+            *counter /= 2;
+        }
+        self.additions /= 2;
+    }
+
+    // One counter per row, each indexed by different bits of a single hash
+    fn slots<K: Hash + ?Sized>(&self, key: &K) -> impl Iterator<Item = usize> + '_ {
+        let hash = BuildHasher::hash_one(&self.hasher, key);
+        SEEDS.iter().enumerate().map(move |(row, seed)| {
+            let index = (hash.wrapping_mul(*seed) >> 32) as usize & self.mask;
+            row * (self.mask + 1) + index
+        })
+    }
+}
@@ -0,0 +1,108 @@
+//! Working-set tracking for capacity advice.
+//!
+//! For a sample of keys the tracker measures the reuse distance of every
+//! lookup: how many distinct keys were looked up since the previous lookup of
+//! the same key. A cache of capacity `c` that keeps recently used entries
+//! serves roughly every lookup with a reuse distance below `c`, so the
+//! distance histogram translates directly into a hit-rate curve.
+//!
+//! Sampling by key hash (as in SHARDS) keeps the cost low: only sampled keys
+//! are tracked, and their distances are scaled up by the sampling rate.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+
+use crate::sieve::DefaultHashBuilder;
+
+// Sampling decisions are made on this many hash buckets
+const SAMPLE_SPACE: u64 = 1 << 24;
+// Upper bound on tracked keys, so memory stays flat on huge key spaces
+const MAX_TRACKED: usize = 1 << 16;
+
+pub(crate) struct ReuseTracker {
+    hasher: DefaultHashBuilder,
+    threshold: u64,
+    rate: f64,
+    // Sampled key hash -> logical time of its last lookup
+    last_seen: HashMap<u64, u64>,
+    // The same, ordered by time
+    by_time: BTreeMap<u64, u64>,
+    now: u64,
+    // Scaled reuse distance -> number of lookups
+    distances: BTreeMap<usize, u64>,
+    // Sampled lookups of keys not seen before
+    cold: u64,
+}
+
+impl ReuseTracker {
+    /// Tracks roughly `rate` (between 0 and 1) of all keys.
+    pub(crate) fn new(rate: f64) -> Self {
+        let rate = rate.clamp(1.0 / SAMPLE_SPACE as f64, 1.0);
+        ReuseTracker {
+            hasher: DefaultHashBuilder::default(),
+            threshold: (rate * SAMPLE_SPACE as f64).ceil() as u64,
+            rate,
+            last_seen: HashMap::new(),
+            by_time: BTreeMap::new(),
+            now: 0,
+            distances: BTreeMap::new(),
+            cold: 0,
+        }
+    }
+
+    pub(crate) fn record<K: Hash + ?Sized>(&mut self, key: &K) {
+        let hash = BuildHasher::hash_one(&self.hasher, key);
+        if hash % SAMPLE_SPACE >= self.threshold {
+            return;
+        }
+        self.now += 1;
+        match self.last_seen.insert(hash, self.now) {
+            Some(previous) => {
+                // Every key looked up since `previous` has a later timestamp
+                let between = self.by_time.range(previous + 1..).count();
+                let distance = (between as f64 / self.rate) as usize;
+                *self.distances.entry(distance).or_default() += 1;
+                self.by_time.remove(&previous);
+            }
+            None => {
+                self.cold += 1;
+                if self.last_seen.len() > MAX_TRACKED {
+                    if let Some((_, oldest)) = self.by_time.pop_first() {
+                        self.last_seen.remove(&oldest);
+                    }
+                }
+            }
+        }
+        self.by_time.insert(self.now, hash);
+    }
+
+    fn lookups(&self) -> u64 {
+        self.cold + self.distances.values().sum::<u64>()
+    }
+
+    /// Fraction of lookups a cache of `capacity` entries would have served.
+    pub(crate) fn hit_rate(&self, capacity: usize) -> f64 {
+        let lookups = self.lookups();
+        if lookups == 0 {
+            return 0.0;
+        }
+        let hits: u64 = self.distances.range(..capacity).map(|(_, n)| n).sum();
+        hits as f64 / lookups as f64
+    }
+
+    /// Smallest capacity whose hit rate reaches `target`, if any does.
+    pub(crate) fn capacity_for(&self, target: f64) -> Option<usize> {
+        let lookups = self.lookups();
+        if lookups == 0 {
+            return None;
+        }
+        let mut hits = 0;
+        for (&distance, &count) in &self.distances {
+            hits += count;
+            if hits as f64 / lookups as f64 >= target {
+                return Some(distance + 1);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,112 @@
+//! Async read-through for callers on a tokio runtime.
+//!
+//! Loaders that are network calls are naturally async; awaiting them inside a
+//! sync loader would need a nested runtime. Here the caller hands over the
+//! future instead, and concurrent callers missing on the same key await one
+//! shared load, as with [`ConcurrentSieveCache::get_or_load`].
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+use tokio::sync::OnceCell;
+
+use crate::concurrent::ConcurrentSieveCache;
+use crate::eviction::EvictionPolicy;
+use crate::sync::Mutex;
+use crate::types::{CacheError, Cached};
+
+type Flight<V> = Arc<OnceCell<Result<V, CacheError>>>;
+
+pub(crate) struct AsyncInFlight<K, V> {
+    flights: Mutex<HashMap<K, Flight<V>>>,
+}
+
+impl<K, V> Default for AsyncInFlight<K, V> {
+    fn default() -> Self {
+        AsyncInFlight {
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K, V, P, S> ConcurrentSieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Returns the cached value for `key`, awaiting `init` and caching its
+    /// value on a miss.
+    ///
+    /// Callers that miss on the same key while a load is in flight await
+    /// that load instead of starting their own, and share its outcome. If
+    /// the caller driving the load is cancelled, one of the waiters takes
+    /// over with its own `init`. Errors are handed back as
+    /// [`CacheError::Load`] and counted and cached like those of a
+    /// [`TryCacheLoader`](crate::TryCacheLoader).
+    ///
+    /// ```
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let cache = nitro::ConcurrentSieveCache::new(100)?;
+    /// let fetch = || async { Ok::<_, std::io::Error>(42) };
+    /// assert_eq!(cache.get_or_try_insert_with_async("answer", fetch).await?, 42);
+    /// # Ok::<(), nitro::CacheError>(())
+    /// # }).unwrap();
+    /// ```
+    pub async fn get_or_try_insert_with_async<F, Fut, E>(
+        &self,
+        key: K,
+        init: F,
+    ) -> Result<V, CacheError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+        E: Error + Send + Sync + 'static,
+    {
+        {
+            let mut cache = self.inner.write()?;
+            if let Cached::Present(value) = cache.get_cached(&key)? {
+                return Ok(value);
+            }
+            cache.check_load_error(&key)?;
+        }
+        let flight = {
+            let mut flights = self.async_loading.flights.lock()?;
+            flights.entry(key.clone()).or_default().clone()
+        };
+        let outcome = flight
+            .get_or_init(|| async {
+                // A load that just finished may have filled the key already
+                if let Some(value) = self.inner.read()?.peek(&key)? {
+                    return Ok(value);
+                }
+                match init().await {
+                    Ok(value) => {
+                        let loaded = Ok(Some(value.clone()));
+                        let current = self.inner.write()?.store_loaded(&key, loaded)?;
+                        Ok(current.unwrap_or(value))
+                    }
+                    Err(error) => {
+                        let error = CacheError::Load(Arc::new(error));
+                        self.inner.write()?.store_loaded(&key, Err(error.clone()))?;
+                        Err(error)
+                    }
+                }
+            })
+            .await
+            .clone();
+
+        let mut flights = self.async_loading.flights.lock_unpoisoned();
+        if flights
+            .get(&key)
+            .is_some_and(|current| Arc::ptr_eq(current, &flight))
+        {
+            flights.remove(&key);
+        }
+        outcome
+    }
+}
@@ -0,0 +1,387 @@
+//! Write-through and write-behind to a backing store.
+//!
+//! With a [`WriteBackend`] configured, the cache becomes a coherent front for
+//! a database or key-value store: writes and deletes reach the backend before
+//! the cache changes, and one the backend rejects leaves the cache as it was.
+//!
+//! In [write-behind](WriteBehind) mode, writes and deletes are queued instead
+//! and stored in batches, trading how soon they are durable for throughput.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::eviction::EvictionPolicy;
+#[cfg(feature = "snapshot")]
+use crate::oplog::RecordRef;
+use crate::sieve::SieveCache;
+use crate::types::{CacheError, RemovalCause};
+
+/// The store that writes to the cache are propagated to.
+pub trait WriteBackend<K, V> {
+    type Error: Error + Send + Sync + 'static;
+
+    /// Stores `value` for `key`.
+    fn write(&self, key: &K, value: &V) -> Result<(), Self::Error>;
+
+    /// Removes `key` from the store. Removing a key the store does not hold
+    /// should succeed.
+    fn delete(&self, key: &K) -> Result<(), Self::Error>;
+
+    /// Stores a batch of queued writes, where `None` deletes the key. Each
+    /// key appears at most once.
+    ///
+    /// Calls [`write`](Self::write) and [`delete`](Self::delete) for each
+    /// key by default; override it to store the batch in one round trip. A
+    /// failed batch is retried whole, so applying part of it before failing
+    /// is fine.
+    fn write_batch(&self, batch: &[(K, Option<V>)]) -> Result<(), Self::Error> {
+        for (key, value) in batch {
+            match value {
+                Some(value) => self.write(key, value)?,
+                None => self.delete(key)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Configures write-behind, see [`SieveCache::set_write_behind`].
+#[derive(Debug, Clone)]
+pub struct WriteBehind {
+    batch_size: usize,
+    interval: Option<Duration>,
+}
+
+impl WriteBehind {
+    /// Flushes once `batch_size` keys are waiting.
+    pub fn new(batch_size: usize) -> Self {
+        WriteBehind {
+            batch_size: batch_size.max(1),
+            interval: None,
+        }
+    }
+
+    /// Also flushes once the oldest waiting write is `interval` old.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+}
+
+// A backend with its errors turned into `CacheError::Backend`
+pub(crate) trait Store<K, V> {
+    fn write(&self, key: &K, value: &V) -> Result<(), CacheError>;
+    fn delete(&self, key: &K) -> Result<(), CacheError>;
+    fn write_batch(&self, batch: &[(K, Option<V>)]) -> Result<(), CacheError>;
+}
+
+impl<K, V, B: WriteBackend<K, V>> Store<K, V> for B {
+    fn write(&self, key: &K, value: &V) -> Result<(), CacheError> {
+        WriteBackend::write(self, key, value).map_err(backend_error)
+    }
+
+    fn delete(&self, key: &K) -> Result<(), CacheError> {
+        WriteBackend::delete(self, key).map_err(backend_error)
+    }
+
+    fn write_batch(&self, batch: &[(K, Option<V>)]) -> Result<(), CacheError> {
+        WriteBackend::write_batch(self, batch).map_err(backend_error)
+    }
+}
+
+fn backend_error<E: Error + Send + Sync + 'static>(error: E) -> CacheError {
+    CacheError::Backend(Arc::new(error))
+}
+
+pub(crate) type SharedBackend<K, V> = Arc<dyn Store<K, V> + Send + Sync>;
+
+// Writes waiting to be stored in write-behind mode
+struct WriteQueue<K, V> {
+    config: WriteBehind,
+    // Latest write per key, in the order the keys were first queued
+    pending: Vec<(K, Option<V>)>,
+    positions: HashMap<K, usize>,
+    oldest: Option<Instant>,
+}
+
+pub(crate) struct Backend<K, V> {
+    store: SharedBackend<K, V>,
+    queue: Option<WriteQueue<K, V>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Backend<K, V> {
+    pub(crate) fn new(store: SharedBackend<K, V>) -> Self {
+        Backend { store, queue: None }
+    }
+
+    pub(crate) fn put(
+        &mut self,
+        key: &K,
+        value: Option<&V>,
+        now: Instant,
+    ) -> Result<(), CacheError> {
+        if self.queue.is_none() {
+            return match value {
+                Some(value) => self.store.write(key, value),
+                None => self.store.delete(key),
+            };
+        }
+        // Flushing first means a failed flush leaves this write out of the
+        // queue as well as out of the cache
+        if self.is_due(now) {
+            self.flush()?;
+        }
+        let Some(queue) = &mut self.queue else {
+            return Ok(());
+        };
+        let value = value.cloned();
+        match queue.positions.get(key) {
+            Some(&position) => queue.pending[position].1 = value,
+            None => {
+                queue.positions.insert(key.clone(), queue.pending.len());
+                queue.pending.push((key.clone(), value));
+                queue.oldest.get_or_insert(now);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        let Some(queue) = &self.queue else {
+            return false;
+        };
+        let aged = match (queue.config.interval, queue.oldest) {
+            (Some(interval), Some(oldest)) => now.saturating_duration_since(oldest) >= interval,
+            _ => false,
+        };
+        aged || queue.pending.len() >= queue.config.batch_size
+    }
+
+    fn flush(&mut self) -> Result<(), CacheError> {
+        let Some(queue) = &mut self.queue else {
+            return Ok(());
+        };
+        if queue.pending.is_empty() {
+            return Ok(());
+        }
+        self.store.write_batch(&queue.pending)?;
+        queue.pending.clear();
+        queue.positions.clear();
+        queue.oldest = None;
+        Ok(())
+    }
+}
+
+impl<K, V> Drop for Backend<K, V> {
+    // Last chance for queued writes; there is no one left to report a
+    // failure to
+    fn drop(&mut self) {
+        if let Some(queue) = &self.queue {
+            if !queue.pending.is_empty() {
+                let _ = self.store.write_batch(&queue.pending);
+            }
+        }
+    }
+}
+
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Propagates writes to `backend`, replacing any previous one.
+    ///
+    /// Every value written to the cache, through [`add`](Self::add),
+    /// [`compare_and_swap`](Self::compare_and_swap), the
+    /// [entry API](Self::entry) or any other write, is stored in the backend,
+    /// and every key removed with [`delete`](Self::delete),
+    /// [`remove`](Self::remove), [`retain`](Self::retain) or
+    /// [`invalidate_prefix`](Self::invalidate_prefix) is deleted from it,
+    /// before the call returns. If the backend fails, the error is returned
+    /// as [`CacheError::Backend`] and the cache is left unchanged, except
+    /// that `add_many` drops the keys the backend stored before it failed,
+    /// and bulk removals keep what they removed before it failed.
+    ///
+    /// A value changed through [`get_mut`](Self::get_mut) is stored when the
+    /// guard is dropped, after the cache changed; if the backend fails then,
+    /// the entry expires at once rather than serve a value the backend does
+    /// not have.
+    ///
+    /// Evictions and expirations do not go to the backend, and neither do
+    /// values fetched by a [loader](Self::set_loader),
+    /// [primed](Self::prime) or [merged](Self::merge), which came from the
+    /// store to begin with. Removals only reach the backend for keys the
+    /// cache holds, as `delete` takes a borrowed form of the key.
+    ///
+    /// Writes queued for the previous backend in
+    /// [write-behind](Self::set_write_behind) mode are stored in it first,
+    /// ignoring failures.
+    pub fn set_write_backend<B>(&mut self, backend: B)
+    where
+        B: WriteBackend<K, V> + Send + Sync + 'static,
+    {
+        let config = self.write_behind_config();
+        self.backend = Some(Backend::new(Arc::new(backend)));
+        self.enable_write_behind(config);
+    }
+
+    /// Queues writes for the [backend](Self::set_write_backend) and stores
+    /// them in batches instead of one by one, or goes back to writing
+    /// through with `None`.
+    ///
+    /// The calls that would write through return once the write is queued,
+    /// and repeated writes to a key are coalesced into its latest one. A
+    /// write that finds the queue due, holding the configured number of keys
+    /// or an entry older than its interval, flushes it first.
+    /// [`run_maintenance`](Self::run_maintenance) flushes a due queue as
+    /// well, and [`flush`](Self::flush) flushes it right away. A failed flush
+    /// keeps the writes queued.
+    ///
+    /// Until a write is flushed the backend lags behind the cache, and a
+    /// [loader](Self::set_loader) reading from the backend can see an older
+    /// value. Writes still queued when the cache is dropped are stored on a
+    /// best-effort basis; call `flush` first to see failures.
+    ///
+    /// Has no effect without a backend. Turning write-behind off flushes the
+    /// queue, failing without turning it off if the flush fails.
+    pub fn set_write_behind(
+        &mut self,
+        write_behind: Option<WriteBehind>,
+    ) -> Result<(), CacheError> {
+        if write_behind.is_none() {
+            self.flush()?;
+        }
+        self.enable_write_behind(write_behind);
+        Ok(())
+    }
+
+    /// Stores every queued write in the backend, see
+    /// [`set_write_behind`](Self::set_write_behind).
+    ///
+    /// # Returns
+    /// - `Ok(())` if the writes were stored, or none were queued
+    /// - `Err(CacheError::Backend)` if the backend failed, in which case the
+    ///   writes stay queued
+    pub fn flush(&mut self) -> Result<(), CacheError> {
+        match &mut self.backend {
+            Some(backend) => backend.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// The number of keys with a write queued for the backend.
+    pub fn pending_writes(&self) -> usize {
+        self.backend
+            .as_ref()
+            .and_then(|backend| backend.queue.as_ref())
+            .map_or(0, |queue| queue.pending.len())
+    }
+
+    fn write_behind_config(&self) -> Option<WriteBehind> {
+        let queue = self.backend.as_ref()?.queue.as_ref()?;
+        Some(queue.config.clone())
+    }
+
+    fn enable_write_behind(&mut self, write_behind: Option<WriteBehind>) {
+        let Some(backend) = &mut self.backend else {
+            return;
+        };
+        match (&mut backend.queue, write_behind) {
+            (Some(queue), Some(config)) => queue.config = config,
+            (queue, config) => {
+                *queue = config.map(|config| WriteQueue {
+                    config,
+                    pending: Vec::new(),
+                    positions: HashMap::new(),
+                    oldest: None,
+                })
+            }
+        }
+    }
+
+    // Flushes queued writes that are due, see `run_maintenance`
+    pub(crate) fn flush_due(&mut self) -> Result<(), CacheError> {
+        let now = self.clock.peek();
+        match &mut self.backend {
+            Some(backend) if backend.is_due(now) => backend.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    // Whether writes have to be passed on to a backend or operation log
+    pub(crate) fn writes_through(&self) -> bool {
+        self.backend.is_some() || self.logs_operations()
+    }
+
+    // Whether writes go to an operation log, see `set_operation_log`
+    pub(crate) fn logs_operations(&self) -> bool {
+        #[cfg(feature = "snapshot")]
+        return self.oplog.is_some();
+        #[cfg(not(feature = "snapshot"))]
+        false
+    }
+
+    // Logs and stores or queues `value` for the backend, if either is set. A
+    // value too large to cache is rejected first, so neither gets ahead of
+    // the cache
+    pub(crate) fn write_through(&mut self, key: &K, value: &V) -> Result<(), CacheError> {
+        self.write_through_with_ttl(key, value, None)
+    }
+
+    // Like `write_through` for an entry with a deadline of its own `ttl`
+    // away, which only the operation log records
+    pub(crate) fn write_through_with_ttl(
+        &mut self,
+        key: &K,
+        value: &V,
+        ttl: Option<Duration>,
+    ) -> Result<(), CacheError> {
+        if !self.writes_through() {
+            return Ok(());
+        }
+        self.check_value_size(value)?;
+        #[cfg(feature = "snapshot")]
+        self.log_operation(RecordRef::Add { key, value, ttl })?;
+        #[cfg(not(feature = "snapshot"))]
+        let _ = ttl;
+        let now = self.clock.peek();
+        match &mut self.backend {
+            Some(backend) => backend.put(key, Some(value), now),
+            None => Ok(()),
+        }
+    }
+
+    // Logs and stores or queues every item, see `write_through`. If the log
+    // or backend fails partway, the keys it already took are dropped from
+    // the cache, which may hold older values for them
+    pub(crate) fn write_many_through(&mut self, items: &[(K, V)]) -> Result<(), CacheError> {
+        for (written, (key, value)) in items.iter().enumerate() {
+            if let Err(error) = self.write_through(key, value) {
+                for (key, _) in &items[..written] {
+                    #[cfg(feature = "snapshot")]
+                    self.log_unreported(RecordRef::Delete { key });
+                    self.remove_node(key, RemovalCause::Explicit)?;
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    // Logs and deletes or queues a delete of `key` for the backend, if
+    // either is set
+    pub(crate) fn delete_through(&mut self, key: &K) -> Result<(), CacheError> {
+        #[cfg(feature = "snapshot")]
+        self.log_operation(RecordRef::Delete { key })?;
+        let now = self.clock.peek();
+        match &mut self.backend {
+            Some(backend) => backend.put(key, None, now),
+            None => Ok(()),
+        }
+    }
+}
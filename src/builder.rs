@@ -0,0 +1,408 @@
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::admission::TinyLfu;
+use crate::backend::{Backend, SharedBackend, WriteBackend, WriteBehind};
+use crate::clock::{Clock, ClockJumpPolicy, SystemClock};
+use crate::eviction::{EvictionPolicy, Sieve};
+use crate::ghost::AdaptiveCapacity;
+use crate::loader::{self, CacheLoader, SharedLoader, TryCacheLoader};
+use crate::recorder::{RecorderBatch, StatsRecorder};
+use crate::sieve::{
+    DefaultHashBuilder, EvictionInterceptor, EvictionListener, KeyClassifier, SieveCache, SizeFn,
+    TtlFn,
+};
+use crate::types::{CacheError, RemovalCause};
+
+/// Configures a [`SieveCache`] before it is created.
+///
+/// ```
+/// use nitro::SieveCache;
+/// use std::time::Duration;
+///
+/// let cache: SieveCache<String, u32> = SieveCache::builder()
+///     .capacity(1000)
+///     .expire_after_write(Duration::from_secs(60))
+///     .initial_entries([(String::from("answer"), 42)])
+///     .build()?;
+/// assert_eq!(cache.len(), 1);
+/// # Ok::<(), nitro::CacheError>(())
+/// ```
+pub struct SieveCacheBuilder<K, V, P = Sieve<K>> {
+    capacity: usize,
+    clock: Arc<dyn Clock>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    eviction_interceptor: Option<(usize, EvictionInterceptor<K, V>)>,
+    recorder: Option<RecorderBatch>,
+    collect_stats: bool,
+    classifier: Option<KeyClassifier<K>>,
+    warming: bool,
+    clock_jump_policy: ClockJumpPolicy,
+    expire_after_write: Option<Duration>,
+    expire_after_access: Option<Duration>,
+    ttl_fn: Option<TtlFn<K, V>>,
+    loader: Option<SharedLoader<K, V>>,
+    load_error_ttl: Option<Duration>,
+    backend: Option<SharedBackend<K, V>>,
+    write_behind: Option<WriteBehind>,
+    refresh_ahead: Option<Duration>,
+    max_value_size: Option<(usize, SizeFn<V>)>,
+    max_idle: Option<Duration>,
+    initial_entries: Vec<(K, V)>,
+    policy: P,
+    admission: Option<TinyLfu>,
+    working_set_sampling: Option<f64>,
+    ghost_capacity: Option<usize>,
+    negative_ttl: Option<Duration>,
+    adaptive: Option<AdaptiveCapacity>,
+    eviction_batch: usize,
+}
+
+impl<K, V> SieveCacheBuilder<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    pub fn new() -> Self {
+        SieveCacheBuilder {
+            capacity: 0,
+            clock: Arc::new(SystemClock),
+            eviction_listener: None,
+            eviction_interceptor: None,
+            recorder: None,
+            collect_stats: true,
+            classifier: None,
+            warming: false,
+            clock_jump_policy: ClockJumpPolicy::default(),
+            expire_after_write: None,
+            expire_after_access: None,
+            ttl_fn: None,
+            loader: None,
+            load_error_ttl: None,
+            backend: None,
+            write_behind: None,
+            refresh_ahead: None,
+            max_value_size: None,
+            max_idle: None,
+            initial_entries: Vec::new(),
+            policy: Sieve::new(),
+            admission: None,
+            working_set_sampling: None,
+            ghost_capacity: None,
+            negative_ttl: None,
+            adaptive: None,
+            eviction_batch: 1,
+        }
+    }
+}
+
+impl<K, V, P> SieveCacheBuilder<K, V, P>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+{
+    /// Evicts according to `policy` instead of SIEVE.
+    pub fn policy<Q: EvictionPolicy<K>>(self, policy: Q) -> SieveCacheBuilder<K, V, Q> {
+        SieveCacheBuilder {
+            capacity: self.capacity,
+            clock: self.clock,
+            eviction_listener: self.eviction_listener,
+            eviction_interceptor: self.eviction_interceptor,
+            recorder: self.recorder,
+            collect_stats: self.collect_stats,
+            classifier: self.classifier,
+            warming: self.warming,
+            clock_jump_policy: self.clock_jump_policy,
+            expire_after_write: self.expire_after_write,
+            expire_after_access: self.expire_after_access,
+            ttl_fn: self.ttl_fn,
+            loader: self.loader,
+            load_error_ttl: self.load_error_ttl,
+            backend: self.backend,
+            write_behind: self.write_behind,
+            refresh_ahead: self.refresh_ahead,
+            max_value_size: self.max_value_size,
+            max_idle: self.max_idle,
+            initial_entries: self.initial_entries,
+            policy,
+            admission: self.admission,
+            working_set_sampling: self.working_set_sampling,
+            ghost_capacity: self.ghost_capacity,
+            negative_ttl: self.negative_ttl,
+            adaptive: self.adaptive,
+            eviction_batch: self.eviction_batch,
+        }
+    }
+
+    /// Maximum number of entries. Required; `build` fails if it is left at zero.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Time source for TTLs and timeouts. Defaults to [`SystemClock`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// How clock readings that jump backwards or far forward are handled.
+    pub fn clock_jump_policy(mut self, policy: ClockJumpPolicy) -> Self {
+        self.clock_jump_policy = policy;
+        self
+    }
+
+    /// Calls `listener` with every entry that leaves the cache and the reason
+    /// it was removed.
+    ///
+    /// The listener runs synchronously inside the cache operation that removed
+    /// the entry, so it should hand slow work off elsewhere.
+    pub fn eviction_listener<F>(mut self, listener: F) -> Self
+    where
+        F: Fn(K, V, RemovalCause) + Send + Sync + 'static,
+    {
+        self.eviction_listener = Some(Box::new(listener));
+        self
+    }
+
+    /// See [`SieveCache::set_eviction_interceptor`].
+    pub fn eviction_interceptor<F>(mut self, max_vetoes: usize, interceptor: F) -> Self
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        self.eviction_interceptor = Some((max_vetoes, Box::new(interceptor)));
+        self
+    }
+
+    /// See [`SieveCache::set_eviction_batch`].
+    pub fn eviction_batch(mut self, batch: usize) -> Self {
+        self.eviction_batch = batch;
+        self
+    }
+
+    /// Forwards hits, misses, evictions and expirations to `recorder`, in
+    /// batches of `batch_size` events.
+    pub fn stats_recorder(
+        mut self,
+        recorder: impl StatsRecorder + 'static,
+        batch_size: usize,
+    ) -> Self {
+        self.recorder = Some(RecorderBatch::new(Box::new(recorder), batch_size));
+        self
+    }
+
+    /// Whether hits, misses, evictions and expirations are counted. Enabled by
+    /// default; when disabled, [`CacheStats`](crate::CacheStats) stays at zero
+    /// and the stats recorder is never called.
+    pub fn collect_stats(mut self, enabled: bool) -> Self {
+        self.collect_stats = enabled;
+        self
+    }
+
+    /// Additionally counts hits and misses per class of key, as named by
+    /// `classifier`, so one shared cache can report separate hit rates for,
+    /// say, user profiles and feed items. Read them through
+    /// [`SieveCache::stats_snapshot`].
+    ///
+    /// Classes are kept for the lifetime of the cache, so `classifier` should
+    /// return one of a handful of fixed names.
+    pub fn key_classifier<F>(mut self, classifier: F) -> Self
+    where
+        F: Fn(&K) -> &'static str + Send + Sync + 'static,
+    {
+        self.classifier = Some(Box::new(classifier));
+        self
+    }
+
+    /// Starts the cache in shadow-warming mode.
+    ///
+    /// Until [`SieveCache::activate`] is called, inserts fill the cache as
+    /// usual and reads still mark entries as visited, but
+    /// [`SieveCache::get`] reports every lookup as a miss and leaves it out of
+    /// the statistics. Callers then keep fetching from the source of truth
+    /// after a deploy while the cache warms up behind them. The entry API and
+    /// `probe` are not affected, as they are what fills the cache.
+    pub fn warming(mut self) -> Self {
+        self.warming = true;
+        self
+    }
+
+    /// Only admits a new entry into a full cache if it has been requested
+    /// more often recently than the victim the policy chose for it.
+    ///
+    /// Keys requested once and never again then stop pushing out entries
+    /// that keep being used. A turned-away insert is not an error; it is
+    /// counted in [`CacheStats::admission_rejections`](crate::CacheStats::admission_rejections)
+    /// and the key simply stays uncached.
+    pub fn admission_filter(mut self, filter: TinyLfu) -> Self {
+        self.admission = Some(filter);
+        self
+    }
+
+    /// See [`SieveCache::set_ghost_capacity`].
+    pub fn ghost_capacity(mut self, capacity: usize) -> Self {
+        self.ghost_capacity = Some(capacity);
+        self
+    }
+
+    /// See [`SieveCache::set_negative_ttl`].
+    pub fn negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = Some(ttl);
+        self
+    }
+
+    /// See [`SieveCache::set_adaptive_capacity`].
+    pub fn adaptive_capacity(mut self, adaptive: AdaptiveCapacity) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    /// See [`SieveCache::set_working_set_sampling`].
+    pub fn working_set_sampling(mut self, sample_rate: f64) -> Self {
+        self.working_set_sampling = Some(sample_rate);
+        self
+    }
+
+    /// See [`SieveCache::set_expire_after_write`].
+    pub fn expire_after_write(mut self, ttl: Duration) -> Self {
+        self.expire_after_write = Some(ttl);
+        self
+    }
+
+    /// See [`SieveCache::set_expire_after_access`].
+    pub fn expire_after_access(mut self, ttl: Duration) -> Self {
+        self.expire_after_access = Some(ttl);
+        self
+    }
+
+    /// See [`SieveCache::set_ttl_fn`].
+    pub fn ttl_fn<F>(mut self, ttl_fn: F) -> Self
+    where
+        F: Fn(&K, &V) -> Option<Duration> + Send + Sync + 'static,
+    {
+        self.ttl_fn = Some(Box::new(ttl_fn));
+        self
+    }
+
+    /// See [`SieveCache::set_loader`].
+    pub fn loader<L>(mut self, loader: L) -> Self
+    where
+        L: CacheLoader<K, V> + Send + Sync + 'static,
+    {
+        self.loader = Some(loader::infallible(loader));
+        self
+    }
+
+    /// See [`SieveCache::set_try_loader`].
+    pub fn try_loader<L>(mut self, loader: L) -> Self
+    where
+        L: TryCacheLoader<K, V> + Send + Sync + 'static,
+    {
+        self.loader = Some(loader::fallible(loader));
+        self
+    }
+
+    /// See [`SieveCache::set_refresh_ahead`].
+    pub fn refresh_ahead(mut self, ahead: Duration) -> Self {
+        self.refresh_ahead = Some(ahead);
+        self
+    }
+
+    /// See [`SieveCache::set_load_error_ttl`].
+    pub fn load_error_ttl(mut self, ttl: Duration) -> Self {
+        self.load_error_ttl = Some(ttl);
+        self
+    }
+
+    /// See [`SieveCache::set_write_backend`]. The
+    /// [initial entries](Self::initial_entries) are not written to it.
+    pub fn write_backend<B>(mut self, backend: B) -> Self
+    where
+        B: WriteBackend<K, V> + Send + Sync + 'static,
+    {
+        self.backend = Some(Arc::new(backend));
+        self
+    }
+
+    /// See [`SieveCache::set_write_behind`].
+    pub fn write_behind(mut self, write_behind: WriteBehind) -> Self {
+        self.write_behind = Some(write_behind);
+        self
+    }
+
+    /// See [`SieveCache::set_max_value_size`].
+    pub fn max_value_size<F>(mut self, max: usize, size_fn: F) -> Self
+    where
+        F: Fn(&V) -> usize + Send + Sync + 'static,
+    {
+        self.max_value_size = Some((max, Box::new(size_fn)));
+        self
+    }
+
+    /// See [`SieveCache::set_max_idle`].
+    pub fn max_idle(mut self, max_idle: Duration) -> Self {
+        self.max_idle = Some(max_idle);
+        self
+    }
+
+    /// Entries added in order once the cache is built. Entries beyond
+    /// `capacity` evict earlier ones as usual.
+    pub fn initial_entries<I>(mut self, entries: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.initial_entries.extend(entries);
+        self
+    }
+
+    pub fn build(self) -> Result<SieveCache<K, V, P>, CacheError> {
+        let mut cache = SieveCache::with_shared_clock(
+            self.capacity,
+            self.clock,
+            self.policy,
+            DefaultHashBuilder::default(),
+        )?;
+        cache.eviction_listener = self.eviction_listener;
+        cache.recorder = self.recorder;
+        cache.collect_stats = self.collect_stats;
+        cache.classifier = self.classifier;
+        cache.admission = self.admission;
+        cache.set_working_set_sampling(self.working_set_sampling);
+        cache.set_ghost_capacity(self.ghost_capacity);
+        cache.set_negative_ttl(self.negative_ttl);
+        cache.set_adaptive_capacity(self.adaptive)?;
+        cache.warming = self.warming;
+        cache.set_clock_jump_policy(self.clock_jump_policy);
+        cache.set_expire_after_write(self.expire_after_write);
+        cache.set_expire_after_access(self.expire_after_access);
+        cache.set_max_idle(self.max_idle);
+        cache.ttl_fn = self.ttl_fn;
+        cache.loader = self.loader;
+        cache.set_load_error_ttl(self.load_error_ttl);
+        cache.set_refresh_ahead(self.refresh_ahead);
+        cache.max_value_size = self.max_value_size;
+        cache.set_eviction_batch(self.eviction_batch);
+        if let Some((max_vetoes, interceptor)) = self.eviction_interceptor {
+            cache.eviction_interceptor = Some(interceptor);
+            cache.max_eviction_vetoes = max_vetoes;
+        }
+        for (key, value) in self.initial_entries {
+            cache.add(key, value)?;
+        }
+        cache.backend = self.backend.map(Backend::new);
+        cache.set_write_behind(self.write_behind)?;
+        Ok(cache)
+    }
+}
+
+impl<K, V> Default for SieveCacheBuilder<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,189 @@
+//! Time sources for expiration.
+//!
+//! Every TTL and timeout in the cache is measured against a [`Clock`]. The
+//! default [`SystemClock`] reads the monotonic system clock, while
+//! [`MockClock`] only moves when told to, which makes expiry testable without
+//! sleeping. [`WallClock`] follows the system wall clock and is therefore
+//! exposed to NTP steps and manual adjustments.
+//!
+//! The cache never uses clock readings directly. It keeps its own monotonic
+//! basis that advances by the difference between readings, and a
+//! [`ClockJumpPolicy`] decides what happens when a reading goes backwards or
+//! leaps forward.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::sync::Mutex;
+
+/// A source of the current time.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// Reads the current time from [`Instant::now`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A manually advanced clock.
+///
+/// Clones share the same time, so a test can keep one handle while the cache
+/// owns another.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl MockClock {
+    /// Creates a clock frozen at the current instant.
+    pub fn new() -> Self {
+        MockClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock_unpoisoned();
+        *now += duration;
+    }
+
+    /// Moves the clock backwards by `duration`, simulating a clock step.
+    pub fn rewind(&self, duration: Duration) {
+        let mut now = self.now.lock_unpoisoned();
+        *now = now.checked_sub(duration).unwrap_or(*now);
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock_unpoisoned()
+    }
+}
+
+/// Derives instants from [`SystemTime`], so it follows wall-clock adjustments.
+///
+/// Only useful when expiry has to line up with wall-clock time, e.g. for
+/// deadlines that come from other machines. Pair it with a
+/// [`ClockJumpPolicy`] that suits how the host clock is managed.
+#[derive(Debug, Clone, Copy)]
+pub struct WallClock {
+    base_instant: Instant,
+    base_time: SystemTime,
+}
+
+impl WallClock {
+    pub fn new() -> Self {
+        WallClock {
+            base_instant: Instant::now(),
+            base_time: SystemTime::now(),
+        }
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for WallClock {
+    fn now(&self) -> Instant {
+        match SystemTime::now().duration_since(self.base_time) {
+            Ok(elapsed) => self.base_instant + elapsed,
+            Err(e) => self
+                .base_instant
+                .checked_sub(e.duration())
+                .unwrap_or(self.base_instant),
+        }
+    }
+}
+
+/// How the cache reacts to clock readings that jump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClockJumpPolicy {
+    /// Use every reading as is. Time may run backwards, reviving entries
+    /// that were about to expire.
+    Trust,
+    /// Time never runs backwards: a backwards step is absorbed and the cache
+    /// keeps counting from where it was. Forward jumps are honored.
+    #[default]
+    Monotonic,
+    /// Like `Monotonic`, and forward jumps longer than the given duration are
+    /// absorbed as well, so a VM resume does not expire everything at once.
+    AbsorbForwardJumps(Duration),
+}
+
+// TimeBasis turns raw clock readings into the cache's notion of "now",
+// applying the jump policy and flagging readings that jumped
+pub(crate) struct TimeBasis {
+    clock: Arc<dyn Clock>,
+    pub(crate) policy: ClockJumpPolicy,
+    // Last raw reading and the time the cache derived from it, behind a lock
+    // so lookups through a shared reference remember their readings as well
+    last: Mutex<Option<(Instant, Instant)>>,
+}
+
+impl Clone for TimeBasis {
+    fn clone(&self) -> Self {
+        TimeBasis {
+            clock: Arc::clone(&self.clock),
+            policy: self.policy,
+            last: Mutex::new(*self.last.lock_unpoisoned()),
+        }
+    }
+}
+
+impl TimeBasis {
+    pub(crate) fn new(clock: Arc<dyn Clock>) -> Self {
+        TimeBasis {
+            clock,
+            policy: ClockJumpPolicy::default(),
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Returns the current time and whether the reading was anomalous.
+    pub(crate) fn now(&self) -> (Instant, bool) {
+        // A pair of instants cannot be left half-written by a panic
+        let mut last = self.last.lock_unpoisoned();
+        let raw = self.clock.now();
+        let (now, anomaly) = self.derive(*last, raw);
+        *last = Some((raw, now));
+        (now, anomaly)
+    }
+
+    /// Returns the current time without remembering the reading, for callers
+    /// that only observe the cache.
+    pub(crate) fn peek(&self) -> Instant {
+        let last = *self.last.lock_unpoisoned();
+        self.derive(last, self.clock.now()).0
+    }
+
+    fn derive(&self, last: Option<(Instant, Instant)>, raw: Instant) -> (Instant, bool) {
+        let Some((last_raw, last_now)) = last else {
+            return (raw, false);
+        };
+        match (self.policy, raw.checked_duration_since(last_raw)) {
+            (ClockJumpPolicy::Trust, elapsed) => (raw, elapsed.is_none()),
+            (_, None) => (last_now, true),
+            (ClockJumpPolicy::AbsorbForwardJumps(limit), Some(elapsed)) if elapsed > limit => {
+                (last_now, true)
+            }
+            (_, Some(elapsed)) => (last_now + elapsed, false),
+        }
+    }
+}
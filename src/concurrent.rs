@@ -0,0 +1,369 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::ops::ControlFlow;
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "tokio")]
+use crate::async_load::AsyncInFlight;
+use crate::eviction::{EvictionPolicy, Sieve};
+use crate::loader::{Load, SharedLoader};
+use crate::sieve::{DefaultHashBuilder, SieveCache};
+use crate::singleflight::InFlight;
+use crate::sync::RwLock;
+use crate::types::{CacheError, CacheStats, ConflictPolicy};
+
+/// A cache that can be shared between threads as is, e.g. behind an `Arc`.
+///
+/// Lookups take a shared lock and go through [`SieveCache::get`], so any
+/// number of threads can read at once; they only wait while an insert or
+/// removal holds the lock exclusively. Everything not covered by a method
+/// here is reachable through [`with`](Self::with).
+///
+/// ```
+/// use nitro::ConcurrentSieveCache;
+/// use std::sync::Arc;
+///
+/// let cache = Arc::new(ConcurrentSieveCache::new(100)?);
+/// cache.add("answer", 42)?;
+///
+/// let reader = Arc::clone(&cache);
+/// let found = std::thread::spawn(move || reader.get(&"answer")).join().unwrap()?;
+/// assert_eq!(found, Some(42));
+/// # Ok::<(), nitro::CacheError>(())
+/// ```
+pub struct ConcurrentSieveCache<K, V, P = Sieve<K>, S = DefaultHashBuilder> {
+    // Shared with refresh-ahead threads, which hold it weakly
+    pub(crate) inner: Arc<RwLock<SieveCache<K, V, P, S>>>,
+    loading: InFlight<K, V>,
+    #[cfg(feature = "tokio")]
+    pub(crate) async_loading: AsyncInFlight<K, V>,
+}
+
+impl<K, V> ConcurrentSieveCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    pub fn new(capacity: usize) -> Result<Self, CacheError> {
+        Ok(Self::from(SieveCache::new(capacity)?))
+    }
+}
+
+impl<K, V, P, S> From<SieveCache<K, V, P, S>> for ConcurrentSieveCache<K, V, P, S> {
+    /// Wraps a cache configured elsewhere, e.g. through
+    /// [`SieveCache::builder`].
+    fn from(cache: SieveCache<K, V, P, S>) -> Self {
+        ConcurrentSieveCache {
+            inner: Arc::new(RwLock::new(cache)),
+            loading: InFlight::default(),
+            #[cfg(feature = "tokio")]
+            async_loading: AsyncInFlight::default(),
+        }
+    }
+}
+
+impl<K, V, P, S> ConcurrentSieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Retrieves a value under the shared lock, see [`SieveCache::get`].
+    pub fn get<Q>(&self, key: &Q) -> Result<Option<V>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.inner.read()?.get(key)
+    }
+
+    /// See [`SieveCache::peek`].
+    pub fn peek<Q>(&self, key: &Q) -> Result<Option<V>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.read()?.peek(key)
+    }
+
+    /// See [`SieveCache::contains_key`].
+    pub fn contains_key<Q>(&self, key: &Q) -> Result<bool, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        Ok(self.inner.read()?.contains_key(key))
+    }
+
+    /// See [`SieveCache::get_or_load_many`]. Like
+    /// [`get_or_load`](Self::get_or_load), the loader runs without the lock,
+    /// but a batch does not wait for loads of its keys already in flight.
+    pub fn get_or_load_many(&self, keys: &[K]) -> Result<HashMap<K, V>, CacheError> {
+        let (mut found, missing, loader) = {
+            let mut cache = self.inner.write()?;
+            let (found, missing) = cache.lookup_many(keys)?;
+            (found, missing, cache.loader.clone())
+        };
+        if let Some(loader) = loader.filter(|_| !missing.is_empty()) {
+            let loaded = loader.load_many(&missing);
+            self.inner
+                .write()?
+                .store_loaded_many(missing, loaded, &mut found)?;
+        }
+        Ok(found)
+    }
+
+    /// See [`SieveCache::prime`].
+    pub fn prime<I>(&self, items: I) -> Result<usize, CacheError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.inner.write()?.prime(items)
+    }
+
+    /// See [`SieveCache::prime_from_loader`]. The loader runs without the
+    /// lock.
+    pub fn prime_from_loader(&self, keys: &[K]) -> Result<usize, CacheError> {
+        let (missing, loader) = {
+            let cache = self.inner.read()?;
+            (cache.uncached(keys), cache.loader.clone())
+        };
+        match loader.filter(|_| !missing.is_empty()) {
+            Some(loader) => {
+                let loaded = loader.load_many(&missing);
+                self.inner.write()?.prime_loaded(missing, loaded)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// See [`SieveCache::merge`].
+    pub fn merge<P2, S2>(
+        &self,
+        other: SieveCache<K, V, P2, S2>,
+        policy: ConflictPolicy,
+    ) -> Result<usize, CacheError>
+    where
+        P2: EvictionPolicy<K>,
+        S2: BuildHasher,
+    {
+        self.inner.write()?.merge(other, policy)
+    }
+
+    /// See [`SieveCache::add`].
+    pub fn add(&self, key: K, value: V) -> Result<bool, CacheError> {
+        self.inner.write()?.add(key, value)
+    }
+
+    /// See [`SieveCache::add_with_ttl`].
+    pub fn add_with_ttl(&self, key: K, value: V, ttl: Duration) -> Result<bool, CacheError> {
+        self.inner.write()?.add_with_ttl(key, value, ttl)
+    }
+
+    /// Retrieves a value and its version under the shared lock, see
+    /// [`SieveCache::get_versioned`].
+    pub fn get_versioned(&self, key: &K) -> Result<Option<(V, u64)>, CacheError> {
+        self.inner.read()?.get_versioned(key)
+    }
+
+    /// See [`SieveCache::put_if_version`]. The check and the write happen
+    /// under one lock, so concurrent writers cannot both succeed.
+    pub fn put_if_version(
+        &self,
+        key: K,
+        value: V,
+        expected: Option<u64>,
+    ) -> Result<Option<u64>, CacheError> {
+        self.inner.write()?.put_if_version(key, value, expected)
+    }
+
+    /// See [`SieveCache::delete`].
+    pub fn delete<Q>(&self, key: &Q) -> Result<bool, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.write()?.delete(key)
+    }
+
+    /// See [`SieveCache::remove`].
+    pub fn remove(&self, key: &K) -> Result<Option<V>, CacheError> {
+        self.inner.write()?.remove(key)
+    }
+
+    /// See [`SieveCache::remove_expired`].
+    pub fn remove_expired(&self) -> Result<usize, CacheError> {
+        self.inner.write()?.remove_expired()
+    }
+
+    /// See [`SieveCache::flush`].
+    pub fn flush(&self) -> Result<(), CacheError> {
+        self.inner.write()?.flush()
+    }
+
+    /// See [`SieveCache::run_maintenance`].
+    pub fn run_maintenance(&self) -> Result<usize, CacheError> {
+        self.inner.write()?.run_maintenance()
+    }
+
+    pub fn purge(&self) -> Result<(), CacheError> {
+        self.inner.write()?.purge();
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read_unpoisoned().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.read_unpoisoned().is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.read_unpoisoned().capacity()
+    }
+
+    /// See [`SieveCache::stats`]. With the `lock_stats` feature, waits
+    /// on this wrapper's lock are included.
+    pub fn get_stats(&self) -> CacheStats {
+        let stats = self.inner.read_unpoisoned().stats();
+        #[cfg(feature = "lock_stats")]
+        let stats = self.inner.stats().added_to(stats);
+        stats
+    }
+
+    /// Runs `f` with exclusive access to the cache, for anything the methods
+    /// above do not cover.
+    pub fn with<R>(
+        &self,
+        f: impl FnOnce(&mut SieveCache<K, V, P, S>) -> R,
+    ) -> Result<R, CacheError> {
+        let mut cache = self.inner.write()?;
+        Ok(f(&mut cache))
+    }
+
+    /// Unwraps the cache again, once any refresh-ahead still writing its
+    /// result back is done.
+    pub fn into_inner(self) -> Result<SieveCache<K, V, P, S>, CacheError> {
+        let mut inner = self.inner;
+        // Refresh threads only hold on to the cache while writing back
+        loop {
+            match Arc::try_unwrap(inner) {
+                Ok(inner) => return inner.into_inner(),
+                Err(shared) => {
+                    inner = shared;
+                    thread::yield_now();
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, P, S> ConcurrentSieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    P: EvictionPolicy<K> + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    /// See [`SieveCache::get_or_load`]. The lock is released while the
+    /// loader runs, so other threads are not held up by a slow fetch.
+    ///
+    /// Threads that miss on the same key at the same time share one load:
+    /// the first one calls the loader and the rest wait for its result, so a
+    /// hot key going missing does not stampede the backing store.
+    ///
+    /// With [refresh-ahead](SieveCache::set_refresh_ahead) enabled, a hit on
+    /// an entry close to expiring starts a reload on a background thread and
+    /// returns the current value right away.
+    pub fn get_or_load(&self, key: &K) -> Result<Option<V>, CacheError> {
+        let loader = {
+            let mut cache = self.inner.write()?;
+            match cache.begin_load(key)? {
+                ControlFlow::Break(value) => {
+                    if let Some((version, loader)) = cache.begin_refresh(key) {
+                        drop(cache);
+                        self.spawn_refresh(key.clone(), version, loader);
+                    }
+                    return Ok(value);
+                }
+                ControlFlow::Continue(loader) => loader,
+            }
+        };
+        self.loading.run(key, || {
+            // A load that just finished may have filled the key already
+            if let Some(value) = self.inner.read()?.peek(key)? {
+                return Ok(Some(value));
+            }
+            let value = loader.load(key);
+            self.inner.write()?.store_loaded(key, value)
+        })
+    }
+
+    fn spawn_refresh(&self, key: K, version: u64, loader: SharedLoader<K, V>) {
+        let refresh = Refresh {
+            cache: Arc::downgrade(&self.inner),
+            key,
+            version,
+            outcome: None,
+        };
+        // If the thread cannot be spawned, dropping the closure drops the
+        // refresh, which ends it without a result
+        let _ = thread::Builder::new()
+            .name("nitro-refresh".to_string())
+            .spawn(move || refresh.run(&*loader));
+    }
+}
+
+// A reload of one entry by refresh-ahead. The outcome is written back when
+// it is dropped, so a loader that panics still ends the refresh.
+struct Refresh<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    cache: Weak<RwLock<SieveCache<K, V, P, S>>>,
+    key: K,
+    version: u64,
+    outcome: Option<Result<Option<V>, CacheError>>,
+}
+
+impl<K, V, P, S> Refresh<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    fn run(mut self, loader: &dyn Load<K, V>) {
+        self.outcome = Some(loader.load(&self.key));
+    }
+}
+
+impl<K, V, P, S> Drop for Refresh<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        let Some(shared) = self.cache.upgrade() else {
+            return;
+        };
+        let outcome = self
+            .outcome
+            .take()
+            .unwrap_or(Err(CacheError::LoaderPanicked));
+        let Ok(mut cache) = shared.write() else {
+            return;
+        };
+        let _ = cache.store_refreshed(&self.key, self.version, outcome);
+    }
+}
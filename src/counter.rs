@@ -0,0 +1,97 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::eviction::EvictionPolicy;
+use crate::sieve::SieveCache;
+use crate::types::CacheError;
+
+/// Numeric values that [`SieveCache::increment`] and
+/// [`SieveCache::decrement`] can update in place.
+///
+/// Integers saturate at their bounds instead of wrapping, so a runaway quota
+/// counter sticks at the limit rather than starting over.
+pub trait Counter: Copy {
+    /// The value a missing counter starts from.
+    const ZERO: Self;
+
+    fn add(self, delta: Self) -> Self;
+
+    fn sub(self, delta: Self) -> Self;
+}
+
+macro_rules! saturating_counter {
+    ($($t:ty),*) => {$(
+        impl Counter for $t {
+            const ZERO: Self = 0;
+
+            fn add(self, delta: Self) -> Self {
+                self.saturating_add(delta)
+            }
+
+            fn sub(self, delta: Self) -> Self {
+                self.saturating_sub(delta)
+            }
+        }
+    )*};
+}
+
+saturating_counter!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl Counter for f32 {
+    const ZERO: Self = 0.0;
+
+    fn add(self, delta: Self) -> Self {
+        self + delta
+    }
+
+    fn sub(self, delta: Self) -> Self {
+        self - delta
+    }
+}
+
+impl Counter for f64 {
+    const ZERO: Self = 0.0;
+
+    fn add(self, delta: Self) -> Self {
+        self + delta
+    }
+
+    fn sub(self, delta: Self) -> Self {
+        self - delta
+    }
+}
+
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Counter,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Adds `delta` to the counter for `key`, starting from
+    /// [`Counter::ZERO`] if the key is absent or expired.
+    ///
+    /// The update counts as a write, like [`modify`](Self::modify).
+    ///
+    /// # Returns
+    /// The counter's new value.
+    pub fn increment(&mut self, key: &K, delta: V) -> Result<V, CacheError> {
+        self.update_counter(key, |value| value.add(delta))
+    }
+
+    /// Subtracts `delta` from the counter for `key`, see
+    /// [`increment`](Self::increment).
+    pub fn decrement(&mut self, key: &K, delta: V) -> Result<V, CacheError> {
+        self.update_counter(key, |value| value.sub(delta))
+    }
+
+    fn update_counter<F: FnOnce(V) -> V>(&mut self, key: &K, f: F) -> Result<V, CacheError> {
+        if let Some(mut value) = self.get_mut(key)? {
+            *value = f(*value);
+            return Ok(*value);
+        }
+        let value = f(V::ZERO);
+        self.write_through(key, &value)?;
+        self.add_entry(key.clone(), value, None)?;
+        Ok(value)
+    }
+}
@@ -0,0 +1,171 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::eviction::{EvictionPolicy, Sieve};
+use crate::node::NodeId;
+use crate::sieve::{DefaultHashBuilder, SieveCache};
+use crate::types::{CacheError, RemovalCause};
+
+/// A view into a single cache slot, obtained from [`SieveCache::entry`].
+///
+/// Accessors hand back clones instead of references, so the entry can still
+/// be consumed after its value was read.
+pub enum Entry<'a, K, V, P = Sieve<K>, S = DefaultHashBuilder>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    Occupied(OccupiedEntry<'a, K, V, P, S>),
+    Vacant(VacantEntry<'a, K, V, P, S>),
+}
+
+pub struct OccupiedEntry<'a, K, V, P = Sieve<K>, S = DefaultHashBuilder>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    pub(crate) cache: &'a mut SieveCache<K, V, P, S>,
+    pub(crate) key: K,
+    pub(crate) node: NodeId,
+}
+
+pub struct VacantEntry<'a, K, V, P = Sieve<K>, S = DefaultHashBuilder>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    pub(crate) cache: &'a mut SieveCache<K, V, P, S>,
+    pub(crate) key: K,
+}
+
+impl<'a, K, V, P, S> Entry<'a, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => &entry.key,
+            Entry::Vacant(entry) => &entry.key,
+        }
+    }
+
+    /// Returns the current value, inserting `default` if the slot is vacant.
+    pub fn or_insert(self, default: V) -> Result<V, CacheError> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the current value, inserting the result of `default` if the
+    /// slot is vacant. `default` only runs on a miss.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> Result<V, CacheError> {
+        match self {
+            Entry::Occupied(entry) => entry.get(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Modifies the value in place if the slot is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Result<Self, CacheError> {
+        match self {
+            Entry::Occupied(mut entry) => {
+                entry.modify(f)?;
+                Ok(Entry::Occupied(entry))
+            }
+            vacant => Ok(vacant),
+        }
+    }
+}
+
+impl<K, V, P, S> OccupiedEntry<'_, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> Result<V, CacheError> {
+        Ok(self.cache.nodes[self.node].value.clone())
+    }
+
+    /// Replaces the value, returning the previous one.
+    pub fn insert(mut self, value: V) -> Result<V, CacheError> {
+        self.cache.check_value_size(&value)?;
+        let ttl = self.cache.ttl_left(self.node);
+        self.cache.write_through_with_ttl(&self.key, &value, ttl)?;
+        let mut previous = None;
+        self.apply(|current| previous = Some(std::mem::replace(current, value)))?;
+        let previous = previous.expect("modify always runs the closure");
+        if let Some(listener) = &self.cache.eviction_listener {
+            listener(self.key, previous.clone(), RemovalCause::Replaced);
+        }
+        Ok(previous)
+    }
+
+    /// Removes the entry, returning its value.
+    pub fn remove(self) -> Result<V, CacheError> {
+        let value = self.get()?;
+        self.cache.delete_through(&self.key)?;
+        self.cache.remove_node(&self.key, RemovalCause::Explicit)?;
+        Ok(value)
+    }
+
+    // Like `apply`, but passes the new value on to any backend or operation
+    // log before the cache changes
+    fn modify<F: FnOnce(&mut V)>(&mut self, f: F) -> Result<(), CacheError> {
+        if !self.cache.writes_through() {
+            return self.apply(f);
+        }
+        let mut value = self.cache.nodes[self.node].value.clone();
+        f(&mut value);
+        let ttl = self.cache.ttl_left(self.node);
+        self.cache.write_through_with_ttl(&self.key, &value, ttl)?;
+        self.apply(|current| *current = value)
+    }
+
+    // Applies `f` to the value and records it as a write
+    fn apply<F: FnOnce(&mut V)>(&mut self, f: F) -> Result<(), CacheError> {
+        let now = self.cache.now();
+        f(&mut self.cache.nodes[self.node].value);
+        if self.cache.ttl_fn.is_some() {
+            let value = &self.cache.nodes[self.node].value;
+            let expires_at = self.cache.derived_expiry(&self.key, value, now);
+            self.cache.nodes[self.node].expires_at = expires_at;
+        }
+        self.cache.generation += 1;
+        let node = &mut self.cache.nodes[self.node];
+        node.set_written_at(now);
+        node.mark_visited();
+        node.version = self.cache.generation;
+        self.cache.schedule_expiry(&self.key)
+    }
+}
+
+impl<K, V, P, S> VacantEntry<'_, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Inserts `value`, evicting another entry if the cache is full.
+    pub fn insert(self, value: V) -> Result<V, CacheError> {
+        self.cache.write_through(&self.key, &value)?;
+        self.cache.insert(self.key, value.clone(), None)?;
+        Ok(value)
+    }
+}
@@ -1,50 +1,794 @@
-use crate::{linked_list::LinkedListOps, CacheError};
-use std::hash::Hash;
-use std::sync::atomic::Ordering;
+//! Replacement strategies.
+//!
+//! A [`SieveCache`] owns the map, the entry list, expiry and statistics, and
+//! delegates the choice of which entry to evict to an [`EvictionPolicy`]. The
+//! default policy is [`Sieve`]; other strategies can be plugged in through
+//! [`SieveCache::with_policy`] or [`SieveCacheBuilder::policy`](crate::SieveCacheBuilder::policy).
 
-pub(crate) trait EvictionPolicy<K, V> {
-    fn evict(&mut self) -> Result<(), CacheError>;
+use crate::linked_list::{detach, push_front};
+use crate::node::{Node, NodeId};
+use crate::recorder::StatEvent;
+use crate::rng::Rng;
+use crate::sieve::{EvictionInterceptor, SieveCache};
+use crate::slab::Slab;
+use crate::types::{CacheError, Priority, RemovalCause};
+use std::borrow::Borrow;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hash};
+
+/// Where an entry sits in an [`EntryList`].
+///
+/// Walking the list by position skips the key clone and hash lookup each
+/// step by key costs. A position stays valid for the rest of the policy
+/// call it was obtained in, as long as its entry stays in the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position(NodeId);
+
+/// The cache's entries as seen by an [`EvictionPolicy`].
+///
+/// Entries form a list ordered by insertion, newest first, and each carries a
+/// visited bit that the cache sets whenever the entry is read or updated.
+/// Policies can walk the list, clear visited bits and reorder entries, so
+/// most strategies need no bookkeeping of their own.
+pub trait EntryList<K> {
+    /// Number of entries in the cache.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn contains(&self, key: &K) -> bool;
+
+    /// The most recently inserted (or moved) entry.
+    fn newest(&self) -> Result<Option<K>, CacheError>;
+
+    /// The entry that has been in the list the longest.
+    fn oldest(&self) -> Result<Option<K>, CacheError>;
+
+    /// The neighbour of `key` on the newer side, if any.
+    fn newer(&self, key: &K) -> Result<Option<K>, CacheError>;
+
+    /// The neighbour of `key` on the older side, if any.
+    fn older(&self, key: &K) -> Result<Option<K>, CacheError>;
+
+    fn visited(&self, key: &K) -> Result<bool, CacheError>;
+
+    fn set_visited(&mut self, key: &K, visited: bool) -> Result<(), CacheError>;
+
+    /// Moves `key` to the newest end of the list.
+    fn move_to_newest(&mut self, key: &K) -> Result<(), CacheError>;
+
+    /// An entry picked uniformly at random, in constant time.
+    fn random(&self) -> Result<Option<K>, CacheError>;
+
+    /// Asks the cache's eviction interceptor whether `key` may be evicted.
+    ///
+    /// Returns `true` once the interceptor has vetoed as many candidates as it
+    /// is allowed to within the current eviction, so a policy that keeps
+    /// asking is guaranteed to find a victim. Pinned entries are refused
+    /// regardless; the cache only asks for a victim while some entry is
+    /// unpinned.
+    fn may_evict(&mut self, key: &K) -> Result<bool, CacheError>;
+
+    /// The priority of `key`, see [`SieveCache::add_with_priority`].
+    fn priority(&self, key: &K) -> Result<Priority, CacheError> {
+        let _ = key;
+        Ok(Priority::Normal)
+    }
+
+    /// The lowest priority any entry has, [`Priority::Normal`] when empty.
+    fn lowest_priority(&self) -> Result<Priority, CacheError> {
+        Ok(Priority::Normal)
+    }
+
+    /// Whether `key` is pinned, see [`SieveCache::pin`]. Policies that evict
+    /// without asking [`may_evict`](Self::may_evict) must skip such entries.
+    fn is_pinned(&self, key: &K) -> Result<bool, CacheError> {
+        let _ = key;
+        Ok(false)
+    }
+
+    /// The position of `key`, if it is cached.
+    fn position(&self, key: &K) -> Option<Position>;
+
+    /// The position of the [`oldest`](Self::oldest) entry.
+    fn oldest_position(&self) -> Option<Position>;
+
+    /// The position of the entry on the newer side of `at`, if any.
+    fn newer_position(&self, at: Position) -> Option<Position>;
+
+    /// The position of the entry on the older side of `at`, if any.
+    fn older_position(&self, at: Position) -> Option<Position>;
+
+    /// The key of the entry at `at`.
+    fn key_at(&self, at: Position) -> K;
+
+    /// Like [`visited`](Self::visited), by position.
+    fn visited_at(&self, at: Position) -> bool;
+
+    /// Like [`set_visited`](Self::set_visited), by position.
+    fn set_visited_at(&mut self, at: Position, visited: bool);
+
+    /// Like [`may_evict`](Self::may_evict), by position.
+    fn may_evict_at(&mut self, at: Position) -> bool;
+
+    /// Like [`priority`](Self::priority), by position.
+    fn priority_at(&self, at: Position) -> Priority {
+        let _ = at;
+        Priority::Normal
+    }
+
+    /// Like [`is_pinned`](Self::is_pinned), by position.
+    fn is_pinned_at(&self, at: Position) -> bool {
+        let _ = at;
+        false
+    }
+}
+
+/// Decides which entry a [`SieveCache`] evicts when it is full.
+///
+/// The trait is object safe, so `Box<dyn EvictionPolicy<K>>` works as a policy
+/// when the strategy is only known at runtime.
+pub trait EvictionPolicy<K> {
+    /// Called after `key` was inserted as the newest entry.
+    fn on_insert(&mut self, key: &K, entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        let _ = (key, entries);
+        Ok(())
+    }
+
+    /// Called after `key` was read or updated and its visited bit was set.
+    fn on_access(&mut self, key: &K, entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        let _ = (key, entries);
+        Ok(())
+    }
+
+    /// Whether [`on_access`](Self::on_access) needs to hear about lookups.
+    ///
+    /// [`SieveCache::get`] only takes `&self`, so it queues every hit for the
+    /// policy until the next call that takes `&mut self`. Policies that only
+    /// rely on the visited bit return `false` to skip the queue.
+    fn observes_lookups(&self) -> bool {
+        true
+    }
+
+    /// Called before `key` leaves the cache for any reason, while it is still
+    /// in the list.
+    fn on_remove(&mut self, key: &K, entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        let _ = (key, entries);
+        Ok(())
+    }
+
+    /// Picks the entry to evict from a full cache.
+    ///
+    /// Returning `None`, or a key that is not in the cache, makes the insert
+    /// that triggered the eviction fail with [`CacheError::CapacityError`].
+    fn select_victim(&mut self, entries: &mut dyn EntryList<K>) -> Result<Option<K>, CacheError>;
+
+    /// Called right after [`select_victim`](Self::select_victim) when the
+    /// cache keeps `victim` after all, because the
+    /// [admission filter](crate::SieveCacheBuilder::admission_filter) turned
+    /// the new key away.
+    ///
+    /// The cache has already restored the visited bits the selection
+    /// cleared. Policies with state of their own, such as a hand, put it
+    /// back, so a rejected insert leaves the eviction order as it was.
+    fn on_victim_kept(
+        &mut self,
+        victim: &K,
+        entries: &mut dyn EntryList<K>,
+    ) -> Result<(), CacheError> {
+        let _ = (victim, entries);
+        Ok(())
+    }
+
+    /// Called when every entry is removed at once.
+    fn clear(&mut self) {}
+}
+
+impl<K, P: EvictionPolicy<K> + ?Sized> EvictionPolicy<K> for Box<P> {
+    fn on_insert(&mut self, key: &K, entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        (**self).on_insert(key, entries)
+    }
+
+    fn on_access(&mut self, key: &K, entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        (**self).on_access(key, entries)
+    }
+
+    fn observes_lookups(&self) -> bool {
+        (**self).observes_lookups()
+    }
+
+    fn on_remove(&mut self, key: &K, entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        (**self).on_remove(key, entries)
+    }
+
+    fn select_victim(&mut self, entries: &mut dyn EntryList<K>) -> Result<Option<K>, CacheError> {
+        (**self).select_victim(entries)
+    }
+
+    fn on_victim_kept(
+        &mut self,
+        victim: &K,
+        entries: &mut dyn EntryList<K>,
+    ) -> Result<(), CacheError> {
+        (**self).on_victim_kept(victim, entries)
+    }
+
+    fn clear(&mut self) {
+        (**self).clear()
+    }
+}
+
+/// The SIEVE algorithm.
+///
+/// A hand sweeps from the oldest entry towards the newest, clearing visited
+/// bits as it goes, and evicts the first entry that was not visited since the
+/// hand last passed it. Survivors keep their position in the list.
+///
+/// When every entry was visited the hand clears the whole list before it
+/// finds a victim. [`with_max_scan`](Self::with_max_scan) caps that sweep so
+/// inserts take bounded time.
+///
+/// Entries with a [`Priority`] above the lowest one in the cache are passed
+/// over while unvisited, so the hand settles on the cheapest entry to lose.
+/// After two laps, by which time every visited bit has been cleared once,
+/// the hand takes whatever unvisited entry comes next, so priorities never
+/// stall an eviction.
+#[derive(Debug, Clone)]
+pub struct Sieve<K> {
+    hand: Option<K>,
+    // Where the last sweep started, for `on_victim_kept`
+    start: Option<K>,
+    max_scan: Option<usize>,
+}
+
+impl<K> Sieve<K> {
+    pub fn new() -> Self {
+        Sieve {
+            hand: None,
+            start: None,
+            max_scan: None,
+        }
+    }
+
+    /// Looks at no more than `max_scan` entries per eviction: if none of the
+    /// first `max_scan - 1` can go, the entry the hand reaches next is evicted
+    /// whether it was visited or not, without asking the eviction
+    /// interceptor. A bound of 1 evicts in insertion order. Pinned entries
+    /// are still passed over.
+    pub fn with_max_scan(max_scan: usize) -> Self {
+        Sieve {
+            hand: None,
+            start: None,
+            max_scan: Some(max_scan.max(1)),
+        }
+    }
+
+    /// The entry the hand rests on, or `None` when the next sweep starts at
+    /// the oldest entry.
+    pub fn hand(&self) -> Option<&K> {
+        self.hand.as_ref()
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn max_scan(&self) -> Option<usize> {
+        self.max_scan
+    }
+
+    // Puts the hand back where a serialized cache had it
+    #[cfg(feature = "serde")]
+    pub(crate) fn set_hand(&mut self, hand: Option<K>) {
+        self.hand = hand;
+    }
+}
+
+impl<K> Default for Sieve<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Clone> EvictionPolicy<K> for Sieve<K> {
+    fn observes_lookups(&self) -> bool {
+        false
+    }
+
+    fn on_remove(&mut self, key: &K, entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        // Keep the hand off the entry being removed
+        if self.hand.as_ref() == Some(key) {
+            self.hand = entries.newer(key)?;
+        }
+        Ok(())
+    }
+
+    fn select_victim(&mut self, entries: &mut dyn EntryList<K>) -> Result<Option<K>, CacheError> {
+        self.start = self.hand.take();
+        // Walk by position, so only the victim's key is ever cloned
+        let mut hand = match &self.start {
+            Some(key) => entries.position(key),
+            None => entries.oldest_position(),
+        };
+
+        let mut scanned = 0;
+        let laps = entries.len().saturating_mul(2);
+        let lowest = entries.lowest_priority()?;
+        while let Some(at) = hand {
+            scanned += 1;
+            let forced = self.max_scan.is_some_and(|max| scanned >= max);
+            // Past the scan limit, the next unpinned entry goes regardless
+            if forced && !entries.is_pinned_at(at) {
+                let key = entries.key_at(at);
+                self.hand = Some(key.clone());
+                return Ok(Some(key));
+            } else if entries.visited_at(at) {
+                entries.set_visited_at(at, false);
+            } else if scanned <= laps && entries.priority_at(at) > lowest {
+                // A cheaper entry is still around
+            } else if entries.may_evict_at(at) {
+                // Vetoed candidates are skipped until the retry budget runs
+                // out, after which the next unvisited entry is evicted
+                // unconditionally. Removing the victim moves the hand on to
+                // its newer neighbour
+                let key = entries.key_at(at);
+                self.hand = Some(key.clone());
+                return Ok(Some(key));
+            }
+
+            hand = entries
+                .newer_position(at)
+                .or_else(|| entries.oldest_position());
+        }
+        Ok(None)
+    }
+
+    fn on_victim_kept(&mut self, _: &K, _: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        self.hand = self.start.take();
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.hand = None;
+        self.start = None;
+    }
+}
+
+/// SIEVE with a probationary and a protected segment.
+///
+/// New entries start out on probation. When the probation hand finds an
+/// entry that was visited since it was inserted, the entry is promoted to the
+/// protected segment instead of merely being spared. Victims are taken from
+/// probation only, so a large one-time sweep cycles through probation without
+/// touching entries that proved themselves earlier.
+///
+/// The protected segment is swept by its own hand: once it outgrows its share
+/// of the cache, entries not visited since that hand last passed are demoted
+/// back to probation.
+#[derive(Debug, Clone)]
+pub struct SegmentedSieve<K> {
+    protected: HashSet<K>,
+    max_protected: usize,
+    probation_hand: Option<K>,
+    protected_hand: Option<K>,
+}
+
+impl<K: Eq + Hash + Clone> SegmentedSieve<K> {
+    /// Creates a policy for a cache of `capacity` entries, reserving 80% of
+    /// it for the protected segment.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_protected_capacity(capacity * 4 / 5)
+    }
+
+    /// Creates a policy whose protected segment holds up to `max_protected`
+    /// entries.
+    pub fn with_protected_capacity(max_protected: usize) -> Self {
+        SegmentedSieve {
+            protected: HashSet::new(),
+            max_protected,
+            probation_hand: None,
+            protected_hand: None,
+        }
+    }
+
+    /// Whether `key` has been promoted to the protected segment.
+    pub fn is_protected(&self, key: &K) -> bool {
+        self.protected.contains(key)
+    }
+
+    /// Number of entries in the protected segment.
+    pub fn protected_len(&self) -> usize {
+        self.protected.len()
+    }
+
+    // Sweeps the protected segment and moves its first unvisited entry back
+    // to probation
+    fn demote(&mut self, entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        let mut hand = match self.protected_hand.take() {
+            Some(hand) => Some(hand),
+            None => entries.oldest()?,
+        };
+        while let Some(key) = hand {
+            let next = match entries.newer(&key)? {
+                Some(newer) => Some(newer),
+                None => entries.oldest()?,
+            };
+            if self.protected.contains(&key) {
+                if entries.visited(&key)? {
+                    entries.set_visited(&key, false)?;
+                } else {
+                    self.protected.remove(&key);
+                    self.protected_hand = next;
+                    return Ok(());
+                }
+            }
+            hand = next;
+        }
+        Ok(())
+    }
+}
+
+impl<K: Eq + Hash + Clone> EvictionPolicy<K> for SegmentedSieve<K> {
+    fn observes_lookups(&self) -> bool {
+        false
+    }
+
+    fn on_remove(&mut self, key: &K, entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        if self.probation_hand.as_ref() == Some(key) {
+            self.probation_hand = entries.newer(key)?;
+        }
+        if self.protected_hand.as_ref() == Some(key) {
+            self.protected_hand = entries.newer(key)?;
+        }
+        self.protected.remove(key);
+        Ok(())
+    }
+
+    fn select_victim(&mut self, entries: &mut dyn EntryList<K>) -> Result<Option<K>, CacheError> {
+        let mut hand = match self.probation_hand.take() {
+            Some(hand) => Some(hand),
+            None => entries.oldest()?,
+        };
+
+        while let Some(key) = hand {
+            // Keep at least one entry on probation to evict from
+            while !self.protected.is_empty() && self.protected.len() >= entries.len() {
+                self.demote(entries)?;
+            }
+
+            if !self.protected.contains(&key) {
+                if entries.visited(&key)? {
+                    entries.set_visited(&key, false)?;
+                    self.protected.insert(key.clone());
+                    if self.protected.len() > self.max_protected {
+                        self.demote(entries)?;
+                    }
+                } else if entries.may_evict(&key)? {
+                    self.probation_hand = Some(key.clone());
+                    return Ok(Some(key));
+                }
+            }
+
+            hand = match entries.newer(&key)? {
+                Some(newer) => Some(newer),
+                None => entries.oldest()?,
+            };
+        }
+        Ok(None)
+    }
+
+    fn clear(&mut self) {
+        self.protected.clear();
+        self.probation_hand = None;
+        self.protected_hand = None;
+    }
 }
 
-impl<K, V> EvictionPolicy<K, V> for super::SieveCache<K, V>
+// CacheEntries exposes the cache's map and list to the policy without handing
+// out the cache itself, which owns the policy
+pub(crate) struct CacheEntries<'a, K, V, S> {
+    pub(crate) map: &'a HashMap<K, NodeId, S>,
+    pub(crate) nodes: &'a mut Slab<Node<K, V>>,
+    pub(crate) head: &'a mut Option<NodeId>,
+    pub(crate) tail: &'a mut Option<NodeId>,
+    pub(crate) slots: &'a [NodeId],
+    pub(crate) rng: &'a Rng,
+    pub(crate) interceptor: Option<&'a EvictionInterceptor<K, V>>,
+    pub(crate) max_vetoes: usize,
+    pub(crate) vetoes: usize,
+    pub(crate) priorities: [usize; 3],
+    // Neighbour lookups and visited bits cleared, for efficiency metrics
+    pub(crate) steps: Cell<usize>,
+    pub(crate) resets: usize,
+    // The entries whose visited bit was cleared, when it may have to be set
+    // again
+    pub(crate) cleared: Option<Vec<NodeId>>,
+}
+
+impl<K, V, S> CacheEntries<'_, K, V, S>
 where
     K: Eq + Hash + Clone,
-    V: Clone,
+    S: BuildHasher,
+{
+    fn key_of(&self, at: Option<Position>) -> Option<K> {
+        at.map(|at| self.key_at(at))
+    }
+
+    fn node_at(&self, at: Position) -> &Node<K, V> {
+        &self.nodes[at.0]
+    }
+}
+
+impl<K, V, S> EntryList<K> for CacheEntries<'_, K, V, S>
+where
+    K: Eq + Hash + Clone,
+    S: BuildHasher,
 {
-    fn evict(&mut self) -> Result<(), CacheError> {
-        if self.hand.is_none() {
-            self.hand = self.tail.clone();
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    fn newest(&self) -> Result<Option<K>, CacheError> {
+        Ok(self.key_of(self.head.map(Position)))
+    }
+
+    fn oldest(&self) -> Result<Option<K>, CacheError> {
+        Ok(self.key_of(self.oldest_position()))
+    }
+
+    fn newer(&self, key: &K) -> Result<Option<K>, CacheError> {
+        let at = self.position(key);
+        Ok(self.key_of(at.and_then(|at| self.newer_position(at))))
+    }
+
+    fn older(&self, key: &K) -> Result<Option<K>, CacheError> {
+        let at = self.position(key);
+        Ok(self.key_of(at.and_then(|at| self.older_position(at))))
+    }
+
+    fn visited(&self, key: &K) -> Result<bool, CacheError> {
+        Ok(self.position(key).is_some_and(|at| self.visited_at(at)))
+    }
+
+    fn set_visited(&mut self, key: &K, visited: bool) -> Result<(), CacheError> {
+        if let Some(at) = self.position(key) {
+            self.set_visited_at(at, visited);
+        }
+        Ok(())
+    }
+
+    fn move_to_newest(&mut self, key: &K) -> Result<(), CacheError> {
+        let Some(&id) = self.map.get(key) else {
+            return Ok(());
+        };
+        detach(self.nodes, self.head, self.tail, id);
+        push_front(self.nodes, self.head, self.tail, id);
+        Ok(())
+    }
+
+    fn random(&self) -> Result<Option<K>, CacheError> {
+        if self.slots.is_empty() {
+            return Ok(None);
         }
+        let id = self.slots[self.rng.below(self.slots.len())];
+        Ok(self.key_of(Some(Position(id))))
+    }
+
+    fn may_evict(&mut self, key: &K) -> Result<bool, CacheError> {
+        Ok(self.position(key).is_none_or(|at| self.may_evict_at(at)))
+    }
 
-        while let Some(current) = &self.hand {
-            let curr_guard = current
-                .lock()
-                .map_err(|e| CacheError::LockError(e.to_string()))?;
+    fn is_pinned(&self, key: &K) -> Result<bool, CacheError> {
+        Ok(self.position(key).is_some_and(|at| self.is_pinned_at(at)))
+    }
 
-            if !curr_guard.visited.load(Ordering::SeqCst) {
-                let key = curr_guard.key.clone();
-                let prev = curr_guard.prev.clone();
+    fn priority(&self, key: &K) -> Result<Priority, CacheError> {
+        Ok(self
+            .position(key)
+            .map_or(Priority::Normal, |at| self.priority_at(at)))
+    }
 
-                // Explicitly drop the guard before further operations
-                drop(curr_guard);
+    fn lowest_priority(&self) -> Result<Priority, CacheError> {
+        Ok(Priority::ALL
+            .into_iter()
+            .find(|&priority| self.priorities[priority as usize] > 0)
+            .unwrap_or(Priority::Normal))
+    }
 
-                self.cache.remove(&key);
-                self.unlink_node(current.clone())?;
-                self.hand = prev;
-                self.size -= 1;
-                return Ok(());
+    fn position(&self, key: &K) -> Option<Position> {
+        self.map.get(key).map(|&id| Position(id))
+    }
+
+    fn oldest_position(&self) -> Option<Position> {
+        self.tail.map(Position)
+    }
+
+    // The list runs from the head (newest) via `next` to the tail
+    fn newer_position(&self, at: Position) -> Option<Position> {
+        self.steps.set(self.steps.get() + 1);
+        self.node_at(at).prev.map(Position)
+    }
+
+    fn older_position(&self, at: Position) -> Option<Position> {
+        self.steps.set(self.steps.get() + 1);
+        self.node_at(at).next.map(Position)
+    }
+
+    fn key_at(&self, at: Position) -> K {
+        self.node_at(at).key.clone()
+    }
+
+    fn visited_at(&self, at: Position) -> bool {
+        self.node_at(at).is_visited()
+    }
+
+    fn set_visited_at(&mut self, at: Position, visited: bool) {
+        let was_visited = self.node_at(at).set_visited(visited);
+        if was_visited && !visited {
+            self.resets += 1;
+            if let Some(cleared) = &mut self.cleared {
+                cleared.push(at.0);
             }
+        }
+    }
+
+    fn may_evict_at(&mut self, at: Position) -> bool {
+        if self.is_pinned_at(at) {
+            return false;
+        }
+        let Some(interceptor) = self.interceptor else {
+            return true;
+        };
+        if self.vetoes >= self.max_vetoes {
+            return true;
+        }
+        let node = self.node_at(at);
+        let allowed = interceptor(&node.key, &node.value);
+        if !allowed {
+            self.vetoes += 1;
+        }
+        allowed
+    }
+
+    fn is_pinned_at(&self, at: Position) -> bool {
+        self.node_at(at).pinned
+    }
+
+    fn priority_at(&self, at: Position) -> Priority {
+        self.node_at(at).priority
+    }
+}
 
-            curr_guard.visited.store(false, Ordering::SeqCst);
-            let prev = curr_guard.prev.clone();
-            drop(curr_guard);
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    // Runs `f` with the policy and a fresh view of the entries
+    pub(crate) fn with_entries<R>(
+        &mut self,
+        f: impl FnOnce(&mut P, &mut CacheEntries<'_, K, V, S>) -> R,
+    ) -> R {
+        let mut entries = CacheEntries {
+            map: &self.cache,
+            nodes: &mut self.nodes,
+            head: &mut self.head,
+            tail: &mut self.tail,
+            slots: &self.slots,
+            rng: &self.rng,
+            interceptor: self.eviction_interceptor.as_ref(),
+            max_vetoes: self.max_eviction_vetoes,
+            vetoes: 0,
+            priorities: self.priorities,
+            steps: Cell::new(0),
+            resets: 0,
+            cleared: None,
+        };
+        f(&mut self.policy, &mut entries)
+    }
 
-            self.hand = prev;
+    // Like `with_entries`, but also hands `f` the cached key equal to `key`,
+    // so lookups by a borrowed form can still notify the policy. Returns
+    // `None` without running `f` if the key is not cached.
+    pub(crate) fn with_cached_key<Q, R>(
+        &mut self,
+        key: &Q,
+        f: impl FnOnce(&mut P, &mut CacheEntries<'_, K, V, S>, &K) -> R,
+    ) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.with_entries(|policy, entries| {
+            let map = entries.map;
+            let (key, _) = map.get_key_value(key)?;
+            Some(f(policy, entries, key))
+        })
+    }
 
-            if self.hand.is_none() {
-                self.hand = self.tail.clone();
+    // Asks the policy for a victim without removing it yet
+    pub(crate) fn select_victim(&mut self) -> Result<K, CacheError> {
+        self.select_victim_with(false).map(|(victim, _)| victim)
+    }
+
+    // Like `select_victim`, but only takes the victim if `accept` agrees.
+    // Otherwise the selection is undone: the visited bits cleared on the way
+    // are set again and the policy puts back its own state.
+    pub(crate) fn select_victim_if(
+        &mut self,
+        accept: impl FnOnce(&Self, &K) -> bool,
+    ) -> Result<Option<K>, CacheError> {
+        let (victim, cleared) = self.select_victim_with(true)?;
+        if accept(self, &victim) {
+            return Ok(Some(victim));
+        }
+        for id in cleared {
+            self.nodes[id].mark_visited();
+        }
+        self.with_entries(|policy, entries| policy.on_victim_kept(&victim, entries))?;
+        Ok(None)
+    }
+
+    fn select_victim_with(&mut self, undoable: bool) -> Result<(K, Vec<NodeId>), CacheError> {
+        // Policies keep looking until something can go, so never ask them
+        // when nothing can
+        if self.size > 0 && self.pinned == self.size {
+            return Err(CacheError::CapacityError(
+                "Every cached entry is pinned".to_string(),
+            ));
+        }
+        let (victim, steps, resets, cleared) = self.with_entries(|policy, entries| {
+            entries.cleared = undoable.then(Vec::new);
+            let victim = policy.select_victim(entries);
+            let cleared = entries.cleared.take().unwrap_or_default();
+            (victim, entries.steps.get(), entries.resets, cleared)
+        });
+        if self.collect_stats {
+            self.stats.hand_steps += steps;
+            self.stats.visited_resets += resets;
+            if steps == 0 {
+                self.stats.first_probe_victims += 1;
             }
         }
-        Ok(())
+        let victim = victim?
+            .filter(|key| self.cache.contains_key(key) && !self.is_pinned(key))
+            .ok_or_else(|| {
+                CacheError::CapacityError(
+                    "Eviction policy did not select a cached entry".to_string(),
+                )
+            })?;
+        Ok((victim, cleared))
+    }
+
+    /// Evicts `victim` and hands back its entry.
+    pub(crate) fn evict_entry(&mut self, victim: &K) -> Result<Option<(K, V)>, CacheError> {
+        let Some(node) = self.take_node(victim)? else {
+            return Ok(None);
+        };
+        // A victim left over from an older epoch or past its deadline
+        // counts as expired, as it would have on its next lookup
+        let cause = if self.is_node_expired(&node, self.clock.peek()) {
+            self.record(StatEvent::Expiration);
+            RemovalCause::Expired
+        } else {
+            self.record(StatEvent::Eviction);
+            RemovalCause::Evicted
+        };
+        let (key, value) = node.into_entry();
+        if cause == RemovalCause::Evicted {
+            if let Some(ghosts) = &mut self.ghosts {
+                ghosts.push(key.clone());
+            }
+        }
+        if let Some(listener) = &self.eviction_listener {
+            listener(key.clone(), value.clone(), cause);
+        }
+        Ok(Some((key, value)))
     }
 }
@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 6;
+
+/// Resolution of the expiration wheel used by `SieveCache`.
+pub(crate) const DEFAULT_TICK: Duration = Duration::from_millis(10);
+
+// TimerWheel is a hierarchical timing wheel over cache keys. Level 0 holds
+// deadlines within the next 64 ticks at single-tick resolution, and every
+// higher level covers 64 times the span of the one below. When the wheel turns
+// past a higher-level slot, its timers cascade down into finer slots, so each
+// tick only touches the timers that are actually due.
+//
+// The wheel never removes timers eagerly. Callers record the deadline a key was
+// scheduled under and ignore timers that no longer match when they fire.
+pub(crate) struct TimerWheel<K> {
+    start: Instant,
+    tick: Duration,
+    current: u64,
+    levels: Vec<Vec<Vec<(K, Instant, u64)>>>,
+    len: usize,
+}
+
+impl<K> TimerWheel<K> {
+    pub(crate) fn new(start: Instant, tick: Duration) -> Self {
+        TimerWheel {
+            start,
+            tick: tick.max(Duration::from_nanos(1)),
+            current: 0,
+            levels: (0..LEVELS)
+                .map(|_| (0..SLOTS).map(|_| Vec::new()).collect())
+                .collect(),
+            len: 0,
+        }
+    }
+
+    /// Registers `key` to fire once the wheel reaches `deadline`.
+    pub(crate) fn schedule(&mut self, key: K, deadline: Instant) {
+        let tick = self.ticks_until(deadline).max(self.current + 1);
+        self.place(key, deadline, tick);
+        self.len += 1;
+    }
+
+    /// Turns the wheel up to `now` and returns every timer that came due, with
+    /// the deadline it was scheduled under.
+    ///
+    /// The tick containing `now` is included, so every deadline up to `now` is
+    /// returned along with some that are up to one tick in the future.
+    pub(crate) fn advance(&mut self, now: Instant) -> Vec<(K, Instant)> {
+        let target = self.ticks_until(now);
+        let mut due = Vec::new();
+        while self.current < target {
+            if self.len == 0 {
+                self.current = target;
+                break;
+            }
+            self.current += 1;
+            self.cascade();
+            let slot = (self.current as usize) & (SLOTS - 1);
+            for (key, deadline, _) in std::mem::take(&mut self.levels[0][slot]) {
+                self.len -= 1;
+                due.push((key, deadline));
+            }
+        }
+        due
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for level in &mut self.levels {
+            for slot in level {
+                slot.clear();
+            }
+        }
+        self.len = 0;
+    }
+
+    fn cascade(&mut self) {
+        for level in (1..LEVELS).rev() {
+            let span_bits = SLOT_BITS * level as u32;
+            if self.current & ((1 << span_bits) - 1) != 0 {
+                continue;
+            }
+            let slot = ((self.current >> span_bits) as usize) & (SLOTS - 1);
+            for (key, deadline, tick) in std::mem::take(&mut self.levels[level][slot]) {
+                self.place(key, deadline, tick);
+            }
+        }
+    }
+
+    fn place(&mut self, key: K, deadline: Instant, tick: u64) {
+        // The level is chosen by the highest bit in which the target tick differs
+        // from the current one, so the slot is reached before the target tick
+        let differing = tick ^ self.current;
+        let level = if differing == 0 {
+            0
+        } else {
+            ((63 - differing.leading_zeros()) / SLOT_BITS) as usize
+        };
+        let (level, slot_tick) = if level >= LEVELS {
+            // Deadlines past the wheel's horizon (2^36 ticks) are parked in the
+            // last top-level slot and re-placed whenever it cascades
+            let top = SLOT_BITS * (LEVELS as u32 - 1);
+            (LEVELS - 1, (SLOTS as u64 - 1) << top)
+        } else {
+            (level, tick)
+        };
+        let slot = ((slot_tick >> (SLOT_BITS * level as u32)) as usize) & (SLOTS - 1);
+        self.levels[level][slot].push((key, deadline, tick));
+    }
+
+    fn ticks_until(&self, instant: Instant) -> u64 {
+        let elapsed = instant.saturating_duration_since(self.start).as_nanos();
+        u64::try_from(elapsed.div_ceil(self.tick.as_nanos())).unwrap_or(u64::MAX)
+    }
+}
@@ -0,0 +1,127 @@
+//! Exporting the cache contents, enabled with the `export` feature.
+
+use std::hash::{BuildHasher, Hash};
+use std::io::Write;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::concurrent::ConcurrentSieveCache;
+use crate::eviction::EvictionPolicy;
+use crate::sieve::SieveCache;
+use crate::types::CacheError;
+
+/// The file format of an [export](SieveCache::export).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A JSON array of `{"key", "value", "visited", "age_ms"}` objects.
+    Json,
+    /// A header line and one `key,value,visited,age_ms` row per entry. Keys
+    /// and values that serialize to strings are written as they are, others
+    /// as JSON.
+    Csv,
+}
+
+#[derive(Serialize)]
+struct ExportedEntry<'a, K, V> {
+    key: &'a K,
+    value: &'a V,
+    visited: bool,
+    age_ms: u64,
+}
+
+fn export_error<E: std::error::Error + Send + Sync + 'static>(error: E) -> CacheError {
+    CacheError::Persistence(Arc::new(error))
+}
+
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Serialize + Eq + Hash + Clone,
+    V: Serialize + Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Writes every entry with its visited bit and age, the time since it
+    /// was last written, to `writer` for offline analysis of what the cache
+    /// holds.
+    ///
+    /// Entries are written from the oldest to the newest. Expired entries
+    /// that were not removed yet are included, and nothing counts as a use
+    /// of an entry.
+    ///
+    /// ```
+    /// use nitro::{Format, SieveCache};
+    ///
+    /// let mut cache = SieveCache::new(10)?;
+    /// cache.add("a", 1)?;
+    /// let mut csv = Vec::new();
+    /// cache.export(&mut csv, Format::Csv)?;
+    /// assert!(String::from_utf8(csv).unwrap().starts_with("key,value,visited,age_ms\na,1,false,"));
+    /// # Ok::<(), nitro::CacheError>(())
+    /// ```
+    ///
+    /// # Returns
+    /// - `Ok(())` once every entry was written
+    /// - `Err(CacheError::Persistence)` if writing or serializing failed
+    pub fn export<W: Write>(&self, mut writer: W, format: Format) -> Result<(), CacheError> {
+        let now = self.clock.peek();
+        let mut entries = Vec::with_capacity(self.size);
+        let mut current = self.tail;
+        while let Some(id) = current {
+            let node = &self.nodes[id];
+            entries.push(ExportedEntry {
+                key: &node.key,
+                value: &node.value,
+                visited: node.is_visited(),
+                age_ms: now.saturating_duration_since(node.written_at).as_millis() as u64,
+            });
+            current = node.prev;
+        }
+
+        match format {
+            Format::Json => serde_json::to_writer(&mut writer, &entries).map_err(export_error)?,
+            Format::Csv => {
+                writeln!(writer, "key,value,visited,age_ms").map_err(export_error)?;
+                for entry in &entries {
+                    writeln!(
+                        writer,
+                        "{},{},{},{}",
+                        csv_field(entry.key)?,
+                        csv_field(entry.value)?,
+                        entry.visited,
+                        entry.age_ms
+                    )
+                    .map_err(export_error)?;
+                }
+            }
+        }
+        writer.flush().map_err(export_error)
+    }
+}
+
+// Renders `value` as one CSV field, quoted if it has to be
+fn csv_field<T: Serialize>(value: &T) -> Result<String, CacheError> {
+    let text = match serde_json::to_value(value).map_err(export_error)? {
+        Value::String(text) => text,
+        other => other.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        Ok(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+        Ok(text)
+    }
+}
+
+impl<K, V, P, S> ConcurrentSieveCache<K, V, P, S>
+where
+    K: Serialize + Eq + Hash + Clone,
+    V: Serialize + Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// See [`SieveCache::export`]. Writers wait while the export is written.
+    pub fn export<W: Write>(&self, writer: W, format: Format) -> Result<(), CacheError> {
+        self.inner.read()?.export(writer, format)
+    }
+}
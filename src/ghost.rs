@@ -0,0 +1,159 @@
+//! Keys of recently evicted entries.
+//!
+//! A miss on a key that was evicted a short while ago would have been a hit
+//! with a somewhat larger cache. Counting those misses tells whether adding
+//! capacity is worth it, at the cost of remembering keys without their values.
+
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+pub(crate) struct GhostList<K> {
+    capacity: usize,
+    // Keys in eviction order; entries whose sequence number no longer
+    // matches `members` were removed or evicted again and are skipped
+    order: VecDeque<(K, u64)>,
+    members: HashMap<K, u64>,
+    sequence: u64,
+}
+
+impl<K: Eq + Hash + Clone> GhostList<K> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        GhostList {
+            capacity,
+            order: VecDeque::new(),
+            members: HashMap::new(),
+            sequence: 0,
+        }
+    }
+
+    /// Remembers `key`, forgetting the oldest key once full.
+    pub(crate) fn push(&mut self, key: K) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.sequence += 1;
+        self.members.insert(key.clone(), self.sequence);
+        self.order.push_back((key, self.sequence));
+        while self.members.len() > self.capacity {
+            self.pop_oldest();
+        }
+        // Bound the stale entries left behind by removals as well
+        if self.order.len() > self.capacity * 2 {
+            self.compact();
+        }
+    }
+
+    /// Forgets `key`, returning whether it was remembered.
+    pub(crate) fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.members.remove(key).is_some()
+    }
+
+    fn pop_oldest(&mut self) {
+        while let Some((key, sequence)) = self.order.pop_front() {
+            if self.members.get(&key) == Some(&sequence) {
+                self.members.remove(&key);
+                return;
+            }
+        }
+    }
+
+    fn compact(&mut self) {
+        let members = &self.members;
+        self.order
+            .retain(|(key, sequence)| members.get(key) == Some(sequence));
+    }
+}
+
+/// Grows or shrinks a cache's capacity within bounds, driven by how often
+/// misses hit the ghost list.
+///
+/// Lookups are counted in windows. At the end of each window, a ghost-hit
+/// rate above the growth threshold adds capacity, and one below the shrink
+/// threshold gives capacity back. Install it with
+/// [`SieveCacheBuilder::adaptive_capacity`](crate::SieveCacheBuilder::adaptive_capacity).
+#[derive(Debug, Clone)]
+pub struct AdaptiveCapacity {
+    min: usize,
+    max: usize,
+    grow_above: f64,
+    shrink_below: f64,
+    window: usize,
+    step: f64,
+    lookups: usize,
+    ghost_hits: usize,
+}
+
+impl AdaptiveCapacity {
+    /// Keeps the capacity between `min` and `max` entries. By default it
+    /// grows when more than 5% of the lookups in a window of 1000 were ghost
+    /// hits, shrinks below 0.1%, and moves by a tenth of the current capacity.
+    pub fn new(min: usize, max: usize) -> Self {
+        AdaptiveCapacity {
+            min: min.max(1),
+            max: max.max(min).max(1),
+            grow_above: 0.05,
+            shrink_below: 0.001,
+            window: 1000,
+            step: 0.1,
+            lookups: 0,
+            ghost_hits: 0,
+        }
+    }
+
+    /// Sets the ghost-hit rates that trigger growing and shrinking.
+    pub fn thresholds(mut self, grow_above: f64, shrink_below: f64) -> Self {
+        self.grow_above = grow_above;
+        self.shrink_below = shrink_below;
+        self
+    }
+
+    /// Sets how many lookups make up a window.
+    pub fn window(mut self, lookups: usize) -> Self {
+        self.window = lookups.max(1);
+        self
+    }
+
+    /// Sets the fraction of the current capacity added or removed at once.
+    pub fn step(mut self, fraction: f64) -> Self {
+        self.step = fraction;
+        self
+    }
+
+    pub fn min(&self) -> usize {
+        self.min
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    // Counts a lookup and returns the new capacity when a window closes with
+    // a rate beyond either threshold
+    pub(crate) fn observe(&mut self, ghost_hit: bool, capacity: usize) -> Option<usize> {
+        self.lookups += 1;
+        if ghost_hit {
+            self.ghost_hits += 1;
+        }
+        if self.lookups < self.window {
+            return None;
+        }
+        let rate = self.ghost_hits as f64 / self.lookups as f64;
+        self.lookups = 0;
+        self.ghost_hits = 0;
+
+        let step = ((capacity as f64 * self.step) as usize).max(1);
+        let target = if rate > self.grow_above {
+            capacity.saturating_add(step).min(self.max)
+        } else if rate < self.shrink_below {
+            capacity.saturating_sub(step).max(self.min)
+        } else {
+            capacity
+        };
+        (target != capacity).then_some(target)
+    }
+}
@@ -0,0 +1,200 @@
+//! Process-wide named caches.
+//!
+//! [`global`] hands out a typed handle to a cache registered under a name,
+//! creating it on first use. The cache behind a handle can be replaced
+//! atomically with [`GlobalCache::replace`]; handles obtained earlier see the
+//! new cache on their next access. [`GlobalCache::rebuild_with`] does the same
+//! for a cache with new settings, carrying the current entries over.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use arc_swap::ArcSwap;
+
+use crate::builder::SieveCacheBuilder;
+use crate::sieve::SieveCache;
+use crate::types::CacheError;
+
+/// Capacity used when [`global`] creates a cache that was not registered yet.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+type Registry = Mutex<HashMap<(String, TypeId), Box<dyn Any + Send + Sync>>>;
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// A cloneable handle to a named, process-wide cache.
+pub struct GlobalCache<K, V> {
+    slot: Arc<ArcSwap<Mutex<SieveCache<K, V>>>>,
+}
+
+impl<K, V> Clone for GlobalCache<K, V> {
+    fn clone(&self) -> Self {
+        GlobalCache {
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl<K, V> GlobalCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Runs `f` with exclusive access to the current cache.
+    pub fn with<R>(&self, f: impl FnOnce(&mut SieveCache<K, V>) -> R) -> Result<R, CacheError> {
+        loop {
+            let current = self.slot.load_full();
+            let mut guard = current
+                .lock()
+                .map_err(|e| CacheError::LockError(e.to_string()))?;
+            // A rebuild may have swapped the cache out while we waited for it
+            if !Arc::ptr_eq(&current, &self.slot.load()) {
+                continue;
+            }
+            return Ok(f(&mut guard));
+        }
+    }
+
+    /// Returns the cache currently installed behind this handle.
+    pub fn load(&self) -> Arc<Mutex<SieveCache<K, V>>> {
+        self.slot.load_full()
+    }
+
+    /// Atomically installs `cache`, returning the one it replaced.
+    ///
+    /// Operations already running against the previous cache finish against it.
+    pub fn replace(&self, cache: SieveCache<K, V>) -> Arc<Mutex<SieveCache<K, V>>> {
+        self.slot.swap(Arc::new(Mutex::new(cache)))
+    }
+
+    /// Builds a new cache from `config`, copies the current entries into it
+    /// and atomically installs it, so settings such as the capacity can change
+    /// without dropping the working set.
+    ///
+    /// Entries are copied oldest first and keep their expiry; if the new
+    /// capacity is smaller, the new cache's policy evicts as it fills. The
+    /// copy is made without holding the current cache's lock, so handles keep
+    /// working meanwhile; only writes that land during the copy are replayed
+    /// while the lock is held for the swap. Run this on a spare thread to keep
+    /// the copy off the caller's path.
+    ///
+    /// Entries the replay drops from the new cache are reported to its
+    /// eviction listener as explicit removals.
+    pub fn rebuild_with(&self, config: SieveCacheBuilder<K, V>) -> Result<(), CacheError> {
+        let current = self.slot.load_full();
+        let (entries, generation, snapshot_at) = {
+            let mut guard = current
+                .lock()
+                .map_err(|e| CacheError::LockError(e.to_string()))?;
+            let now = guard.now();
+            (oldest_first(&guard, None, now)?, guard.generation, now)
+        };
+
+        let mut rebuilt = config.build()?;
+        for (key, value, expires_at) in entries {
+            rebuilt.add_entry(key, value, expires_at)?;
+        }
+
+        let mut guard = current
+            .lock()
+            .map_err(|e| CacheError::LockError(e.to_string()))?;
+        if guard.generation != generation {
+            let now = guard.now();
+            let stale: Vec<K> = rebuilt
+                .cache
+                .keys()
+                .filter(|key| !guard.cache.contains_key(key))
+                .cloned()
+                .collect();
+            for key in stale {
+                rebuilt.delete(&key)?;
+            }
+            for (key, value, expires_at) in oldest_first(&guard, Some(snapshot_at), now)? {
+                rebuilt.add_entry(key, value, expires_at)?;
+            }
+        }
+        self.slot.store(Arc::new(Mutex::new(rebuilt)));
+        Ok(())
+    }
+}
+
+type Migrated<K, V> = Vec<(K, V, Option<Instant>)>;
+
+// The unexpired entries of `cache` from the oldest to the newest, limited to
+// those written at or after `since` if given
+fn oldest_first<K, V>(
+    cache: &SieveCache<K, V>,
+    since: Option<Instant>,
+    now: Instant,
+) -> Result<Migrated<K, V>, CacheError>
+where
+    K: Clone,
+    V: Clone,
+{
+    let mut entries = Vec::new();
+    let mut current = cache.tail;
+    while let Some(id) = current {
+        let node = &cache.nodes[id];
+        let live = node.expires_at.is_none_or(|expires_at| expires_at > now);
+        let fresh = since.is_none_or(|since| node.written_at >= since);
+        if live && fresh {
+            entries.push((node.key.clone(), node.value.clone(), node.expires_at));
+        }
+        current = node.prev;
+    }
+    Ok(entries)
+}
+
+/// Returns the global cache registered under `name` for these key and value
+/// types, creating it with [`DEFAULT_CAPACITY`] if it does not exist yet.
+///
+/// Caches are keyed by name and type, so the same name used with different
+/// `K`/`V` refers to distinct caches.
+pub fn global<K, V>(name: &str) -> GlobalCache<K, V>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    register(name, || {
+        SieveCache::new(DEFAULT_CAPACITY).expect("default capacity is non-zero")
+    })
+}
+
+/// Like [`global`], but creates the cache with `capacity` if it is not
+/// registered yet. An existing cache is returned unchanged.
+pub fn global_with_capacity<K, V>(
+    name: &str,
+    capacity: usize,
+) -> Result<GlobalCache<K, V>, CacheError>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    let cache = SieveCache::new(capacity)?;
+    Ok(register(name, || cache))
+}
+
+fn register<K, V>(name: &str, init: impl FnOnce() -> SieveCache<K, V>) -> GlobalCache<K, V>
+where
+    K: Send + 'static,
+    V: Send + 'static,
+{
+    let registry = REGISTRY.get_or_init(Default::default);
+    // The registry only ever holds fully constructed handles, so a poisoned
+    // lock leaves it in a consistent state
+    let mut registry = registry.lock().unwrap_or_else(|e| e.into_inner());
+    let handle = registry
+        .entry((name.to_string(), TypeId::of::<(K, V)>()))
+        .or_insert_with(|| {
+            Box::new(GlobalCache {
+                slot: Arc::new(ArcSwap::from_pointee(Mutex::new(init()))),
+            })
+        });
+    handle
+        .downcast_ref::<GlobalCache<K, V>>()
+        .expect("registry entries are keyed by type")
+        .clone()
+}
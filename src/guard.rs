@@ -0,0 +1,175 @@
+use std::hash::{BuildHasher, Hash};
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
+
+use crate::eviction::{EvictionPolicy, Sieve};
+use crate::expiry::TimerWheel;
+use crate::node::Node;
+use crate::sieve::{node_deadline, DefaultHashBuilder, SieveCache, TtlFn};
+use crate::types::CacheError;
+
+/// Removes a scoped entry from the cache when dropped.
+///
+/// Returned by [`SieveCache::insert_scoped`]. The guard derefs to the cache,
+/// so it can keep being used while the entry is alive. If the key is evicted
+/// and re-added in the meantime, the newer entry is removed on drop.
+pub struct EntryGuard<'a, K, V, P = Sieve<K>, S = DefaultHashBuilder>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    pub(crate) cache: &'a mut SieveCache<K, V, P, S>,
+    pub(crate) key: Option<K>,
+}
+
+impl<K, V, P, S> EntryGuard<'_, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// The key of the scoped entry.
+    pub fn key(&self) -> &K {
+        self.key
+            .as_ref()
+            .expect("key is only taken on keep or drop")
+    }
+
+    /// Keeps the entry in the cache after the guard goes away.
+    pub fn keep(mut self) -> K {
+        self.key.take().expect("key is only taken on keep or drop")
+    }
+}
+
+impl<K, V, P, S> Deref for EntryGuard<'_, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    type Target = SieveCache<K, V, P, S>;
+
+    fn deref(&self) -> &Self::Target {
+        self.cache
+    }
+}
+
+impl<K, V, P, S> DerefMut for EntryGuard<'_, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.cache
+    }
+}
+
+impl<K, V, P, S> Drop for EntryGuard<'_, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            // Nothing useful can be done with a lock error while dropping
+            let _ = self.cache.delete(&key);
+        }
+    }
+}
+
+/// Shared access to a cached value, returned by [`SieveCache::get_ref`].
+///
+/// The cache stays borrowed while the guard is alive.
+pub struct ValueGuard<'a, K, V> {
+    pub(crate) node: &'a Node<K, V>,
+}
+
+impl<K, V> Deref for ValueGuard<'_, K, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.node.value
+    }
+}
+
+// Passes a value changed through a `ValueMut` on to the cache's backend and
+// operation log
+pub(crate) type WriteHook<'a, K, V> =
+    Box<dyn FnMut(&K, &V, Option<Instant>) -> Result<(), CacheError> + 'a>;
+
+/// Mutable access to a cached value, returned by [`SieveCache::get_mut`].
+///
+/// The cache stays borrowed while the guard is alive. Dropping the guard
+/// marks the entry as written.
+pub struct ValueMut<'a, K: Clone, V> {
+    pub(crate) node: &'a mut Node<K, V>,
+    pub(crate) timers: &'a mut TimerWheel<K>,
+    pub(crate) ttl_fn: Option<&'a TtlFn<K, V>>,
+    pub(crate) expire_after_write: Option<Duration>,
+    pub(crate) expire_after_access: Option<Duration>,
+    pub(crate) now: Instant,
+    pub(crate) write: Option<WriteHook<'a, K, V>>,
+    pub(crate) committed: bool,
+}
+
+impl<K: Clone, V> Deref for ValueMut<'_, K, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.node.value
+    }
+}
+
+impl<K: Clone, V> DerefMut for ValueMut<'_, K, V> {
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.node.value
+    }
+}
+
+impl<K: Clone, V> ValueMut<'_, K, V> {
+    // Marks the entry as written and passes the value on to the backend and
+    // operation log. If they fail, the entry expires right away, so the cache
+    // does not serve a value they never took
+    pub(crate) fn commit(&mut self) -> Result<(), CacheError> {
+        if std::mem::replace(&mut self.committed, true) {
+            return Ok(());
+        }
+        let now = self.now;
+        let node = &mut *self.node;
+        node.set_written_at(now);
+        node.mark_visited();
+        let written = match &mut self.write {
+            Some(write) => write(&node.key, &node.value, node.expires_at),
+            None => Ok(()),
+        };
+        if written.is_err() {
+            node.expires_at = Some(now);
+        } else if let Some(ttl_fn) = self.ttl_fn {
+            node.expires_at = ttl_fn(&node.key, &node.value).and_then(|ttl| now.checked_add(ttl));
+        }
+        let deadline = node_deadline(node, self.expire_after_write, self.expire_after_access);
+        if node.timer != deadline {
+            node.timer = deadline;
+            if let Some(deadline) = deadline {
+                self.timers.schedule(node.key.clone(), deadline);
+            }
+        }
+        written
+    }
+}
+
+impl<K: Clone, V> Drop for ValueMut<'_, K, V> {
+    fn drop(&mut self) {
+        // There is no one left to report a failed write to; the entry has
+        // expired instead
+        let _ = self.commit();
+    }
+}
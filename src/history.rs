@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::types::CacheStats;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Aggregation period for [`SieveCache::stats_history`](crate::SieveCache::stats_history).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsPeriod {
+    /// One bucket per hour, the last 24 hours are retained
+    Hourly,
+    /// One bucket per day, the last 7 days are retained
+    Daily,
+}
+
+/// Cache activity aggregated over one period.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsBucket {
+    pub start: Instant,
+    pub duration: Duration,
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+    pub expirations: usize,
+    /// Largest number of entries observed during the period
+    pub peak_size: usize,
+}
+
+impl StatsBucket {
+    /// Fraction of lookups in this period that were hits, or 0 without lookups.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+// StatsHistory keeps a small ring of closed buckets per period. Counters are
+// not tracked per bucket; each open bucket remembers the cumulative stats at
+// its start and the difference is taken when it closes.
+pub(crate) struct StatsHistory {
+    hourly: Ring,
+    daily: Ring,
+}
+
+struct Ring {
+    period: Duration,
+    retain: usize,
+    open: Option<OpenBucket>,
+    closed: VecDeque<StatsBucket>,
+}
+
+struct OpenBucket {
+    start: Instant,
+    base: CacheStats,
+    peak_size: usize,
+}
+
+impl StatsHistory {
+    pub(crate) fn new() -> Self {
+        StatsHistory {
+            hourly: Ring::new(HOUR, 24),
+            daily: Ring::new(DAY, 7),
+        }
+    }
+
+    /// Records the cache state at `now`, closing buckets whose period ended.
+    pub(crate) fn observe(&mut self, now: Instant, stats: &CacheStats, size: usize) {
+        self.hourly.observe(now, stats, size);
+        self.daily.observe(now, stats, size);
+    }
+
+    /// Returns the retained buckets oldest first, ending with the open one.
+    pub(crate) fn buckets(&self, period: StatsPeriod, stats: &CacheStats) -> Vec<StatsBucket> {
+        let ring = match period {
+            StatsPeriod::Hourly => &self.hourly,
+            StatsPeriod::Daily => &self.daily,
+        };
+        let mut buckets: Vec<StatsBucket> = ring.closed.iter().cloned().collect();
+        if let Some(open) = &ring.open {
+            buckets.push(open.close(ring.period, stats));
+        }
+        buckets
+    }
+}
+
+impl Ring {
+    fn new(period: Duration, retain: usize) -> Self {
+        Ring {
+            period,
+            retain,
+            open: None,
+            closed: VecDeque::with_capacity(retain),
+        }
+    }
+
+    fn observe(&mut self, now: Instant, stats: &CacheStats, size: usize) {
+        match &mut self.open {
+            Some(open) if now.saturating_duration_since(open.start) < self.period => {
+                open.peak_size = open.peak_size.max(size);
+            }
+            Some(open) => {
+                self.closed.push_back(open.close(self.period, stats));
+                if self.closed.len() > self.retain {
+                    self.closed.pop_front();
+                }
+                // Idle periods produce no buckets, the next one starts on the
+                // period boundary that contains `now`
+                let elapsed = now.saturating_duration_since(open.start);
+                let skipped = (elapsed.as_nanos() / self.period.as_nanos()) as u32;
+                *open = OpenBucket {
+                    start: open.start + self.period * skipped,
+                    base: stats.clone(),
+                    peak_size: size,
+                };
+            }
+            None => {
+                self.open = Some(OpenBucket {
+                    start: now,
+                    base: stats.clone(),
+                    peak_size: size,
+                });
+            }
+        }
+    }
+}
+
+impl OpenBucket {
+    fn close(&self, duration: Duration, stats: &CacheStats) -> StatsBucket {
+        StatsBucket {
+            start: self.start,
+            duration,
+            hits: stats.hits - self.base.hits,
+            misses: stats.misses - self.base.misses,
+            evictions: stats.evictions - self.base.evictions,
+            expirations: stats.expirations - self.base.expirations,
+            peak_size: self.peak_size,
+        }
+    }
+}
@@ -1,27 +1,268 @@
-use crate::node::Node;
-use std::sync::{Arc, Mutex};
+use crate::eviction::EvictionPolicy;
+use crate::node::{Node, NodeId};
+use crate::sieve::{EvictionInterceptor, SieveCache};
+use crate::slab::Slab;
+use std::collections::hash_map;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
 
+/// Iterates over a cache's entries from the newest to the oldest, or from
+/// the oldest to the newest with [`rev`](Iterator::rev).
 pub struct CacheIterator<'a, K, V> {
-    pub(crate) current: Option<Arc<Mutex<Node<K, V>>>>,
-    pub(crate) _phantom: std::marker::PhantomData<&'a (K, V)>,
+    pub(crate) nodes: &'a Slab<Node<K, V>>,
+    pub(crate) current: Option<NodeId>,
+    pub(crate) back: Option<NodeId>,
+    // Entries between `current` and `back`, so the ends stop where they meet
+    pub(crate) remaining: usize,
 }
 
 impl<K: Clone, V: Clone> Iterator for CacheIterator<'_, K, V> {
     type Item = (K, V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Take ownership of current value and replace with None
-        let current = self.current.take()?;
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.nodes[self.current?];
+        self.current = node.next;
+        self.remaining -= 1;
+        Some((node.key.clone(), node.value.clone()))
+    }
 
-        // If we can't acquire the lock, end iteration
-        let guard = match current.lock() {
-            Ok(guard) => guard,
-            Err(_) => return None,
-        };
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
 
-        let result = (guard.key.clone(), guard.value.clone());
-        // Store the next node before dropping the guard
-        self.current = guard.next.clone();
-        Some(result)
+impl<K: Clone, V: Clone> DoubleEndedIterator for CacheIterator<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.nodes[self.back?];
+        self.back = node.prev;
+        self.remaining -= 1;
+        Some((node.key.clone(), node.value.clone()))
     }
 }
+
+impl<K: Clone, V: Clone> ExactSizeIterator for CacheIterator<'_, K, V> {}
+
+/// Borrowed keys of a cache in no particular order, see
+/// [`SieveCache::iter_keys`](crate::SieveCache::iter_keys).
+pub struct Keys<'a, K, V> {
+    pub(crate) inner: hash_map::Keys<'a, K, NodeId>,
+    pub(crate) _phantom: PhantomData<&'a V>,
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Keys<'_, K, V> {}
+
+/// Empties a cache in eviction order, see
+/// [`SieveCache::drain`](crate::SieveCache::drain).
+pub struct Drain<'a, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    cache: &'a mut SieveCache<K, V, P, S>,
+    // Set aside for the drain and restored on drop
+    interceptor: Option<EvictionInterceptor<K, V>>,
+}
+
+impl<'a, K, V, P, S> Drain<'a, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    pub(crate) fn new(cache: &'a mut SieveCache<K, V, P, S>) -> Self {
+        let interceptor = cache.eviction_interceptor.take();
+        Drain { cache, interceptor }
+    }
+
+    fn next_key(&mut self) -> Option<K> {
+        let chosen = self
+            .cache
+            .with_entries(|policy, entries| policy.select_victim(entries))
+            .ok()
+            .flatten()
+            .filter(|key| self.cache.cache.contains_key(key));
+        // A policy that comes up empty still leaves the oldest entry
+        chosen.or_else(|| Some(self.cache.nodes[self.cache.tail?].key.clone()))
+    }
+}
+
+impl<K, V, P, S> Iterator for Drain<'_, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.next_key()?;
+        let node = self.cache.take_node(&key).ok()??;
+        Some(node.into_entry())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.cache.len()))
+    }
+}
+
+impl<K, V, P, S> Drop for Drain<'_, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        // Lock errors leave entries behind, so finish with a plain clear
+        for _ in self.by_ref() {}
+        if !self.cache.is_empty() {
+            self.cache.clear_quietly();
+        }
+        self.cache.eviction_interceptor = self.interceptor.take();
+    }
+}
+
+/// Owning iterator over a cache's entries, newest first.
+pub struct IntoIter<K, V> {
+    inner: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V, P, S> IntoIterator for SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    /// Consumes the cache, yielding its entries newest first without
+    /// notifying the eviction listener.
+    fn into_iter(mut self) -> IntoIter<K, V> {
+        let mut entries = Vec::with_capacity(self.size);
+        let mut current = self.head;
+        while let Some(id) = current {
+            // Move the entries out of the slab rather than cloning them
+            let node = self.nodes.remove(id);
+            current = node.next;
+            entries.push(node.into_entry());
+        }
+        IntoIter {
+            inner: entries.into_iter(),
+        }
+    }
+}
+
+impl<'a, K, V, P, S> IntoIterator for &'a SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    type Item = (K, V);
+    type IntoIter = CacheIterator<'a, K, V>;
+
+    fn into_iter(self) -> CacheIterator<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for SieveCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Builds a cache just large enough to hold every entry. Use
+    /// [`SieveCacheBuilder::initial_entries`](crate::SieveCacheBuilder::initial_entries)
+    /// to pick the capacity instead.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let entries: Vec<(K, V)> = iter.into_iter().collect();
+        let mut cache = SieveCache::new(entries.len().max(1)).expect("capacity is at least one");
+        cache.extend(entries);
+        cache
+    }
+}
+
+impl<K, V, P, S> Extend<(K, V)> for SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Adds every entry as [`add`](SieveCache::add) would, evicting as needed.
+    ///
+    /// Entries that `add` rejects, e.g. a value too large for the cache or
+    /// one the write backend fails to store, are skipped; use `add` to see
+    /// why an entry was not added.
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            let _ = self.add(key, value);
+        }
+    }
+}
+
+/// A point-in-time copy of a cache's entries, newest first, see
+/// [`SieveCache::snapshot`](crate::SieveCache::snapshot).
+#[derive(Debug, Clone)]
+pub struct Snapshot<K, V> {
+    pub(crate) inner: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for Snapshot<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Snapshot<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Snapshot<K, V> {}
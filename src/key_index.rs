@@ -0,0 +1,56 @@
+//! An ordered index over the cached keys.
+//!
+//! The cache only asks for `K: Ord` where the index is enabled and queried,
+//! so it keeps the index behind an object-safe trait and maintains it on
+//! inserts and removals without knowing how keys compare. Queries downcast
+//! back to the concrete set to use its range lookups.
+
+use std::any::Any;
+use std::collections::BTreeSet;
+use std::ops::{Bound, RangeBounds};
+
+pub(crate) trait KeyIndex<K>: Send + Sync {
+    fn insert(&mut self, key: K);
+    fn remove(&mut self, key: &K);
+    fn clear(&mut self);
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<K> KeyIndex<K> for BTreeSet<K>
+where
+    K: Ord + Send + Sync + 'static,
+{
+    fn insert(&mut self, key: K) {
+        BTreeSet::insert(self, key);
+    }
+
+    fn remove(&mut self, key: &K) {
+        BTreeSet::remove(self, key);
+    }
+
+    fn clear(&mut self) {
+        BTreeSet::clear(self);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// Whether `range` holds no key at all because of the bounds alone. These are
+// the ranges `BTreeSet::range` panics on: a start after the end, or one key
+// excluded at both ends.
+pub(crate) fn is_empty_range<Q, R>(range: &R) -> bool
+where
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+{
+    match (range.start_bound(), range.end_bound()) {
+        (
+            Bound::Included(start) | Bound::Excluded(start),
+            Bound::Included(end) | Bound::Excluded(end),
+        ) if start > end => true,
+        (Bound::Excluded(start), Bound::Excluded(end)) => start == end,
+        _ => false,
+    }
+}
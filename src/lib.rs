@@ -1,17 +1,117 @@
+#[cfg(feature = "actor")]
+mod actor;
+mod admission;
+mod advisor;
+#[cfg(feature = "tokio")]
+mod async_load;
+mod backend;
+mod builder;
+mod clock;
+mod concurrent;
+mod counter;
+mod entry;
 mod eviction;
+mod expiry;
+#[cfg(feature = "export")]
+mod export;
+mod ghost;
+#[cfg(feature = "global")]
+pub mod global;
+mod guard;
+mod history;
 mod iter;
+mod key_index;
 mod linked_list;
+mod loader;
+#[cfg(feature = "tokio")]
+mod maintenance;
+mod namespaced;
+mod negative;
 mod node;
+#[cfg(feature = "snapshot")]
+mod oplog;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "snapshot")]
+mod persist;
+mod policies;
+mod read_buffer;
+#[cfg(feature = "reaper")]
+mod reaper;
+mod recorder;
+mod rng;
+#[cfg(feature = "serde")]
+mod serialize;
+mod shared;
 mod sieve;
+mod singleflight;
+mod size_tiered;
+mod slab;
+#[cfg(feature = "split")]
+mod split;
+#[cfg(feature = "stream")]
+mod stream;
+mod sync;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod tiered;
 mod types;
+mod write_buffer;
 
-pub use iter::CacheIterator;
-pub use sieve::SieveCache;
-pub use types::{CacheError, CacheStats};
+#[cfg(feature = "actor")]
+pub use actor::CacheHandle;
+pub use admission::TinyLfu;
+pub use backend::{WriteBackend, WriteBehind};
+pub use builder::SieveCacheBuilder;
+pub use clock::{Clock, ClockJumpPolicy, MockClock, SystemClock, WallClock};
+pub use concurrent::ConcurrentSieveCache;
+pub use counter::Counter;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use eviction::{EntryList, EvictionPolicy, Position, SegmentedSieve, Sieve};
+#[cfg(feature = "export")]
+pub use export::Format;
+pub use ghost::AdaptiveCapacity;
+#[cfg(feature = "global")]
+pub use global::global;
+pub use guard::{EntryGuard, ValueGuard, ValueMut};
+pub use history::{StatsBucket, StatsPeriod};
+pub use iter::{CacheIterator, Drain, IntoIter, Keys, Snapshot};
+pub use loader::{CacheLoader, TryCacheLoader};
+#[cfg(feature = "tokio")]
+pub use maintenance::MaintenanceGuard;
+pub use namespaced::{NamespaceStats, NamespacedCache};
+#[cfg(feature = "snapshot")]
+pub use oplog::{LogRecord, OperationLog};
+#[cfg(feature = "snapshot")]
+pub use persist::AutoSnapshot;
+pub use policies::{ClockPolicy, FifoPolicy, LfuPolicy, LruPolicy, RandomPolicy, TwoQueuePolicy};
+#[cfg(feature = "reaper")]
+pub use reaper::{start_reaper, ReaperHandle};
+pub use recorder::StatsRecorder;
+pub use shared::SharedSieveCache;
+pub use sieve::{
+    DefaultHashBuilder, EvictionInterceptor, EvictionListener, KeyClassifier, SieveCache, SizeFn,
+    TtlFn,
+};
+pub use size_tiered::{SizeClass, SizeTieredCache};
+#[cfg(feature = "split")]
+pub use split::{ReadHandle, WriteHandle};
+#[cfg(feature = "stream")]
+pub use stream::EntryStream;
+pub use tiered::{Cache, TieredCache};
+pub use types::{
+    CacheError, CacheStats, Cached, ClassStats, ConflictPolicy, InsertResult, Priority,
+    RemovalCause, StatsSnapshot, TierStats,
+};
+pub use write_buffer::{OverflowPolicy, WriteBuffer, WriteBufferStats};
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
     #[test]
     fn test_new_cache() {
@@ -115,4 +215,1985 @@ mod tests {
         assert_eq!(cache.get(&1).unwrap(), Some(String::from("one")));
         assert_eq!(cache.get(&2).unwrap(), Some(String::from("two")));
     }
+
+    #[test]
+    fn test_add_with_ttl() {
+        let clock = MockClock::new();
+        let mut cache: SieveCache<String, i32> = SieveCache::with_clock(3, clock.clone()).unwrap();
+
+        assert!(!cache
+            .add_with_ttl(String::from("short"), 1, Duration::from_millis(20))
+            .unwrap());
+        assert!(!cache
+            .add_with_ttl(String::from("long"), 2, Duration::from_secs(60))
+            .unwrap());
+        assert_eq!(cache.get(&String::from("short")).unwrap(), Some(1));
+
+        clock.advance(Duration::from_millis(20));
+
+        // Expired entries are removed lazily and count as misses
+        let misses = cache.stats().misses;
+        assert_eq!(cache.get(&String::from("short")).unwrap(), None);
+        assert_eq!(cache.stats().misses, misses + 1);
+        cache.run_maintenance().unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&String::from("long")).unwrap(), Some(2));
+
+        // Re-adding an expired key counts as a fresh insert
+        cache
+            .add_with_ttl(String::from("long"), 3, Duration::ZERO)
+            .unwrap();
+        assert!(!cache.add(String::from("long"), 4).unwrap());
+        assert_eq!(cache.get(&String::from("long")).unwrap(), Some(4));
+    }
+
+    #[test]
+    fn test_expire_after_write_and_access() {
+        let clock = MockClock::new();
+        let mut cache: SieveCache<String, i32> = SieveCache::with_clock(3, clock.clone()).unwrap();
+        cache.set_expire_after_access(Some(Duration::from_millis(60)));
+
+        cache.add(String::from("idle"), 1).unwrap();
+        cache.add(String::from("busy"), 2).unwrap();
+        for _ in 0..3 {
+            clock.advance(Duration::from_millis(25));
+            assert_eq!(cache.get(&String::from("busy")).unwrap(), Some(2));
+        }
+        assert_eq!(cache.get(&String::from("idle")).unwrap(), None);
+
+        // Write expiry ignores reads
+        cache.set_expire_after_access(None);
+        cache.set_expire_after_write(Some(Duration::from_millis(60)));
+        clock.advance(Duration::from_millis(60));
+        assert_eq!(cache.get(&String::from("busy")).unwrap(), None);
+
+        cache.run_maintenance().unwrap();
+        let stats = cache.get_stats();
+        assert_eq!(stats.expirations, 2);
+        assert_eq!(stats.evictions, 0);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_remove_expired() {
+        let mut cache: SieveCache<String, i32> = SieveCache::new(3).unwrap();
+
+        cache
+            .add_with_ttl(String::from("key1"), 1, Duration::ZERO)
+            .unwrap();
+        cache.add(String::from("key2"), 2).unwrap();
+        cache
+            .add_with_ttl(String::from("key3"), 3, Duration::ZERO)
+            .unwrap();
+
+        assert_eq!(cache.remove_expired().unwrap(), 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get_stats().expirations, 2);
+        assert_eq!(cache.iter().map(|(k, _)| k).collect::<Vec<_>>(), ["key2"]);
+    }
+
+    #[test]
+    fn test_insert_scoped() {
+        let mut cache: SieveCache<String, i32> = SieveCache::new(3).unwrap();
+
+        {
+            let guard = cache.insert_scoped(String::from("temp"), 1).unwrap();
+            assert_eq!(guard.get(&String::from("temp")).unwrap(), Some(1));
+        }
+        assert_eq!(cache.get(&String::from("temp")).unwrap(), None);
+
+        let guard = cache.insert_scoped(String::from("kept"), 2).unwrap();
+        guard.keep();
+        assert_eq!(cache.get(&String::from("kept")).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_eviction_interceptor_veto() {
+        let mut cache: SieveCache<String, i32> = SieveCache::new(2).unwrap();
+        cache.set_eviction_interceptor(1, |key: &String, _: &i32| key != "busy");
+
+        cache.add(String::from("busy"), 1).unwrap();
+        cache.add(String::from("key2"), 2).unwrap();
+
+        // "busy" is the SIEVE victim but is vetoed, so the next candidate goes
+        cache.add(String::from("key3"), 3).unwrap();
+        assert!(cache.get(&String::from("busy")).unwrap().is_some());
+        assert!(cache.get(&String::from("key2")).unwrap().is_none());
+
+        // Once the veto budget is spent the victim is evicted anyway
+        cache.set_eviction_interceptor(0, |_: &String, _: &i32| false);
+        cache.add(String::from("key4"), 4).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get_stats().evictions, 2);
+    }
+
+    #[test]
+    fn test_timer_wheel_cascades() {
+        use crate::expiry::TimerWheel;
+        use std::time::Instant;
+
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut wheel = TimerWheel::new(start, Duration::from_millis(1));
+
+        // Spread deadlines across several wheel levels
+        wheel.schedule("near", at(5));
+        wheel.schedule("mid", at(300));
+        wheel.schedule("far", at(70_000));
+
+        assert!(wheel.advance(at(4)).is_empty());
+        assert_eq!(wheel.advance(at(5)), [("near", at(5))]);
+        assert!(wheel.advance(at(299)).is_empty());
+        assert_eq!(wheel.advance(at(1_000)), [("mid", at(300))]);
+        assert!(wheel.advance(at(69_999)).is_empty());
+        assert_eq!(wheel.advance(at(70_000)), [("far", at(70_000))]);
+    }
+
+    #[test]
+    fn test_stats_history_buckets() {
+        let clock = MockClock::new();
+        let mut cache: SieveCache<i32, i32> = SieveCache::with_clock(2, clock.clone()).unwrap();
+
+        cache.add(1, 1).unwrap();
+        cache.add(2, 2).unwrap();
+        cache.get(&1).unwrap();
+        cache.get(&3).unwrap();
+
+        clock.advance(Duration::from_secs(60 * 60));
+        cache.add(3, 3).unwrap();
+        cache.get(&3).unwrap();
+
+        let hourly = cache.stats_history(StatsPeriod::Hourly);
+        assert_eq!(hourly.len(), 2);
+        assert_eq!((hourly[0].hits, hourly[0].misses), (1, 1));
+        assert_eq!(hourly[0].peak_size, 2);
+        assert_eq!(hourly[0].hit_rate(), 0.5);
+        assert_eq!((hourly[1].hits, hourly[1].evictions), (1, 1));
+
+        let daily = cache.stats_history(StatsPeriod::Daily);
+        assert_eq!(daily.len(), 1);
+        assert_eq!(
+            (daily[0].hits, daily[0].misses, daily[0].evictions),
+            (2, 1, 1)
+        );
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut cache: SieveCache<i32, i32> = SieveCache::new(100).unwrap();
+        for i in 0..100 {
+            cache.add(i, i * 10).unwrap();
+        }
+        cache.delete(&42).unwrap();
+
+        let sample = cache.sample(10).unwrap();
+        assert_eq!(sample.len(), 10);
+        let mut keys: Vec<i32> = sample.iter().map(|(k, _)| *k).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), 10);
+        assert!(sample.iter().all(|(k, v)| *k != 42 && *v == k * 10));
+        assert_eq!(cache.sample(500).unwrap().len(), 99);
+
+        // Zero-weight entries are never drawn
+        let weighted = cache
+            .sample_weighted(20, 1.0, |k, _| if k % 2 == 0 { 1.0 } else { 0.0 })
+            .unwrap();
+        assert!(!weighted.is_empty());
+        assert!(weighted.iter().all(|(k, _)| k % 2 == 0));
+        assert_eq!(cache.get_stats().hits, 0);
+    }
+
+    #[test]
+    fn test_entry_api() {
+        let mut cache: SieveCache<String, Vec<i32>> = SieveCache::new(2).unwrap();
+
+        let value = cache
+            .entry(String::from("list"))
+            .unwrap()
+            .and_modify(|v| v.push(2))
+            .unwrap()
+            .or_insert(vec![1])
+            .unwrap();
+        assert_eq!(value, vec![1]);
+
+        let value = cache
+            .entry(String::from("list"))
+            .unwrap()
+            .and_modify(|v| v.push(2))
+            .unwrap()
+            .or_insert_with(|| unreachable!())
+            .unwrap();
+        assert_eq!(value, vec![1, 2]);
+
+        match cache.entry(String::from("list")).unwrap() {
+            Entry::Occupied(entry) => assert_eq!(entry.remove().unwrap(), vec![1, 2]),
+            Entry::Vacant(_) => panic!("entry should be occupied"),
+        }
+        assert!(cache.is_empty());
+        assert_eq!((cache.get_stats().hits, cache.get_stats().misses), (2, 1));
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut cache: SieveCache<String, i32> = SieveCache::new(2).unwrap();
+        let mut computed = 0;
+
+        for _ in 0..3 {
+            let value = cache
+                .get_or_insert_with(String::from("key1"), || {
+                    computed += 1;
+                    42
+                })
+                .unwrap();
+            assert_eq!(value, 42);
+        }
+
+        assert_eq!(computed, 1);
+        assert_eq!(cache.get_stats().misses, 1);
+        assert_eq!(cache.get_stats().hits, 2);
+    }
+
+    #[test]
+    fn test_clock_jump_policies() {
+        let clock = MockClock::new();
+        let mut cache: SieveCache<i32, i32> = SieveCache::with_clock(4, clock.clone()).unwrap();
+        cache.add_with_ttl(1, 1, Duration::from_secs(10)).unwrap();
+
+        // A backwards step does not buy the entry extra time
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.get(&1).unwrap(), Some(1));
+        clock.rewind(Duration::from_secs(3));
+        assert_eq!(cache.get(&1).unwrap(), Some(1));
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.get(&1).unwrap(), None);
+        assert_eq!(cache.stats().clock_anomalies, 1);
+
+        // Large forward jumps can be absorbed
+        cache.set_clock_jump_policy(ClockJumpPolicy::AbsorbForwardJumps(Duration::from_secs(60)));
+        cache.add_with_ttl(2, 2, Duration::from_secs(10)).unwrap();
+        clock.advance(Duration::from_secs(60 * 60));
+        assert_eq!(cache.get(&2).unwrap(), Some(2));
+        assert_eq!(cache.stats().clock_anomalies, 2);
+    }
+
+    #[test]
+    fn test_max_idle_purge() {
+        let clock = MockClock::new();
+        let mut cache: SieveCache<i32, i32> = SieveCache::with_clock(4, clock.clone()).unwrap();
+        cache.set_max_idle(Some(Duration::from_secs(60)));
+        cache.add(1, 1).unwrap();
+        cache.add(2, 2).unwrap();
+
+        // Regular use keeps the cache alive
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(cache.get(&1).unwrap(), Some(1));
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(cache.remove_expired().unwrap(), 0);
+        assert_eq!(cache.len(), 2);
+
+        // Maintenance does not count as activity
+        clock.advance(Duration::from_secs(15));
+        assert_eq!(cache.remove_expired().unwrap(), 2);
+        assert!(cache.is_empty());
+        assert_eq!(cache.get_stats().expirations, 2);
+
+        // Otherwise the next operation purges
+        cache.add(3, 3).unwrap();
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(cache.get(&3).unwrap(), None);
+    }
+
+    #[test]
+    fn test_lru_policy() {
+        fn survivors<P: EvictionPolicy<i32>>(mut cache: SieveCache<i32, i32, P>) -> Vec<i32> {
+            for key in 0..3 {
+                cache.add(key, key).unwrap();
+            }
+            cache.get(&0).unwrap();
+            cache.add(3, 3).unwrap();
+            cache.get(&2).unwrap();
+            cache.add(4, 4).unwrap();
+            let mut keys: Vec<i32> = cache.iter().map(|(key, _)| key).collect();
+            keys.sort();
+            keys
+        }
+
+        // LRU evicts 0, read before 2, while the SIEVE hand has already
+        // passed 0 and evicts 3, which was never read
+        assert_eq!(
+            survivors(SieveCache::with_policy(3, LruPolicy::new()).unwrap()),
+            [2, 3, 4]
+        );
+        assert_eq!(survivors(SieveCache::new(3).unwrap()), [0, 2, 4]);
+    }
+
+    #[test]
+    fn test_fifo_and_clock_policies() {
+        fn evictions<P: EvictionPolicy<i32>>(policy: P) -> Vec<i32> {
+            let evicted = Arc::new(Mutex::new(Vec::new()));
+            let sink = evicted.clone();
+            let mut cache = SieveCache::builder()
+                .capacity(3)
+                .policy(policy)
+                .eviction_listener(move |key, _, _| sink.lock().unwrap().push(key))
+                .build()
+                .unwrap();
+            for key in 0..3 {
+                cache.add(key, key).unwrap();
+            }
+            cache.get(&0).unwrap();
+            for key in 3..6 {
+                cache.add(key, key).unwrap();
+            }
+            let evicted = evicted.lock().unwrap().clone();
+            evicted
+        }
+
+        // FIFO ignores the read, CLOCK requeues 0 behind 1 and 2, and SIEVE
+        // leaves it at the tail while the hand moves on to 3
+        assert_eq!(evictions(FifoPolicy::new()), [0, 1, 2]);
+        assert_eq!(evictions(ClockPolicy::new()), [1, 2, 0]);
+        assert_eq!(evictions(Sieve::new()), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_lfu_policy() {
+        let mut cache = SieveCache::with_policy(3, LfuPolicy::new()).unwrap();
+        cache.add(String::from("popular"), 1).unwrap();
+        for _ in 0..5 {
+            cache.get(&String::from("popular")).unwrap();
+        }
+        cache.add(String::from("once"), 2).unwrap();
+        cache.get(&String::from("once")).unwrap();
+        cache.add(String::from("never"), 3).unwrap();
+        assert_eq!(cache.policy().frequency(&String::from("popular")), Some(6));
+
+        // Frequency wins over recency
+        cache.add(String::from("new"), 4).unwrap();
+        assert_eq!(cache.get(&String::from("never")).unwrap(), None);
+        cache.add(String::from("newer"), 5).unwrap();
+        assert_eq!(cache.get(&String::from("new")).unwrap(), None);
+        assert_eq!(cache.get(&String::from("popular")).unwrap(), Some(1));
+        assert_eq!(cache.policy().frequency(&String::from("never")), None);
+    }
+
+    #[test]
+    fn test_tiny_lfu_admission() {
+        let mut cache: SieveCache<String, i32> = SieveCache::builder()
+            .capacity(2)
+            .admission_filter(TinyLfu::new(2))
+            .build()
+            .unwrap();
+        cache.add(String::from("hot1"), 1).unwrap();
+        cache.add(String::from("hot2"), 2).unwrap();
+        for _ in 0..3 {
+            cache.get(&String::from("hot1")).unwrap();
+            cache.get(&String::from("hot2")).unwrap();
+        }
+
+        // A scan of one-off keys does not get past the filter
+        for i in 0..10 {
+            cache.add(format!("scan{}", i), i).unwrap();
+        }
+        assert_eq!(cache.get(&String::from("hot1")).unwrap(), Some(1));
+        assert_eq!(cache.get(&String::from("hot2")).unwrap(), Some(2));
+        assert_eq!(cache.get_stats().admission_rejections, 10);
+
+        // A key that keeps being requested eventually gets in
+        for _ in 0..10 {
+            if cache.get(&String::from("rising")).unwrap().is_none() {
+                cache.add(String::from("rising"), 3).unwrap();
+            }
+        }
+        assert_eq!(cache.get(&String::from("rising")).unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_two_queue_policy() {
+        let mut cache = SieveCache::with_policy(4, TwoQueuePolicy::new(4)).unwrap();
+        for key in 0..4 {
+            cache.add(key, key).unwrap();
+        }
+        // Hits while on probation do not promote
+        cache.get(&0).unwrap();
+        cache.add(4, 4).unwrap();
+        assert_eq!(cache.get(&0).unwrap(), None);
+
+        // Coming back while remembered in A1out lands in the main queue
+        cache.add(0, 0).unwrap();
+        assert!(cache.policy().is_main(&0));
+        for key in 5..9 {
+            cache.add(key, key).unwrap();
+        }
+        assert_eq!(cache.get(&0).unwrap(), Some(0));
+        assert!(!cache.policy().is_main(&5));
+    }
+
+    #[test]
+    fn test_recommended_capacity() {
+        let mut cache: SieveCache<u32, u32> = SieveCache::builder()
+            .capacity(4)
+            .working_set_sampling(1.0)
+            .build()
+            .unwrap();
+        assert_eq!(cache.recommended_capacity(0.5), None);
+
+        // Cycling through 10 keys misses on every lookup with only 4 slots
+        for _ in 0..20 {
+            for key in 0..10 {
+                if cache.get(&key).unwrap().is_none() {
+                    cache.add(key, key).unwrap();
+                }
+            }
+        }
+        assert_eq!(cache.get_stats().hits, 0);
+        assert_eq!(cache.recommended_capacity(0.9), Some(10));
+        assert_eq!(cache.predicted_hit_rate(10), Some(0.95));
+        assert_eq!(cache.predicted_hit_rate(9), Some(0.0));
+        assert_eq!(cache.recommended_capacity(0.99), None);
+    }
+
+    #[test]
+    fn test_segmented_sieve() {
+        let mut cache = SieveCache::with_policy(4, SegmentedSieve::new(4)).unwrap();
+        for key in 0..4 {
+            cache.add(key, key).unwrap();
+        }
+        cache.get(&0).unwrap();
+        cache.get(&1).unwrap();
+
+        // The first eviction promotes the visited entries and evicts from
+        // probation; a one-time scan never reaches the protected segment
+        for key in 100..200 {
+            cache.add(key, key).unwrap();
+        }
+        assert!(cache.policy().is_protected(&0));
+        assert!(cache.policy().is_protected(&1));
+        assert_eq!(cache.policy().protected_len(), 2);
+        let mut keys: Vec<_> = cache.iter().map(|(key, _)| key).collect();
+        keys.sort();
+        assert_eq!(keys, vec![0, 1, 198, 199]);
+
+        // Removing a protected entry takes it out of its segment
+        cache.delete(&0).unwrap();
+        assert!(!cache.policy().is_protected(&0));
+        assert_eq!(cache.policy().protected_len(), 1);
+
+        // Once everything is protected, the stalest entry is demoted and
+        // evicted rather than failing the insert
+        let mut cache = SieveCache::with_policy(2, SegmentedSieve::new(2)).unwrap();
+        cache.add(1, 1).unwrap();
+        cache.add(2, 2).unwrap();
+        cache.get(&1).unwrap();
+        cache.get(&2).unwrap();
+        cache.add(3, 3).unwrap();
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_random_policy() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = SieveCache::builder()
+            .capacity(8)
+            .policy(RandomPolicy::new())
+            .eviction_listener(move |key, _, _| sink.lock().unwrap().push(key))
+            .build()
+            .unwrap();
+        // Key 0 is never a victim while the interceptor has vetoes left
+        cache.set_eviction_interceptor(usize::MAX, |key, _| *key != 0);
+        for key in 0..200 {
+            cache.add(key, key).unwrap();
+        }
+        assert_eq!(cache.len(), 8);
+        assert_eq!(cache.get(&0).unwrap(), Some(0));
+
+        let evicted = evicted.lock().unwrap();
+        assert_eq!(evicted.len(), 192);
+        // Unlike FIFO, some older keys outlive newer ones
+        assert!(evicted.windows(2).any(|pair| pair[0] > pair[1]));
+    }
+
+    #[test]
+    fn test_ghost_hits() {
+        let mut cache: SieveCache<u32, u32> = SieveCache::builder()
+            .capacity(2)
+            .ghost_capacity(2)
+            .build()
+            .unwrap();
+        for key in 0..5 {
+            cache.add(key, key).unwrap();
+        }
+        // 0, 1 and 2 were evicted; only the last two are remembered
+        assert_eq!(cache.get(&0).unwrap(), None);
+        assert_eq!(cache.get(&1).unwrap(), None);
+        assert_eq!(cache.get(&2).unwrap(), None);
+        assert_eq!(cache.get(&9).unwrap(), None);
+        cache.run_maintenance().unwrap();
+        assert_eq!(cache.get_stats().misses, 4);
+        assert_eq!(cache.get_stats().ghost_hits, 2);
+
+        // Each ghost is counted once, and re-inserting a key forgets it
+        assert_eq!(cache.get(&1).unwrap(), None);
+        cache.add(2, 2).unwrap();
+        cache.delete(&2).unwrap();
+        assert_eq!(cache.get(&2).unwrap(), None);
+        cache.run_maintenance().unwrap();
+        assert_eq!(cache.get_stats().ghost_hits, 2);
+    }
+
+    #[test]
+    fn test_borrowed_keys() {
+        let mut cache = SieveCache::new(3).unwrap();
+        for key in ["a", "b", "c"] {
+            cache.add(key.to_string(), key.len()).unwrap();
+        }
+        let keys = cache.iter_keys();
+        assert_eq!(keys.len(), 3);
+        let mut keys: Vec<&String> = keys.collect();
+        keys.sort();
+        assert_eq!(keys, ["a", "b", "c"]);
+
+        let mut seen = Vec::new();
+        cache
+            .for_each_entry(|key, value| seen.push(format!("{}={}", key, value)))
+            .unwrap();
+        assert_eq!(seen, ["c=1", "b=1", "a=1"]);
+    }
+
+    #[test]
+    fn test_adaptive_capacity() {
+        let mut cache: SieveCache<u32, u32> = SieveCache::builder()
+            .capacity(2)
+            .adaptive_capacity(
+                AdaptiveCapacity::new(2, 6)
+                    .window(10)
+                    .step(0.5)
+                    .thresholds(0.05, 0.0),
+            )
+            .build()
+            .unwrap();
+
+        // Cycling through 5 keys keeps missing on keys evicted moments ago
+        for _ in 0..10 {
+            for key in 0..5 {
+                if cache.get(&key).unwrap().is_none() {
+                    cache.add(key, key).unwrap();
+                }
+            }
+        }
+        assert!(cache.capacity() >= 5);
+        assert!(cache.capacity() <= 6);
+        assert!(cache.get_stats().ghost_hits > 0);
+
+        // Once everything hits, the spare capacity is given back
+        let adaptive = AdaptiveCapacity::new(2, 6).window(10).step(0.5);
+        cache.set_adaptive_capacity(Some(adaptive)).unwrap();
+        for _ in 0..20 {
+            cache.get(&0).unwrap();
+        }
+        cache.run_maintenance().unwrap();
+        assert!(cache.capacity() < 5);
+        assert!(cache.len() <= cache.capacity());
+    }
+
+    #[test]
+    fn test_eviction_efficiency_metrics() {
+        let mut cache = SieveCache::new(4).unwrap();
+        for key in 0..4 {
+            cache.add(key, key).unwrap();
+        }
+        cache.get(&0).unwrap();
+        cache.get(&1).unwrap();
+
+        // The hand clears 0 and 1, then evicts 2 two steps in
+        cache.add(4, 4).unwrap();
+        let stats = cache.get_stats();
+        assert_eq!(stats.hand_steps, 2);
+        assert_eq!(stats.visited_resets, 2);
+        assert_eq!(stats.first_probe_victims, 0);
+
+        // Then 3 is evicted right where the hand rests
+        cache.add(5, 5).unwrap();
+        let stats = cache.get_stats();
+        assert_eq!(stats.first_probe_victims, 1);
+        assert_eq!(stats.avg_hand_travel(), 1.0);
+        assert_eq!(stats.first_probe_rate(), 0.5);
+        assert_eq!(stats.visited_reset_rate(), 1.0);
+    }
+
+    #[test]
+    fn test_set_capacity() {
+        let mut cache = SieveCache::new(4).unwrap();
+        for key in 0..4 {
+            cache.add(key, key * 10).unwrap();
+        }
+        cache.get(&0).unwrap();
+
+        let evicted = cache.set_capacity(2).unwrap();
+        assert_eq!(evicted, vec![(1, 10), (2, 20)]);
+        assert_eq!(cache.capacity(), 2);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get_stats().evictions, 2);
+
+        assert!(cache.set_capacity(8).unwrap().is_empty());
+        for key in 10..16 {
+            cache.add(key, key).unwrap();
+        }
+        assert_eq!(cache.len(), 8);
+        assert!(cache.set_capacity(0).is_err());
+        assert!(cache.set_capacity(usize::MAX).is_err());
+        assert_eq!(cache.capacity(), 8);
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut cache = SieveCache::new(10).unwrap();
+        for user in ["alice", "bob"] {
+            for page in 0..3 {
+                cache.add(format!("{}/{}", user, page), page).unwrap();
+            }
+        }
+        let removed = cache.retain(|key, _| !key.starts_with("alice/")).unwrap();
+        assert_eq!(removed, 3);
+        assert_eq!(cache.len(), 3);
+        assert!(cache.iter().all(|(key, _)| key.starts_with("bob/")));
+
+        assert_eq!(cache.retain(|_, page| *page > 0).unwrap(), 1);
+        assert_eq!(cache.retain(|_, _| false).unwrap(), 2);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_drain() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = SieveCache::builder()
+            .capacity(4)
+            .eviction_listener(move |key, _, _| sink.lock().unwrap().push(key))
+            .build()
+            .unwrap();
+        for key in 0..4 {
+            cache.add(key, key * 10).unwrap();
+        }
+        cache.get(&0).unwrap();
+
+        // SIEVE spares the visited entry until the hand comes around again
+        let drained: Vec<_> = cache.drain().collect();
+        assert_eq!(drained, vec![(1, 10), (2, 20), (3, 30), (0, 0)]);
+        assert!(cache.is_empty());
+        assert!(evicted.lock().unwrap().is_empty());
+
+        // Dropping the iterator early still empties the cache
+        for key in 0..4 {
+            cache.add(key, key).unwrap();
+        }
+        assert_eq!(cache.drain().next(), Some((0, 0)));
+        assert!(cache.is_empty());
+        cache.add(9, 9).unwrap();
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_into_iterator() {
+        let mut cache = SieveCache::new(3).unwrap();
+        for key in 0..3 {
+            cache.add(key, vec![key; 4]).unwrap();
+        }
+
+        let mut total = 0;
+        for (key, value) in &cache {
+            total += key + value.len() as i32;
+        }
+        assert_eq!(total, 15);
+
+        let entries: Vec<_> = cache.into_iter().collect();
+        assert_eq!(
+            entries,
+            vec![(2, vec![2; 4]), (1, vec![1; 4]), (0, vec![0; 4])]
+        );
+    }
+
+    #[test]
+    fn test_iterate_from_both_ends() {
+        let mut cache = SieveCache::new(5).unwrap();
+        for key in 0..5 {
+            cache.add(key, key * 10).unwrap();
+        }
+        let iter = cache.iter();
+        assert_eq!(iter.len(), 5);
+
+        let newest: Vec<_> = cache.iter().take(2).map(|(key, _)| key).collect();
+        assert_eq!(newest, [4, 3]);
+        let oldest: Vec<_> = cache.iter().rev().take(2).map(|(key, _)| key).collect();
+        assert_eq!(oldest, [0, 1]);
+
+        // Both ends stop where they meet
+        let mut iter = cache.iter();
+        assert_eq!(iter.next(), Some((4, 40)));
+        assert_eq!(iter.next_back(), Some((0, 0)));
+        assert_eq!(iter.len(), 3);
+        let middle: Vec<_> = iter.map(|(key, _)| key).collect();
+        assert_eq!(middle, [3, 2, 1]);
+    }
+
+    #[test]
+    fn test_iter_by_eviction_order() {
+        let mut cache = SieveCache::new(5).unwrap();
+        for key in 0..5 {
+            cache.add(key, key).unwrap();
+        }
+        cache.get(&0).unwrap();
+        cache.get(&3).unwrap();
+
+        let order = |cache: &SieveCache<i32, i32>| -> Vec<i32> {
+            cache
+                .iter_by_eviction_order()
+                .unwrap()
+                .map(|(key, _)| key)
+                .collect()
+        };
+        assert_eq!(order(&cache), [1, 2, 4, 0, 3]);
+
+        // The preview matches what actually happens
+        cache.add(5, 5).unwrap();
+        assert_eq!(cache.get(&1).unwrap(), None);
+        assert_eq!(order(&cache), [2, 4, 5, 0, 3]);
+        cache.add(6, 6).unwrap();
+        assert_eq!(cache.get(&2).unwrap(), None);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut cache = SieveCache::new(3).unwrap();
+        for key in 0..3 {
+            cache.add(key, key).unwrap();
+        }
+        let snapshot = cache.snapshot().unwrap();
+        assert_eq!(snapshot.len(), 3);
+
+        // Changes after the snapshot do not show up in it
+        cache.purge();
+        cache.add(9, 9).unwrap();
+        assert_eq!(snapshot.collect::<Vec<_>>(), [(2, 2), (1, 1), (0, 0)]);
+        assert_eq!(cache.snapshot().unwrap().next_back(), Some((9, 9)));
+    }
+
+    #[test]
+    fn test_peek() {
+        let clock = MockClock::new();
+        let mut cache = SieveCache::with_clock(2, clock.clone()).unwrap();
+        cache.add("a", 1).unwrap();
+        cache.add("b", 2).unwrap();
+
+        assert_eq!(cache.peek(&"a").unwrap(), Some(1));
+        assert_eq!(cache.peek(&"missing").unwrap(), None);
+        assert_eq!(cache.get_stats().hits, 0);
+        assert_eq!(cache.get_stats().misses, 0);
+
+        // The peeked entry was not marked visited, so it is still evicted first
+        cache.add("c", 3).unwrap();
+        assert_eq!(cache.peek(&"a").unwrap(), None);
+
+        cache.add_with_ttl("d", 4, Duration::from_secs(1)).unwrap();
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cache.peek(&"d").unwrap(), None);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_contains_key() {
+        let mut cache = SieveCache::new(2).unwrap();
+        cache.add("a", vec![0u8; 1024]).unwrap();
+        cache.add("b", vec![1u8; 1024]).unwrap();
+        assert!(cache.contains_key(&"a"));
+        assert!(!cache.contains_key(&"z"));
+        assert_eq!(cache.get_stats().misses, 0);
+
+        // Membership checks do not protect an entry from eviction
+        cache.add("c", Vec::new()).unwrap();
+        assert!(!cache.contains_key(&"a"));
+    }
+
+    #[test]
+    fn test_modify_in_place() {
+        let clock = MockClock::new();
+        let mut cache = SieveCache::with_clock(2, clock.clone()).unwrap();
+        cache.set_expire_after_write(Some(Duration::from_secs(10)));
+        cache.add("log", vec![1]).unwrap();
+
+        assert!(cache.modify(&"log", |lines| lines.push(2)).unwrap());
+        assert!(!cache.modify(&"missing", |lines| lines.push(0)).unwrap());
+        cache.get_mut(&"log").unwrap().unwrap().push(3);
+        assert_eq!(cache.peek(&"log").unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(cache.get_stats().hits, 2);
+        assert_eq!(cache.get_stats().misses, 1);
+
+        // A modification counts as a write for expire-after-write
+        clock.advance(Duration::from_secs(8));
+        cache.modify(&"log", |lines| lines.clear()).unwrap();
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(cache.get(&"log").unwrap(), Some(vec![]));
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(cache.remove_expired().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_remove_returns_value() {
+        let clock = MockClock::new();
+        let mut cache = SieveCache::with_clock(4, clock.clone()).unwrap();
+        cache.add("buffer", vec![0u8; 16]).unwrap();
+        cache
+            .add_with_ttl("stale", vec![], Duration::from_secs(1))
+            .unwrap();
+
+        assert_eq!(cache.remove(&"buffer").unwrap(), Some(vec![0u8; 16]));
+        assert_eq!(cache.remove(&"buffer").unwrap(), None);
+        assert_eq!(cache.len(), 1);
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cache.remove(&"stale").unwrap(), None);
+        assert!(cache.is_empty());
+        assert_eq!(cache.get_stats().expirations, 1);
+    }
+
+    #[test]
+    fn test_put_returns_displaced_entry() {
+        let mut cache = SieveCache::new(2).unwrap();
+        assert_eq!(cache.put("a", 1).unwrap(), InsertResult::Inserted);
+        assert_eq!(cache.put("b", 2).unwrap(), InsertResult::Inserted);
+        assert_eq!(cache.put("a", 10).unwrap(), InsertResult::Updated);
+
+        // "a" was just written, so the hand passes it and evicts "b"
+        assert_eq!(cache.put("c", 3).unwrap(), InsertResult::Evicted("b", 2));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get_stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let mut cache = SieveCache::new(4).unwrap();
+        cache.add("a", 1).unwrap();
+        assert!(!cache.compare_and_swap(&"a", &2, 3).unwrap());
+        assert!(cache.compare_and_swap(&"a", &1, 3).unwrap());
+        assert_eq!(cache.peek(&"a").unwrap(), Some(3));
+        assert!(!cache.compare_and_swap(&"missing", &1, 3).unwrap());
+
+        // A write in between invalidates the version read earlier
+        let version = cache.version(&"a").unwrap().unwrap();
+        cache.modify(&"a", |v| *v += 1).unwrap();
+        assert!(!cache.compare_and_swap_version(&"a", version, 10).unwrap());
+        let version = cache.version(&"a").unwrap().unwrap();
+        assert!(cache.compare_and_swap_version(&"a", version, 10).unwrap());
+        assert_eq!(cache.peek(&"a").unwrap(), Some(10));
+
+        // Re-adding a removed key does not bring its old version back
+        cache.remove(&"a").unwrap();
+        cache.add("a", 10).unwrap();
+        assert!(!cache.compare_and_swap_version(&"a", version, 11).unwrap());
+    }
+
+    #[test]
+    fn test_compare_and_swap_keeps_ttl() {
+        let clock = MockClock::new();
+        let mut cache = SieveCache::with_clock(4, clock.clone()).unwrap();
+        cache.add_with_ttl("a", 1, Duration::from_secs(10)).unwrap();
+        clock.advance(Duration::from_secs(6));
+        assert!(cache.compare_and_swap(&"a", &1, 2).unwrap());
+        let version = cache.version(&"a").unwrap();
+        assert!(cache.put_if_version("a", 3, version).unwrap().is_some());
+
+        // The swapped value expires when the original one would have
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(cache.peek(&"a").unwrap(), None);
+    }
+
+    #[test]
+    fn test_increment_and_decrement() {
+        let mut cache: SieveCache<&str, u32> = SieveCache::new(2).unwrap();
+        assert_eq!(cache.increment(&"hits", 3).unwrap(), 3);
+        assert_eq!(cache.increment(&"hits", 2).unwrap(), 5);
+        assert_eq!(cache.decrement(&"hits", 1).unwrap(), 4);
+        assert_eq!(cache.peek(&"hits").unwrap(), Some(4));
+
+        // Unsigned counters stop at zero instead of wrapping
+        assert_eq!(cache.decrement(&"quota", 1).unwrap(), 0);
+        assert_eq!(cache.decrement(&"hits", 10).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_get_many() {
+        let mut cache = SieveCache::new(3).unwrap();
+        cache.add("a", 1).unwrap();
+        cache.add("b", 2).unwrap();
+
+        let values = cache.get_many(&["b", "missing", "a"]).unwrap();
+        assert_eq!(values, vec![Some(2), None, Some(1)]);
+        assert_eq!(cache.get_stats().hits, 2);
+        assert_eq!(cache.get_stats().misses, 1);
+    }
+
+    #[test]
+    fn test_add_many() {
+        let mut cache = SieveCache::new(3).unwrap();
+        cache.add("a", 1).unwrap();
+        cache.add("b", 2).unwrap();
+
+        cache
+            .add_many(vec![("c", 3), ("b", 20), ("d", 4), ("c", 30)])
+            .unwrap();
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get_stats().evictions, 1);
+        assert_eq!(cache.peek(&"b").unwrap(), Some(20));
+        assert_eq!(cache.peek(&"c").unwrap(), Some(30));
+        assert_eq!(cache.peek(&"d").unwrap(), Some(4));
+
+        // More new keys than fit keeps the last ones
+        let mut cache = SieveCache::new(3).unwrap();
+        cache.add_many((0..10).map(|i| (i, i))).unwrap();
+        let mut keys: Vec<_> = cache.iter_keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn test_borrowed_key_lookups() {
+        let mut cache: SieveCache<String, i32> = SieveCache::new(2).unwrap();
+        cache.add("a".to_string(), 1).unwrap();
+
+        assert_eq!(cache.get("a").unwrap(), Some(1));
+        assert_eq!(cache.get("b").unwrap(), None);
+        assert_eq!(cache.peek("a").unwrap(), Some(1));
+        assert!(cache.contains_key("a"));
+        assert!(cache.delete("a").unwrap());
+        assert!(!cache.contains_key("a"));
+    }
+
+    #[test]
+    fn test_with_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        let hasher = BuildHasherDefault::<DefaultHasher>::default();
+        let mut cache = SieveCache::with_hasher(2, hasher.clone()).unwrap();
+        cache.add("a", 1).unwrap();
+        cache.add("b", 2).unwrap();
+        cache.add("c", 3).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"c").unwrap(), Some(3));
+
+        let mut cache = SieveCache::with_policy_and_hasher(2, LruPolicy::new(), hasher).unwrap();
+        cache.add("a", 1).unwrap();
+        assert!(cache.contains_key(&"a"));
+    }
+
+    #[test]
+    fn test_shared_values_need_no_clone() {
+        // Deliberately not Clone
+        #[derive(Debug, PartialEq)]
+        struct Blob(Vec<u8>);
+
+        let mut cache: SharedSieveCache<&str, Blob> = SieveCache::new_shared(2).unwrap();
+        assert!(!cache.add_shared("a", Blob(vec![1; 1024])).unwrap());
+        let first = cache.get(&"a").unwrap().unwrap();
+        let second = cache.get(&"a").unwrap().unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(*first, Blob(vec![1; 1024]));
+    }
+
+    #[test]
+    fn test_get_ref() {
+        let mut cache: SieveCache<String, Vec<u8>> = SieveCache::new(2).unwrap();
+        cache.add("a".to_string(), vec![7; 4096]).unwrap();
+
+        let value = cache.get_ref("a").unwrap().unwrap();
+        assert_eq!(value.len(), 4096);
+        assert!(cache.get_ref("missing").unwrap().is_none());
+        assert_eq!(cache.get_stats().hits, 1);
+        assert_eq!(cache.get_stats().misses, 1);
+    }
+
+    #[test]
+    fn test_read_through_shared_reference() {
+        let mut cache = SieveCache::new(4).unwrap();
+        cache.add(1, "one".to_string()).unwrap();
+        let cache = Arc::new(cache);
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                std::thread::spawn(move || {
+                    assert_eq!(cache.get(&1).unwrap().as_deref(), Some("one"));
+                    assert_eq!(cache.get(&2).unwrap(), None);
+                })
+            })
+            .collect();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        let mut cache = Arc::try_unwrap(cache).unwrap();
+        assert_eq!((cache.stats().hits, cache.stats().misses), (4, 4));
+        assert_eq!(cache.get_stats().hits, 0);
+        // The next exclusive call folds the shared counters in
+        cache.run_maintenance().unwrap();
+        assert_eq!(cache.get_stats().hits, 4);
+        assert_eq!(cache.stats().hits, 4);
+    }
+
+    #[test]
+    fn test_removed_entries_are_freed() {
+        let tracker = Arc::new(());
+        let mut cache = SieveCache::new(3).unwrap();
+        for key in 0..3 {
+            cache.add(key, Arc::clone(&tracker)).unwrap();
+        }
+        assert_eq!(Arc::strong_count(&tracker), 4);
+
+        cache.delete(&0).unwrap();
+        assert_eq!(Arc::strong_count(&tracker), 3);
+
+        // Entries on both sides of a removed one link to each other, which
+        // must not keep any of them alive
+        cache.add(3, Arc::clone(&tracker)).unwrap();
+        cache.add(4, Arc::clone(&tracker)).unwrap();
+        assert_eq!(Arc::strong_count(&tracker), 4);
+
+        cache.purge();
+        assert_eq!(Arc::strong_count(&tracker), 1);
+        assert!(cache.is_empty());
+
+        cache.add(5, Arc::clone(&tracker)).unwrap();
+        drop(cache);
+        assert_eq!(Arc::strong_count(&tracker), 1);
+    }
+
+    #[test]
+    fn test_bounded_scan() {
+        let mut cache = SieveCache::with_policy(4, Sieve::with_max_scan(2)).unwrap();
+        for key in 0..4 {
+            cache.add(key, key).unwrap();
+            cache.get(&key).unwrap();
+        }
+
+        // Every entry is visited, so the hand gives up on the second one
+        cache.add(4, 4).unwrap();
+        assert!(cache.contains_key(&0));
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.get_stats().hand_steps, 1);
+
+        // The next sweep resumes after the victim
+        cache.add(5, 5).unwrap();
+        assert!(cache.contains_key(&2));
+        assert!(!cache.contains_key(&3));
+    }
+
+    #[test]
+    fn test_evict_n_and_batches() {
+        let mut cache = SieveCache::new(8).unwrap();
+        for key in 0..8 {
+            cache.add(key, key).unwrap();
+        }
+        assert_eq!(cache.evict_n(3).unwrap(), vec![(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(cache.len(), 5);
+        assert_eq!(cache.evict_n(10).unwrap().len(), 5);
+        assert!(cache.is_empty());
+
+        cache.set_eviction_batch(4);
+        for key in 0..9 {
+            cache.add(key, key).unwrap();
+        }
+        // The ninth insert evicted four entries at once
+        assert_eq!(cache.len(), 5);
+        assert_eq!(cache.get_stats().evictions, 12);
+        for key in 9..12 {
+            cache.add(key, key).unwrap();
+        }
+        assert_eq!(cache.len(), 8);
+        assert_eq!(cache.get_stats().evictions, 12);
+    }
+
+    #[test]
+    fn test_concurrent_cache() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ConcurrentSieveCache<String, Vec<u8>>>();
+
+        let cache = Arc::new(ConcurrentSieveCache::new(512).unwrap());
+        let workers: Vec<_> = (0..4)
+            .map(|worker| {
+                let cache = Arc::clone(&cache);
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        let key = worker * 100 + i;
+                        cache.add(key, key * 2).unwrap();
+                        assert_eq!(cache.get(&key).unwrap(), Some(key * 2));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(cache.len(), 400);
+        assert_eq!(cache.get_stats().hits, 400);
+        let cache = Arc::try_unwrap(cache).ok().unwrap().into_inner().unwrap();
+        assert_eq!(cache.get(&399).unwrap(), Some(798));
+    }
+
+    #[test]
+    fn test_pin() {
+        let mut cache: SieveCache<u32, u32> = SieveCache::new(3).unwrap();
+        for i in 0..3 {
+            cache.add(i, i).unwrap();
+        }
+        assert!(cache.pin(&0));
+        assert!(!cache.pin(&7));
+        assert!(cache.is_pinned(&0));
+
+        // The unvisited, pinned oldest entry is passed over
+        cache.add(3, 3).unwrap();
+        assert!(cache.contains_key(&0));
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get_stats().pinned, 1);
+
+        cache.pin(&2);
+        cache.pin(&3);
+        assert!(cache.add(4, 4).is_err());
+        assert_eq!(cache.evict_n(2).unwrap(), vec![]);
+
+        cache.unpin(&0);
+        cache.add(4, 4).unwrap();
+        assert!(!cache.contains_key(&0));
+        assert_eq!(cache.get_stats().pinned, 2);
+
+        // Removing a pinned entry releases its pin
+        cache.remove(&2).unwrap();
+        assert_eq!(cache.get_stats().pinned, 1);
+        cache.purge();
+        assert_eq!(cache.get_stats().pinned, 0);
+    }
+
+    #[test]
+    fn test_priorities() {
+        let mut cache: SieveCache<u32, u32> = SieveCache::new(3).unwrap();
+        cache.add_with_priority(0, 0, Priority::High).unwrap();
+        cache.add_with_priority(1, 1, Priority::Low).unwrap();
+        cache.add(2, 2).unwrap();
+        assert_eq!(cache.priority(&0), Some(Priority::High));
+        assert_eq!(cache.priority(&2), Some(Priority::Normal));
+
+        // The high priority oldest entry is passed over for the low one
+        cache.add(3, 3).unwrap();
+        assert!(!cache.contains_key(&1));
+        cache.add(4, 4).unwrap();
+        assert!(!cache.contains_key(&2));
+        assert!(cache.contains_key(&0));
+
+        // With nothing cheaper left the hand evicts in SIEVE order
+        cache.set_priority(&3, Priority::High);
+        cache.set_priority(&4, Priority::High);
+        cache.add_with_priority(5, 5, Priority::High).unwrap();
+        assert!(!cache.contains_key(&3));
+
+        // A visited low priority entry is spared by one pass only
+        cache.set_priority(&5, Priority::Low);
+        cache.get(&5).unwrap();
+        cache.add(6, 6).unwrap();
+        assert!(!cache.contains_key(&5));
+        assert!(cache.contains_key(&0) && cache.contains_key(&4));
+    }
+
+    #[test]
+    fn test_max_value_size() {
+        let mut cache: SieveCache<u32, String> = SieveCache::builder()
+            .capacity(4)
+            .max_value_size(8, |value: &String| value.len())
+            .build()
+            .unwrap();
+        cache.add(1, String::from("small")).unwrap();
+        assert!(matches!(
+            cache.add(2, String::from("far too large")),
+            Err(CacheError::ValueTooLarge { size: 13, max: 8 })
+        ));
+        assert!(cache.add(1, String::from("far too large")).is_err());
+        assert_eq!(cache.get(&1).unwrap(), Some(String::from("small")));
+        assert!(cache
+            .add_many([(3, String::from("ok")), (4, String::from("not ok at all"))])
+            .is_err());
+        assert_eq!(cache.len(), 1);
+
+        cache.extend([(5, String::from("not ok at all")), (6, String::from("ok"))]);
+        assert!(!cache.contains_key(&5));
+        assert_eq!(cache.get(&6).unwrap(), Some(String::from("ok")));
+    }
+
+    #[test]
+    fn test_namespaced_cache() {
+        let mut cache = NamespacedCache::new(3).unwrap();
+        cache.add("users", 1, 10).unwrap();
+        cache.add("orders", 1, 100).unwrap();
+        assert!(cache.add("users", 1, 11).unwrap());
+        assert_eq!(cache.get(&"users", &1).unwrap(), Some(11));
+        assert_eq!(cache.get(&"orders", &1).unwrap(), Some(100));
+        assert_eq!(cache.get(&"orders", &2).unwrap(), None);
+
+        // Both namespaces share the capacity
+        cache.add("orders", 2, 200).unwrap();
+        cache.add("orders", 3, 300).unwrap();
+        assert_eq!(cache.len(), 3);
+        let users = cache.namespace_stats(&"users");
+        let orders = cache.namespace_stats(&"orders");
+        assert_eq!(users.len + orders.len, 3);
+        assert_eq!(users.evictions + orders.evictions, 1);
+        assert_eq!((orders.hits, orders.misses), (1, 1));
+
+        assert_eq!(cache.purge(&"orders").unwrap(), orders.len);
+        assert_eq!(cache.namespace_len(&"orders"), 0);
+        assert_eq!(cache.len(), users.len);
+    }
+
+    #[test]
+    fn test_namespace_quotas() {
+        let mut cache = NamespacedCache::new(10).unwrap();
+        cache.set_quota("noisy", 0.3);
+        assert_eq!(cache.quota(&"noisy"), Some(3));
+        for i in 0..5 {
+            cache.add("quiet", i, i).unwrap();
+        }
+
+        // The noisy namespace borrows the free room, then only evicts itself
+        for i in 0..20 {
+            cache.add("noisy", i, i).unwrap();
+        }
+        assert_eq!(cache.namespace_len(&"quiet"), 5);
+        assert_eq!(cache.namespace_len(&"noisy"), 5);
+        assert_eq!(cache.namespace_stats(&"noisy").evictions, 15);
+
+        // Others reclaim what it borrowed first
+        cache.add("quiet", 5, 5).unwrap();
+        cache.add("quiet", 6, 6).unwrap();
+        assert_eq!(cache.namespace_len(&"noisy"), 3);
+        assert_eq!(cache.namespace_stats(&"quiet").evictions, 0);
+
+        cache.remove_quota(&"noisy");
+        assert_eq!(cache.quota(&"noisy"), None);
+    }
+
+    #[test]
+    fn test_range_and_prefix_invalidation() {
+        use std::ops::Bound;
+
+        for indexed in [false, true] {
+            let mut cache: SieveCache<String, u32> = SieveCache::new(10).unwrap();
+            if indexed {
+                cache.enable_key_index();
+            }
+            for (i, key) in ["session:2", "user:1", "session:1", "session", "sessions"]
+                .into_iter()
+                .enumerate()
+            {
+                cache.add(key.to_string(), i as u32).unwrap();
+            }
+
+            let bounds = (Bound::Included("session:"), Bound::Excluded("session;"));
+            let found = cache.range::<str, _>(bounds).unwrap();
+            let keys: Vec<&str> = found.iter().map(|(key, _)| key.as_str()).collect();
+            assert_eq!(keys, ["session:1", "session:2"]);
+
+            // Ranges that cannot hold a key come back empty
+            let bounds = (Bound::Included("user:1"), Bound::Excluded("session"));
+            assert!(cache.range::<str, _>(bounds).unwrap().is_empty());
+            let bounds = (Bound::Excluded("user:1"), Bound::Excluded("user:1"));
+            assert!(cache.range::<str, _>(bounds).unwrap().is_empty());
+
+            assert_eq!(cache.invalidate_prefix("session:").unwrap(), 2);
+            assert_eq!(cache.len(), 3);
+            let all = cache.range::<str, _>(..).unwrap();
+            let keys: Vec<&str> = all.iter().map(|(key, _)| key.as_str()).collect();
+            assert_eq!(keys, ["session", "sessions", "user:1"]);
+        }
+    }
+
+    #[test]
+    fn test_bump_epoch() {
+        let mut cache = SieveCache::new(10).unwrap();
+        cache.add("a", 1).unwrap();
+        cache.add("b", 2).unwrap();
+
+        assert_eq!(cache.bump_epoch(), 1);
+        assert_eq!(cache.get(&"a").unwrap(), None);
+        assert!(!cache.add("b", 3).unwrap());
+        assert_eq!(cache.get(&"b").unwrap(), Some(3));
+
+        cache.add("c", 4).unwrap();
+        assert_eq!(cache.get(&"c").unwrap(), Some(4));
+        assert_eq!(cache.get_stats().expirations, 2);
+    }
+
+    #[test]
+    fn test_put_if_version() {
+        let mut cache = SieveCache::new(10).unwrap();
+        let version = cache.put_if_version("a", 1, None).unwrap().unwrap();
+        assert_eq!(cache.put_if_version("a", 2, None).unwrap(), None);
+
+        let (value, seen) = cache.get_versioned(&"a").unwrap().unwrap();
+        assert_eq!((value, seen), (1, version));
+        let next = cache.put_if_version("a", 2, Some(seen)).unwrap().unwrap();
+        assert!(next > seen);
+        assert_eq!(cache.put_if_version("a", 3, Some(seen)).unwrap(), None);
+        assert_eq!(cache.get_versioned(&"a").unwrap(), Some((2, next)));
+        assert_eq!(cache.get_versioned(&"b").unwrap(), None);
+    }
+
+    #[test]
+    fn test_negative_caching() {
+        let clock = MockClock::new();
+        let mut cache: SieveCache<&str, i32> = SieveCache::builder()
+            .capacity(10)
+            .clock(clock.clone())
+            .negative_ttl(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        cache.add("gone", 1).unwrap();
+        cache.mark_absent("gone").unwrap();
+        assert_eq!(cache.len(), 0);
+        assert_eq!(cache.get_cached(&"gone").unwrap(), Cached::Absent);
+        assert_eq!(cache.get_cached(&"other").unwrap(), Cached::Unknown);
+
+        let stats = cache.get_stats();
+        assert_eq!((stats.negative_hits, stats.negative_entries), (1, 1));
+        assert_eq!(stats.misses, 1);
+
+        // Markers expire on their own TTL, and writes clear them
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.get_cached(&"gone").unwrap(), Cached::Unknown);
+        cache.mark_absent("back").unwrap();
+        cache.add("back", 2).unwrap();
+        assert_eq!(cache.get_cached(&"back").unwrap(), Cached::Present(2));
+        assert_eq!(cache.get_stats().negative_entries, 0);
+    }
+
+    #[test]
+    fn test_get_or_load() {
+        let calls = Arc::new(Mutex::new(0));
+        let counted = calls.clone();
+        let mut cache: SieveCache<u32, String> = SieveCache::builder()
+            .capacity(10)
+            .negative_ttl(Duration::from_secs(60))
+            .loader(move |key: &u32| {
+                *counted.lock().unwrap() += 1;
+                (*key < 100).then(|| key.to_string())
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(cache.get_or_load(&7).unwrap(), Some("7".to_string()));
+        assert_eq!(cache.get_or_load(&7).unwrap(), Some("7".to_string()));
+        assert_eq!(cache.get_or_load(&500).unwrap(), None);
+        assert_eq!(cache.get_or_load(&500).unwrap(), None);
+        assert_eq!(*calls.lock().unwrap(), 2);
+
+        let stats = cache.get_stats();
+        assert_eq!((stats.hits, stats.loads, stats.negative_hits), (1, 2, 1));
+    }
+
+    #[test]
+    fn test_get_or_load_singleflight() {
+        let calls = Arc::new(Mutex::new(0));
+        let counted = calls.clone();
+        let loader = move |key: &u32| {
+            *counted.lock().unwrap() += 1;
+            std::thread::sleep(Duration::from_millis(50));
+            Some(key * 2)
+        };
+        let cache: ConcurrentSieveCache<u32, u32> = SieveCache::builder()
+            .capacity(10)
+            .loader(loader)
+            .build()
+            .unwrap()
+            .into();
+        let cache = Arc::new(cache);
+        let barrier = Arc::new(std::sync::Barrier::new(8));
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    cache.get_or_load(&21).unwrap()
+                })
+            })
+            .collect();
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), Some(42));
+        }
+        assert_eq!(*calls.lock().unwrap(), 1);
+        assert_eq!(cache.get_stats().loads, 1);
+    }
+
+    #[test]
+    fn test_get_or_load_many() {
+        struct Store(Arc<Mutex<Vec<Vec<u32>>>>);
+        impl CacheLoader<u32, u32> for Store {
+            fn load(&self, key: &u32) -> Option<u32> {
+                self.load_many(&[*key]).remove(key)
+            }
+            fn load_many(&self, keys: &[u32]) -> HashMap<u32, u32> {
+                self.0.lock().unwrap().push(keys.to_vec());
+                keys.iter()
+                    .filter(|&&key| key % 2 == 0)
+                    .map(|&key| (key, key * 10))
+                    .collect()
+            }
+        }
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let mut cache: SieveCache<u32, u32> = SieveCache::builder()
+            .capacity(10)
+            .loader(Store(batches.clone()))
+            .build()
+            .unwrap();
+        cache.add(1, 100).unwrap();
+
+        let found = cache.get_or_load_many(&[1, 2, 3, 4, 2]).unwrap();
+        assert_eq!(found, HashMap::from([(1, 100), (2, 20), (4, 40)]));
+        assert_eq!(*batches.lock().unwrap(), vec![vec![2, 3, 4]]);
+        assert_eq!(cache.get(&4).unwrap(), Some(40));
+        assert_eq!(cache.get_stats().loads, 1);
+    }
+
+    #[test]
+    fn test_fallible_loader() {
+        let clock = MockClock::new();
+        let failing = Arc::new(Mutex::new(true));
+        let backend = failing.clone();
+        let mut cache: SieveCache<u32, u32> = SieveCache::builder()
+            .capacity(10)
+            .clock(clock.clone())
+            .load_error_ttl(Duration::from_secs(5))
+            .try_loader(move |key: &u32| {
+                if *backend.lock().unwrap() {
+                    Err(std::io::Error::other("backend down"))
+                } else {
+                    Ok(Some(key + 1))
+                }
+            })
+            .build()
+            .unwrap();
+
+        let error = cache.get_or_load(&1).unwrap_err();
+        assert!(matches!(error, CacheError::Load(_)));
+        assert_eq!(error.to_string(), "Load error: backend down");
+
+        // The error is served from the cache until its TTL elapses
+        *failing.lock().unwrap() = false;
+        assert!(cache.get_or_load(&1).is_err());
+        assert!(cache.get_or_load_many(&[1, 2]).is_err());
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.get_or_load(&1).unwrap(), Some(2));
+
+        let stats = cache.get_stats();
+        assert_eq!((stats.loads, stats.load_errors), (2, 1));
+
+        // Without a TTL, every lookup retries
+        cache.set_load_error_ttl(None);
+        *failing.lock().unwrap() = true;
+        assert!(cache.get_or_load(&3).is_err());
+        *failing.lock().unwrap() = false;
+        assert_eq!(cache.get_or_load(&3).unwrap(), Some(4));
+    }
+
+    #[test]
+    fn test_refresh_ahead() {
+        let clock = MockClock::new();
+        let generation = Arc::new(Mutex::new(0));
+        let counted = generation.clone();
+        let cache: ConcurrentSieveCache<&str, u32> = SieveCache::builder()
+            .capacity(10)
+            .clock(clock.clone())
+            .expire_after_write(Duration::from_secs(10))
+            .refresh_ahead(Duration::from_secs(3))
+            .loader(move |_: &&str| {
+                let mut generation = counted.lock().unwrap();
+                *generation += 1;
+                Some(*generation)
+            })
+            .build()
+            .unwrap()
+            .into();
+
+        assert_eq!(cache.get_or_load(&"hot").unwrap(), Some(1));
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.get_or_load(&"hot").unwrap(), Some(1));
+        assert_eq!(cache.get_stats().refreshes, 0);
+
+        // Close to expiring: the current value is served while it reloads
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(cache.get_or_load(&"hot").unwrap(), Some(1));
+        while cache.get_stats().loads < 2 {
+            std::thread::yield_now();
+        }
+        assert_eq!(cache.get(&"hot").unwrap(), Some(2));
+        assert_eq!(cache.get_stats().refreshes, 1);
+
+        // The reload restarted the entry's write timeout
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.get(&"hot").unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_refresh_ahead_keeps_entry_ttl() {
+        let clock = MockClock::new();
+        let cache: ConcurrentSieveCache<&str, u32> = SieveCache::builder()
+            .capacity(10)
+            .clock(clock.clone())
+            .refresh_ahead(Duration::from_secs(3))
+            .loader(|_: &&str| Some(2))
+            .build()
+            .unwrap()
+            .into();
+
+        cache.add_with_ttl("k", 1, Duration::from_secs(10)).unwrap();
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(cache.get_or_load(&"k").unwrap(), Some(1));
+        while cache.get_stats().loads < 1 {
+            std::thread::yield_now();
+        }
+        assert_eq!(cache.get(&"k").unwrap(), Some(2));
+
+        // The reloaded value still expires at the entry's own deadline
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(cache.get(&"k").unwrap(), None);
+    }
+
+    #[test]
+    fn test_refresh_ahead_after_delete() {
+        let clock = MockClock::new();
+        let calls = Arc::new(Mutex::new(0));
+        let counted = calls.clone();
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let loading = barrier.clone();
+        let cache: ConcurrentSieveCache<&str, u32> = SieveCache::builder()
+            .capacity(10)
+            .clock(clock.clone())
+            .expire_after_write(Duration::from_secs(10))
+            .refresh_ahead(Duration::from_secs(3))
+            .loader(move |_: &&str| {
+                let mut calls = counted.lock().unwrap();
+                *calls += 1;
+                if *calls == 1 {
+                    return Some(1);
+                }
+                drop(calls);
+                loading.wait();
+                loading.wait();
+                Some(99)
+            })
+            .build()
+            .unwrap()
+            .into();
+
+        assert_eq!(cache.get_or_load(&"k").unwrap(), Some(1));
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(cache.get_or_load(&"k").unwrap(), Some(1));
+
+        // Deleted while the reload runs, so its value is dropped
+        barrier.wait();
+        assert!(cache.delete(&"k").unwrap());
+        barrier.wait();
+        while cache.get_stats().loads < 2 {
+            std::thread::yield_now();
+        }
+        assert_eq!(cache.get(&"k").unwrap(), None);
+    }
+
+    #[test]
+    fn test_run_maintenance() {
+        let clock = MockClock::new();
+        let mut cache = SieveCache::with_clock(10, clock.clone()).unwrap();
+        cache.add_with_ttl("a", 1, Duration::from_secs(1)).unwrap();
+        cache.add("b", 2).unwrap();
+        cache.add("c", 3).unwrap();
+        assert_eq!(cache.get(&"b").unwrap(), Some(2));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.run_maintenance().unwrap(), 1);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get_stats().hits, 1);
+
+        cache.bump_epoch();
+        cache.add("d", 4).unwrap();
+        assert_eq!(cache.run_maintenance().unwrap(), 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.run_maintenance().unwrap(), 0);
+        assert_eq!(cache.get_stats().expirations, 3);
+    }
+
+    #[test]
+    fn test_write_backend() {
+        #[derive(Default)]
+        struct Store {
+            rows: Mutex<HashMap<String, i32>>,
+        }
+
+        impl WriteBackend<String, i32> for Arc<Store> {
+            type Error = std::io::Error;
+
+            fn write(&self, key: &String, value: &i32) -> Result<(), Self::Error> {
+                if *value < 0 {
+                    return Err(std::io::Error::other("negative"));
+                }
+                self.rows.lock().unwrap().insert(key.clone(), *value);
+                Ok(())
+            }
+
+            fn delete(&self, key: &String) -> Result<(), Self::Error> {
+                self.rows.lock().unwrap().remove(key);
+                Ok(())
+            }
+        }
+
+        let store = Arc::new(Store::default());
+        let mut cache = SieveCache::new(10).unwrap();
+        cache.set_write_backend(store.clone());
+        let rows = || store.rows.lock().unwrap().clone();
+
+        cache.add("a".to_string(), 1).unwrap();
+        cache.add("b".to_string(), 2).unwrap();
+        assert_eq!(rows().get("a"), Some(&1));
+
+        let error = cache.add("a".to_string(), -1).unwrap_err();
+        assert!(matches!(error, CacheError::Backend(_)));
+        assert_eq!(cache.get("a").unwrap(), Some(1));
+
+        assert!(cache.delete("a").unwrap());
+        assert_eq!(cache.remove(&"c".to_string()).unwrap(), None);
+        assert_eq!(rows().len(), 1);
+
+        let batch = [("b".to_string(), 3), ("c".to_string(), -3)];
+        assert!(cache.add_many(batch).is_err());
+        assert_eq!(rows().get("b"), Some(&3));
+        assert_eq!(cache.get("b").unwrap(), None);
+
+        // Every other way of writing or removing reaches the backend too
+        cache.add("b".to_string(), 3).unwrap();
+        assert!(cache.compare_and_swap(&"b".to_string(), &3, 4).unwrap());
+        assert_eq!(cache.probe("c".to_string(), 5).unwrap(), (5, false));
+        cache.entry("d".to_string()).unwrap().or_insert(6).unwrap();
+        let entry = cache.entry("d".to_string()).unwrap();
+        entry.and_modify(|value| *value += 1).unwrap();
+        assert!(cache
+            .modify(&"c".to_string(), |value| *value *= 10)
+            .unwrap());
+        *cache.get_mut(&"b".to_string()).unwrap().unwrap() = 40;
+        let expected =
+            [("b", 40), ("c", 50), ("d", 7)].map(|(key, value)| (key.to_string(), value));
+        assert_eq!(rows(), HashMap::from(expected));
+
+        assert_eq!(cache.retain(|key, _| key.as_str() != "d").unwrap(), 1);
+        cache.add("x:1".to_string(), 1).unwrap();
+        assert_eq!(cache.invalidate_prefix("x:").unwrap(), 1);
+        assert_eq!(rows().len(), 2);
+
+        // A value the backend refuses after it changed in place expires
+        let error = cache.modify(&"c".to_string(), |value| *value = -1);
+        assert!(matches!(error, Err(CacheError::Backend(_))));
+        assert_eq!(cache.get("c").unwrap(), None);
+        assert_eq!(rows().get("c"), Some(&50));
+
+        match cache.entry("b".to_string()).unwrap() {
+            Entry::Occupied(entry) => assert_eq!(entry.remove().unwrap(), 40),
+            Entry::Vacant(_) => panic!("b is cached"),
+        }
+        assert_eq!(rows().get("b"), None);
+
+        // A counter reaches it from its first update on
+        assert_eq!(cache.increment(&"n".to_string(), 2).unwrap(), 2);
+        assert_eq!(rows().get("n"), Some(&2));
+        assert_eq!(cache.increment(&"n".to_string(), 3).unwrap(), 5);
+        assert_eq!(rows().get("n"), Some(&5));
+    }
+
+    #[test]
+    fn test_write_behind() {
+        #[derive(Default)]
+        struct Store {
+            rows: Mutex<HashMap<u32, u32>>,
+            batches: Mutex<usize>,
+        }
+
+        impl WriteBackend<u32, u32> for Arc<Store> {
+            type Error = std::io::Error;
+
+            fn write(&self, key: &u32, value: &u32) -> Result<(), Self::Error> {
+                self.rows.lock().unwrap().insert(*key, *value);
+                Ok(())
+            }
+
+            fn delete(&self, key: &u32) -> Result<(), Self::Error> {
+                self.rows.lock().unwrap().remove(key);
+                Ok(())
+            }
+
+            fn write_batch(&self, batch: &[(u32, Option<u32>)]) -> Result<(), Self::Error> {
+                *self.batches.lock().unwrap() += 1;
+                for (key, value) in batch {
+                    match value {
+                        Some(value) => self.write(key, value)?,
+                        None => self.delete(key)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        let store = Arc::new(Store::default());
+        let clock = MockClock::new();
+        let mut cache = SieveCache::builder()
+            .capacity(10)
+            .clock(clock.clone())
+            .write_backend(store.clone())
+            .write_behind(WriteBehind::new(3).interval(Duration::from_secs(5)))
+            .build()
+            .unwrap();
+
+        cache.add(1, 10).unwrap();
+        cache.add(1, 11).unwrap();
+        cache.add(2, 20).unwrap();
+        assert!(cache.delete(&2).unwrap());
+        assert_eq!(cache.pending_writes(), 2);
+        assert!(store.rows.lock().unwrap().is_empty());
+
+        // The queue is due once it holds three keys
+        cache.add(3, 30).unwrap();
+        cache.add(4, 40).unwrap();
+        assert_eq!(cache.pending_writes(), 1);
+        assert_eq!(*store.batches.lock().unwrap(), 1);
+        assert_eq!(store.rows.lock().unwrap().get(&1), Some(&11));
+        assert!(!store.rows.lock().unwrap().contains_key(&2));
+
+        clock.advance(Duration::from_secs(5));
+        cache.run_maintenance().unwrap();
+        assert_eq!(cache.pending_writes(), 0);
+        assert_eq!(store.rows.lock().unwrap().get(&4), Some(&40));
+
+        cache.add(5, 50).unwrap();
+        cache.flush().unwrap();
+        cache.add(6, 60).unwrap();
+        drop(cache);
+        assert_eq!(*store.batches.lock().unwrap(), 4);
+        assert_eq!(store.rows.lock().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_prime() {
+        let mut cache: SieveCache<u32, u32> = SieveCache::new(3).unwrap();
+        cache.add(1, 1).unwrap();
+        assert_eq!(cache.prime([(1, 100), (2, 2), (3, 3)]).unwrap(), 2);
+        assert_eq!(cache.get(&1).unwrap(), Some(1));
+
+        // Primed entries are unvisited, so the hand takes them first
+        cache.add(4, 4).unwrap();
+        assert!(!cache.contains_key(&2));
+
+        cache.set_loader(|key: &u32| key.is_multiple_of(2).then_some(key * 10));
+        assert_eq!(cache.prime_from_loader(&[4, 6, 7, 6]).unwrap(), 1);
+        assert_eq!(cache.peek(&6).unwrap(), Some(60));
+        let stats = cache.get_stats();
+        assert_eq!((stats.hits, stats.misses, stats.loads), (1, 0, 1));
+    }
+
+    #[test]
+    fn test_merge() {
+        // `a` writes 1 and 2 before `b` writes 2 to 5, then 3 after it
+        let caches = || {
+            let clock = MockClock::new();
+            let mut a: SieveCache<u32, u32> = SieveCache::with_clock(4, clock.clone()).unwrap();
+            a.add(1, 10).unwrap();
+            a.add(2, 20).unwrap();
+            clock.advance(Duration::from_secs(1));
+            let mut b: SieveCache<u32, u32> = SieveCache::with_clock(4, clock.clone()).unwrap();
+            for key in 2..6 {
+                b.add(key, key * 100).unwrap();
+            }
+            clock.advance(Duration::from_secs(1));
+            a.add(3, 30).unwrap();
+            (a, b)
+        };
+        let values = |cache: &SieveCache<u32, u32>| {
+            let mut values: Vec<_> = cache.iter().collect();
+            values.sort();
+            values
+        };
+
+        let (mut a, b) = caches();
+        assert_eq!(a.merge(b, ConflictPolicy::KeepExisting).unwrap(), 2);
+        // Key 1, the oldest unvisited entry, made room for key 5
+        assert_eq!(values(&a), [(2, 20), (3, 30), (4, 400), (5, 500)]);
+
+        let (mut a, b) = caches();
+        assert_eq!(a.merge(b, ConflictPolicy::Overwrite).unwrap(), 4);
+        assert_eq!(a.get(&2).unwrap(), Some(200));
+        assert_eq!(a.get(&3).unwrap(), Some(300));
+
+        let (mut a, b) = caches();
+        assert_eq!(a.merge(b, ConflictPolicy::NewestWins).unwrap(), 3);
+        assert_eq!(a.get(&2).unwrap(), Some(200));
+        assert_eq!(a.get(&3).unwrap(), Some(30));
+    }
+
+    #[test]
+    fn test_bump_epoch_victim_is_expired() {
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let sink = removed.clone();
+        let mut cache = SieveCache::builder()
+            .capacity(1)
+            .eviction_listener(move |key, _, cause| sink.lock().unwrap().push((key, cause)))
+            .build()
+            .unwrap();
+        cache.add("a", 1).unwrap();
+        cache.bump_epoch();
+        cache.add("b", 2).unwrap();
+
+        assert_eq!(*removed.lock().unwrap(), [("a", RemovalCause::Expired)]);
+        let stats = cache.get_stats();
+        assert_eq!((stats.evictions, stats.expirations), (0, 1));
+        assert_eq!(cache.get(&"b").unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_merge_keeps_priority_and_pins() {
+        let mut other = SieveCache::new(4).unwrap();
+        other.add_with_priority(1, 10, Priority::High).unwrap();
+        other.add(2, 20).unwrap();
+        other.pin(&2);
+        let mut cache = SieveCache::new(2).unwrap();
+        cache.add(3, 30).unwrap();
+        assert_eq!(cache.merge(other, ConflictPolicy::KeepExisting).unwrap(), 2);
+
+        assert_eq!(cache.priority(&1), Some(Priority::High));
+        assert!(cache.is_pinned(&2));
+        assert_eq!(cache.get_stats().pinned, 1);
+        // Key 3 was evicted for key 2, and the pinned entry stays put
+        cache.add(4, 40).unwrap();
+        assert!(cache.contains_key(&2) && !cache.contains_key(&3));
+    }
+
+    // Counts the allocations made by the current thread, so tests running
+    // alongside do not disturb the count
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    #[test]
+    fn test_small_values_are_stored_inline() {
+        let mut cache: SieveCache<u64, [u8; 32]> =
+            SieveCache::with_clock(64, MockClock::new()).unwrap();
+        for key in 0..64 {
+            cache.add(key, [key as u8; 32]).unwrap();
+        }
+        let before = ALLOCATIONS.with(|count| count.get());
+        // Every add evicts an entry and reuses its slot in the slab. The mock
+        // clock keeps the statistics history from opening a new bucket
+        for key in 64..1024 {
+            cache.add(key, [key as u8; 32]).unwrap();
+            cache.get(&key).unwrap();
+        }
+        // The key map may grow once as removed keys leave tombstones behind,
+        // but no value is allocated on its own
+        let after = ALLOCATIONS.with(|count| count.get());
+        assert!(after - before <= 1);
+    }
+
+    #[test]
+    fn test_warming_ignores_expired_lookups() {
+        let clock = MockClock::new();
+        let mut cache: SieveCache<u32, u32> = SieveCache::builder()
+            .capacity(4)
+            .clock(clock.clone())
+            .warming()
+            .build()
+            .unwrap();
+        cache.add_with_ttl(1, 1, Duration::from_secs(1)).unwrap();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.get_many(&[1, 2]).unwrap(), [None, None]);
+        assert_eq!(cache.get_stats().misses, 0);
+        assert_eq!(cache.get_stats().expirations, 1);
+    }
+
+    #[test]
+    fn test_rejected_insert_keeps_eviction_order() {
+        let mut cache: SieveCache<u32, u32> = SieveCache::builder()
+            .capacity(3)
+            .admission_filter(TinyLfu::new(3))
+            .build()
+            .unwrap();
+        for key in 0..3 {
+            cache.add(key, key).unwrap();
+        }
+        cache.get(&0).unwrap();
+
+        // Finding 1 as the victim swept past 0; a rejection undoes that
+        cache.add(99, 99).unwrap();
+        assert_eq!(cache.get_stats().admission_rejections, 1);
+        assert_eq!(cache.policy().hand(), None);
+
+        // So the next sweep starts over and clears 0's visited bit itself
+        let resets = cache.get_stats().visited_resets;
+        for _ in 0..3 {
+            cache.get(&7).unwrap();
+        }
+        cache.add(7, 7).unwrap();
+        assert_eq!(cache.get_stats().visited_resets, resets + 1);
+        assert!(cache.contains_key(&0) && !cache.contains_key(&1));
+    }
+
+    #[test]
+    fn test_pluggable_clock() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::time::Instant;
+
+        // Clones of a mock clock share one frozen time
+        let clock = MockClock::new();
+        let start = clock.now();
+        clock.clone().advance(Duration::from_secs(5));
+        assert_eq!(clock.now() - start, Duration::from_secs(5));
+        assert_eq!(clock.now(), clock.now());
+
+        // Any clock drives expiry, not just the ones the crate ships
+        #[derive(Debug)]
+        struct SecondsSince(Instant, Arc<AtomicU64>);
+        impl Clock for SecondsSince {
+            fn now(&self) -> Instant {
+                let seconds = self.1.load(Ordering::Relaxed);
+                self.0 + Duration::from_secs(seconds)
+            }
+        }
+        let seconds = Arc::new(AtomicU64::new(0));
+        let clock = SecondsSince(Instant::now(), Arc::clone(&seconds));
+        let mut cache = SieveCache::with_clock(2, clock).unwrap();
+        cache.add_with_ttl("a", 1, Duration::from_secs(10)).unwrap();
+        seconds.store(9, Ordering::Relaxed);
+        assert_eq!(cache.get(&"a").unwrap(), Some(1));
+        seconds.store(10, Ordering::Relaxed);
+        assert_eq!(cache.get(&"a").unwrap(), None);
+    }
 }
@@ -1,77 +1,101 @@
-use crate::{node::Node, CacheError};
-use std::hash::Hash;
-use std::sync::{Arc, Mutex};
+use crate::eviction::EvictionPolicy;
+use crate::node::{Node, NodeId};
+use crate::slab::Slab;
+use std::hash::{BuildHasher, Hash};
 
 pub(crate) trait LinkedListOps<K, V> {
-    fn insert_node(&mut self, key: K, value: V) -> Result<(), CacheError>;
-    fn unlink_node(&mut self, node: Arc<Mutex<Node<K, V>>>) -> Result<(), CacheError>;
+    fn insert_node(&mut self, node: Node<K, V>);
+    fn unlink_node(&mut self, id: NodeId) -> Node<K, V>;
 }
 
-impl<K, V> LinkedListOps<K, V> for super::SieveCache<K, V>
+impl<K, V, P, S> LinkedListOps<K, V> for super::SieveCache<K, V, P, S>
 where
     K: Eq + Hash + Clone,
     V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
 {
-    fn insert_node(&mut self, key: K, value: V) -> Result<(), CacheError> {
-        let new_node = Node::new(key.clone(), value);
-        let new_node = Arc::new(Mutex::new(new_node));
-
-        // set the next pointer
-        {
-            let mut node = new_node
-                .lock()
-                .map_err(|e| CacheError::LockError(e.to_string()))?;
-            node.next = self.head.clone();
+    fn insert_node(&mut self, mut node: Node<K, V>) {
+        let key = node.key.clone();
+        self.generation += 1;
+        node.version = self.generation;
+        node.epoch = self.epoch;
+        node.slot = self.slots.len();
+        self.priorities[node.priority as usize] += 1;
+        let id = self.nodes.insert(node);
+        self.slots.push(id);
+        push_front(&mut self.nodes, &mut self.head, &mut self.tail, id);
+        if let Some(index) = &mut self.key_index {
+            index.insert(key.clone());
         }
+        self.cache.insert(key, id);
+        self.size += 1;
+    }
 
-        // update the prev pointer of the old head
-        if let Some(head) = &self.head {
-            let mut head_guard = head
-                .lock()
-                .map_err(|e| CacheError::LockError(e.to_string()))?;
+    fn unlink_node(&mut self, id: NodeId) -> Node<K, V> {
+        let slot = self.nodes[id].slot;
 
-            head_guard.prev = Some(new_node.clone());
+        // fill the hole in the slot list with the last node
+        self.slots.swap_remove(slot);
+        if let Some(&moved) = self.slots.get(slot) {
+            self.nodes[moved].slot = slot;
         }
 
-        // set the new head
-        self.head = Some(new_node.clone());
-
-        // if theres no tail, this is the first node
-        if self.tail.is_none() {
-            self.tail = Some(new_node.clone());
+        detach(&mut self.nodes, &mut self.head, &mut self.tail, id);
+        let node = self.nodes.remove(id);
+        if node.pinned {
+            self.pinned -= 1;
+            self.stats.pinned = self.pinned;
         }
+        self.priorities[node.priority as usize] -= 1;
+        if let Some(index) = &mut self.key_index {
+            index.remove(&node.key);
+        }
+        node
+    }
+}
 
-        self.cache.insert(key, new_node);
-        self.size += 1;
-        Ok(())
+// Links a detached node in as the new head
+pub(crate) fn push_front<K, V>(
+    nodes: &mut Slab<Node<K, V>>,
+    head: &mut Option<NodeId>,
+    tail: &mut Option<NodeId>,
+    id: NodeId,
+) {
+    // set the next pointer
+    nodes[id].next = *head;
+
+    // update the prev pointer of the old head
+    if let Some(old_head) = *head {
+        nodes[old_head].prev = Some(id);
     }
 
-    fn unlink_node(&mut self, node: Arc<Mutex<Node<K, V>>>) -> Result<(), CacheError> {
-        let (next, prev) = {
-            let node_guard = node
-                .lock()
-                .map_err(|e| CacheError::LockError(e.to_string()))?;
-            (node_guard.next.clone(), node_guard.prev.clone())
-        };
+    // set the new head
+    *head = Some(id);
 
-        if let Some(prev_node) = &prev {
-            let mut prev_guard = prev_node
-                .lock()
-                .map_err(|e| CacheError::LockError(e.to_string()))?;
-            prev_guard.next = next.clone();
-        } else {
-            self.head = next.clone();
-        }
+    // if theres no tail, this is the first node
+    if tail.is_none() {
+        *tail = Some(id);
+    }
+}
 
-        if let Some(next_node) = next {
-            let mut next_guard = next_node
-                .lock()
-                .map_err(|e| CacheError::LockError(e.to_string()))?;
-            next_guard.prev = prev.clone();
-        } else {
-            self.tail = prev;
-        }
+// Takes a node out of the list, joining its neighbours
+pub(crate) fn detach<K, V>(
+    nodes: &mut Slab<Node<K, V>>,
+    head: &mut Option<NodeId>,
+    tail: &mut Option<NodeId>,
+    id: NodeId,
+) {
+    let node = &mut nodes[id];
+    let (next, prev) = (node.next.take(), node.prev.take());
+
+    match prev {
+        Some(prev) => nodes[prev].next = next,
+        None => *head = next,
+    }
 
-        Ok(())
+    match next {
+        Some(next) => nodes[next].prev = prev,
+        None => *tail = prev,
     }
 }
@@ -0,0 +1,480 @@
+//! Read-through loading of values the cache does not hold.
+//!
+//! A cache with a [`CacheLoader`] fills its own misses: [`get_or_load`]
+//! fetches an absent key from the loader, caches the result and returns it,
+//! so call sites no longer need their own miss path. [`get_or_load_many`]
+//! does the same for a batch of keys, fetching all the misses at once.
+//!
+//! Loaders that can fail implement [`TryCacheLoader`] instead. Their errors
+//! are handed to the caller as [`CacheError::Load`], and can be cached for a
+//! while so a failing backend is not hammered with retries, see
+//! [`SieveCache::set_load_error_ttl`].
+//!
+//! [`get_or_load`]: SieveCache::get_or_load
+//! [`get_or_load_many`]: SieveCache::get_or_load_many
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::hash::{BuildHasher, Hash};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::eviction::EvictionPolicy;
+use crate::negative::NegativeCache;
+use crate::sieve::{node_deadline, SieveCache};
+use crate::types::{CacheError, Cached};
+
+/// Fetches values from the backing store on a cache miss.
+///
+/// Implemented for every `Fn(&K) -> Option<V>`, so a closure will do.
+pub trait CacheLoader<K, V> {
+    /// Fetches the value for `key`, or `None` if the store has none.
+    fn load(&self, key: &K) -> Option<V>;
+
+    /// Fetches the values for several keys, leaving out those the store has
+    /// none for.
+    ///
+    /// Calls [`load`](Self::load) once per key by default; override it to
+    /// fetch the whole batch in a single round trip.
+    fn load_many(&self, keys: &[K]) -> HashMap<K, V>
+    where
+        K: Eq + Hash + Clone,
+    {
+        keys.iter()
+            .filter_map(|key| Some((key.clone(), self.load(key)?)))
+            .collect()
+    }
+}
+
+impl<K, V, F> CacheLoader<K, V> for F
+where
+    F: Fn(&K) -> Option<V>,
+{
+    fn load(&self, key: &K) -> Option<V> {
+        self(key)
+    }
+}
+
+/// Fetches values from a backing store that can fail.
+///
+/// Implemented for every `Fn(&K) -> Result<Option<V>, E>`.
+pub trait TryCacheLoader<K, V> {
+    type Error: Error + Send + Sync + 'static;
+
+    /// Fetches the value for `key`, `Ok(None)` if the store has none.
+    fn try_load(&self, key: &K) -> Result<Option<V>, Self::Error>;
+
+    /// Fetches the values for several keys, leaving out those the store has
+    /// none for. Any error fails the whole batch.
+    ///
+    /// Calls [`try_load`](Self::try_load) once per key by default; override
+    /// it to fetch the whole batch in a single round trip.
+    fn try_load_many(&self, keys: &[K]) -> Result<HashMap<K, V>, Self::Error>
+    where
+        K: Eq + Hash + Clone,
+    {
+        let mut found = HashMap::with_capacity(keys.len());
+        for key in keys {
+            if let Some(value) = self.try_load(key)? {
+                found.insert(key.clone(), value);
+            }
+        }
+        Ok(found)
+    }
+}
+
+impl<K, V, E, F> TryCacheLoader<K, V> for F
+where
+    F: Fn(&K) -> Result<Option<V>, E>,
+    E: Error + Send + Sync + 'static,
+{
+    type Error = E;
+
+    fn try_load(&self, key: &K) -> Result<Option<V>, E> {
+        self(key)
+    }
+}
+
+// Either kind of loader, with its errors turned into `CacheError::Load`
+pub(crate) trait Load<K, V> {
+    fn load(&self, key: &K) -> Result<Option<V>, CacheError>;
+    fn load_many(&self, keys: &[K]) -> Result<HashMap<K, V>, CacheError>;
+}
+
+struct Infallible<L>(L);
+
+impl<K, V, L> Load<K, V> for Infallible<L>
+where
+    K: Eq + Hash + Clone,
+    L: CacheLoader<K, V>,
+{
+    fn load(&self, key: &K) -> Result<Option<V>, CacheError> {
+        Ok(self.0.load(key))
+    }
+
+    fn load_many(&self, keys: &[K]) -> Result<HashMap<K, V>, CacheError> {
+        Ok(self.0.load_many(keys))
+    }
+}
+
+struct Fallible<L>(L);
+
+impl<K, V, L> Load<K, V> for Fallible<L>
+where
+    K: Eq + Hash + Clone,
+    L: TryCacheLoader<K, V>,
+{
+    fn load(&self, key: &K) -> Result<Option<V>, CacheError> {
+        self.0.try_load(key).map_err(load_error)
+    }
+
+    fn load_many(&self, keys: &[K]) -> Result<HashMap<K, V>, CacheError> {
+        self.0.try_load_many(keys).map_err(load_error)
+    }
+}
+
+fn load_error<E: Error + Send + Sync + 'static>(error: E) -> CacheError {
+    CacheError::Load(Arc::new(error))
+}
+
+pub(crate) type SharedLoader<K, V> = Arc<dyn Load<K, V> + Send + Sync>;
+
+pub(crate) fn infallible<K, V, L>(loader: L) -> SharedLoader<K, V>
+where
+    K: Eq + Hash + Clone,
+    L: CacheLoader<K, V> + Send + Sync + 'static,
+{
+    Arc::new(Infallible(loader))
+}
+
+pub(crate) fn fallible<K, V, L>(loader: L) -> SharedLoader<K, V>
+where
+    K: Eq + Hash + Clone,
+    L: TryCacheLoader<K, V> + Send + Sync + 'static,
+{
+    Arc::new(Fallible(loader))
+}
+
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Sets the loader [`get_or_load`](Self::get_or_load) fetches misses
+    /// from, replacing any previous one.
+    pub fn set_loader<L>(&mut self, loader: L)
+    where
+        L: CacheLoader<K, V> + Send + Sync + 'static,
+    {
+        self.loader = Some(infallible(loader));
+    }
+
+    /// Like [`set_loader`](Self::set_loader), for a loader that can fail.
+    pub fn set_try_loader<L>(&mut self, loader: L)
+    where
+        L: TryCacheLoader<K, V> + Send + Sync + 'static,
+    {
+        self.loader = Some(fallible(loader));
+    }
+
+    /// Remembers a failed load for `ttl`, so lookups of the key fail with the
+    /// same error without calling the loader again until then. With `None`,
+    /// errors are handed to the caller and the next lookup retries.
+    ///
+    /// Up to [`capacity`](Self::capacity) errors are kept; writing a value
+    /// for the key forgets its error.
+    pub fn set_load_error_ttl(&mut self, ttl: Option<Duration>) {
+        match (&mut self.load_errors, ttl) {
+            (Some(errors), Some(ttl)) => errors.ttl = ttl,
+            (errors, ttl) => *errors = ttl.map(NegativeCache::new),
+        }
+    }
+
+    /// Retrieves the value for `key`, fetching it from the loader and caching
+    /// it on a miss.
+    ///
+    /// The lookup counts like [`get`](Self::get), and every call to the
+    /// loader is counted in [`loads`](crate::CacheStats::loads). A key the
+    /// loader has no value for is [marked absent](Self::mark_absent), so with
+    /// negative caching enabled it is not fetched again until the marker
+    /// expires.
+    ///
+    /// # Returns
+    /// - `Ok(Some(V))` if the key was cached or the loader found it
+    /// - `Ok(None)` if neither had it, or no loader is set
+    /// - `Err(CacheError::Load)` if the loader failed, now or, with a
+    ///   [load error TTL](Self::set_load_error_ttl), recently enough
+    /// - `Err(CacheError)` if there was a lock poisoning or the loaded value
+    ///   is too large to cache
+    pub fn get_or_load(&mut self, key: &K) -> Result<Option<V>, CacheError> {
+        match self.begin_load(key)? {
+            ControlFlow::Break(value) => Ok(value),
+            ControlFlow::Continue(loader) => {
+                let value = loader.load(key);
+                self.store_loaded(key, value)
+            }
+        }
+    }
+
+    /// Retrieves the values for several keys, fetching all the misses from
+    /// the loader with a single [`load_many`](CacheLoader::load_many) call.
+    ///
+    /// Each key counts like [`get_or_load`](Self::get_or_load), except that
+    /// the batch counts as one load. Keys found neither in the cache nor by
+    /// the loader are left out of the map. A failed batch, or a key whose
+    /// last load failed within the load error TTL, fails the whole call.
+    pub fn get_or_load_many(&mut self, keys: &[K]) -> Result<HashMap<K, V>, CacheError> {
+        let (mut found, missing) = self.lookup_many(keys)?;
+        if let Some(loader) = self.loader.clone().filter(|_| !missing.is_empty()) {
+            let loaded = loader.load_many(&missing);
+            self.store_loaded_many(missing, loaded, &mut found)?;
+        }
+        Ok(found)
+    }
+
+    /// [Primes](Self::prime) the cache with the values the loader finds for
+    /// `keys`, fetched with a single [`load_many`](CacheLoader::load_many)
+    /// call for the keys not cached yet.
+    ///
+    /// The batch counts as one load, but no key counts as a miss, and keys
+    /// the loader has no value for are not [marked absent](Self::mark_absent).
+    ///
+    /// # Returns
+    /// - `Ok(usize)` with the number of entries primed, 0 without a loader
+    /// - `Err(CacheError::Load)` if the loader failed
+    /// - `Err(CacheError)` if a loaded value is too large to cache
+    pub fn prime_from_loader(&mut self, keys: &[K]) -> Result<usize, CacheError> {
+        let missing = self.uncached(keys);
+        match self.loader.clone().filter(|_| !missing.is_empty()) {
+            Some(loader) => {
+                let loaded = loader.load_many(&missing);
+                self.prime_loaded(missing, loaded)
+            }
+            None => Ok(0),
+        }
+    }
+
+    // The keys to fetch for `prime_from_loader`
+    pub(crate) fn uncached(&self, keys: &[K]) -> Vec<K> {
+        let mut seen = HashSet::with_capacity(keys.len());
+        keys.iter()
+            .filter(|&key| !self.cache.contains_key(key) && seen.insert(key))
+            .cloned()
+            .collect()
+    }
+
+    // Primes the outcome of one `load_many` call for `missing`, in the order
+    // the keys were asked for
+    pub(crate) fn prime_loaded(
+        &mut self,
+        missing: Vec<K>,
+        loaded: Result<HashMap<K, V>, CacheError>,
+    ) -> Result<usize, CacheError> {
+        self.count_load(loaded.is_err());
+        let mut loaded = loaded?;
+        self.prime(
+            missing
+                .into_iter()
+                .filter_map(|key| loaded.remove(&key).map(|value| (key, value))),
+        )
+    }
+
+    /// Reloads entries in the background when they are read less than `ahead`
+    /// before they expire, so a hot key is refreshed before it ever misses.
+    ///
+    /// Refreshing needs a thread to run on, so it only happens in
+    /// [`ConcurrentSieveCache::get_or_load`](crate::ConcurrentSieveCache::get_or_load);
+    /// the current value is served while the reload runs. A reload that fails
+    /// leaves the entry to expire as usual. The reloaded value restarts the
+    /// cache-wide timeouts but keeps a deadline the entry was added with,
+    /// e.g. by [`add_with_ttl`](Self::add_with_ttl). `None` disables
+    /// refresh-ahead.
+    pub fn set_refresh_ahead(&mut self, ahead: Option<Duration>) {
+        self.refresh_ahead = ahead;
+    }
+
+    // Claims a refresh of `key` if one is due and none is running, handing
+    // back the entry's current version and the loader to refresh it with
+    pub(crate) fn begin_refresh(&mut self, key: &K) -> Option<(u64, SharedLoader<K, V>)> {
+        let ahead = self.refresh_ahead?;
+        let loader = self.loader.clone()?;
+        let &id = self.cache.get(key)?;
+        let node = &self.nodes[id];
+        let deadline = node_deadline(node, self.expire_after_write, self.expire_after_access)?;
+        if deadline.saturating_duration_since(self.clock.peek()) >= ahead {
+            return None;
+        }
+        let version = node.version;
+        if !self.refreshing.insert(key.clone()) {
+            return None;
+        }
+        if self.collect_stats {
+            self.stats.refreshes += 1;
+        }
+        Some((version, loader))
+    }
+
+    // Stores the outcome of a refresh of `key` begun at `version`, unless the
+    // entry was written or removed since
+    pub(crate) fn store_refreshed(
+        &mut self,
+        key: &K,
+        version: u64,
+        value: Result<Option<V>, CacheError>,
+    ) -> Result<(), CacheError> {
+        self.refreshing.remove(key);
+        self.count_load(value.is_err());
+        if self.version(key)? != Some(version) {
+            return Ok(());
+        }
+        match value {
+            Ok(Some(value)) => {
+                let expires_at = self.kept_deadline(key);
+                self.add_entry(key.clone(), value, expires_at).map(drop)
+            }
+            Ok(None) => self.mark_absent(key.clone()),
+            Err(_) => Ok(()),
+        }
+    }
+
+    // Looks up every key once, returning the values found and the keys the
+    // cache knows nothing about
+    pub(crate) fn lookup_many(
+        &mut self,
+        keys: &[K],
+    ) -> Result<(HashMap<K, V>, Vec<K>), CacheError> {
+        let mut found = HashMap::with_capacity(keys.len());
+        let mut missing = Vec::new();
+        let mut seen = HashSet::with_capacity(keys.len());
+        for key in keys {
+            if !seen.insert(key) {
+                continue;
+            }
+            match self.get_cached(key)? {
+                Cached::Present(value) => {
+                    found.insert(key.clone(), value);
+                }
+                Cached::Absent => {}
+                Cached::Unknown => {
+                    self.check_load_error(key)?;
+                    missing.push(key.clone());
+                }
+            }
+        }
+        Ok((found, missing))
+    }
+
+    // Caches the outcome of one `load_many` call for `missing` and adds the
+    // current values to `found`
+    pub(crate) fn store_loaded_many(
+        &mut self,
+        missing: Vec<K>,
+        loaded: Result<HashMap<K, V>, CacheError>,
+        found: &mut HashMap<K, V>,
+    ) -> Result<(), CacheError> {
+        self.count_load(loaded.is_err());
+        let mut loaded = match loaded {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                for key in missing {
+                    self.remember_load_error(key, &error);
+                }
+                return Err(error);
+            }
+        };
+        for key in missing {
+            let value = loaded.remove(&key);
+            if let Some(value) = self.cache_loaded(&key, value)? {
+                found.insert(key, value);
+            }
+        }
+        Ok(())
+    }
+
+    // Looks `key` up, handing back the loader to fetch it with if the cache
+    // cannot answer by itself
+    pub(crate) fn begin_load(
+        &mut self,
+        key: &K,
+    ) -> Result<ControlFlow<Option<V>, SharedLoader<K, V>>, CacheError> {
+        Ok(match self.get_cached(key)? {
+            Cached::Present(value) => ControlFlow::Break(Some(value)),
+            Cached::Absent => ControlFlow::Break(None),
+            Cached::Unknown => {
+                self.check_load_error(key)?;
+                match &self.loader {
+                    Some(loader) => ControlFlow::Continue(loader.clone()),
+                    None => ControlFlow::Break(None),
+                }
+            }
+        })
+    }
+
+    // Caches the outcome of one `load` call for `key`
+    pub(crate) fn store_loaded(
+        &mut self,
+        key: &K,
+        value: Result<Option<V>, CacheError>,
+    ) -> Result<Option<V>, CacheError> {
+        self.count_load(value.is_err());
+        match value {
+            Ok(value) => self.cache_loaded(key, value),
+            Err(error) => {
+                self.remember_load_error(key.clone(), &error);
+                Err(error)
+            }
+        }
+    }
+
+    fn count_load(&mut self, failed: bool) {
+        if self.collect_stats {
+            self.stats.loads += 1;
+            if failed {
+                self.stats.load_errors += 1;
+            }
+        }
+    }
+
+    // Fails with the error of a recent load of `key`, if one is remembered
+    pub(crate) fn check_load_error(&mut self, key: &K) -> Result<(), CacheError> {
+        let now = self.clock.peek();
+        let epoch = self.epoch;
+        match self
+            .load_errors
+            .as_mut()
+            .and_then(|errors| errors.get(key, now, epoch))
+        {
+            Some(error) => Err(error.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn remember_load_error(&mut self, key: K, error: &CacheError) {
+        let now = self.clock.peek();
+        if let Some(errors) = &mut self.load_errors {
+            errors.insert(key, error.clone(), now, self.epoch, self.capacity);
+        }
+    }
+
+    // Caches what the loader returned for `key`, unless a value was written
+    // while it loaded, and returns whichever value is now current
+    fn cache_loaded(&mut self, key: &K, value: Option<V>) -> Result<Option<V>, CacheError> {
+        if let Some(current) = self.peek(key)? {
+            return Ok(Some(current));
+        }
+        match value {
+            Some(value) => {
+                let expires_at = self.kept_deadline(key);
+                self.add_entry(key.clone(), value.clone(), expires_at)?;
+                Ok(Some(value))
+            }
+            None => {
+                self.mark_absent(key.clone())?;
+                Ok(None)
+            }
+        }
+    }
+}
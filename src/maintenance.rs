@@ -0,0 +1,67 @@
+//! Periodic housekeeping on a tokio runtime.
+
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+use crate::concurrent::ConcurrentSieveCache;
+use crate::eviction::EvictionPolicy;
+
+/// Stops the maintenance task when dropped, see
+/// [`ConcurrentSieveCache::spawn_maintenance`].
+pub struct MaintenanceGuard {
+    task: JoinHandle<()>,
+}
+
+impl MaintenanceGuard {
+    /// Stops the task. A pass that is already running is not interrupted,
+    /// since it never yields to the runtime.
+    pub fn stop(self) {}
+}
+
+impl Drop for MaintenanceGuard {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl<K, V, P, S> ConcurrentSieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    P: EvictionPolicy<K> + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    /// Spawns a task on `runtime` that calls
+    /// [`SieveCache::run_maintenance`](crate::SieveCache::run_maintenance)
+    /// every `interval`, so expired and invalidated entries are removed and
+    /// statistics are flushed off the request path.
+    ///
+    /// The task only holds a weak reference, so it also ends once the last
+    /// `Arc` to the cache is dropped. A poisoned cache lock ends it as well.
+    pub fn spawn_maintenance(
+        self: &Arc<Self>,
+        runtime: &Handle,
+        interval: Duration,
+    ) -> MaintenanceGuard {
+        let cache = Arc::downgrade(self);
+        let task = runtime.spawn(async move {
+            let mut ticks = time::interval_at(Instant::now() + interval, interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                let Some(cache) = cache.upgrade() else {
+                    return;
+                };
+                if cache.run_maintenance().is_err() {
+                    return;
+                }
+            }
+        });
+        MaintenanceGuard { task }
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crate::sieve::SieveCache;
+use crate::sync::Mutex;
+use crate::types::{CacheError, CacheStats, InsertResult, RemovalCause};
+
+/// Counters for one namespace of a [`NamespacedCache`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NamespaceStats {
+    /// Entries the namespace currently holds
+    pub len: usize,
+    pub hits: usize,
+    pub misses: usize,
+    /// Entries of this namespace evicted to make room, whichever namespace
+    /// needed it
+    pub evictions: usize,
+    pub expirations: usize,
+}
+
+struct Namespaces<N> {
+    stats: HashMap<N, NamespaceStats>,
+    // Maximum number of entries per namespace, for those that have one
+    quotas: HashMap<N, usize>,
+    // Namespace of the entry being inserted, which already counts toward
+    // its quota while room is made for it
+    incoming: Option<N>,
+}
+
+impl<N: Eq + Hash> Namespaces<N> {
+    fn over_quota(&self, namespace: &N) -> bool {
+        let Some(&quota) = self.quotas.get(namespace) else {
+            return false;
+        };
+        let len = self.stats.get(namespace).map_or(0, |stats| stats.len);
+        let incoming = usize::from(self.incoming.as_ref() == Some(namespace));
+        len + incoming > quota
+    }
+
+    // While any namespace is over its quota, only those give up entries
+    fn may_evict_from(&self, namespace: &N) -> bool {
+        self.over_quota(namespace) || !self.quotas.keys().any(|other| self.over_quota(other))
+    }
+}
+
+impl<N> Default for Namespaces<N> {
+    fn default() -> Self {
+        Namespaces {
+            stats: HashMap::new(),
+            quotas: HashMap::new(),
+            incoming: None,
+        }
+    }
+}
+
+/// One cache shared by several subsystems, each working in its own namespace.
+///
+/// Keys only need to be unique within a namespace, and every namespace draws
+/// from the same capacity: SIEVE evicts across namespaces as if they were a
+/// single cache. A namespace can be given a [quota](Self::set_quota) so a
+/// noisy one cannot flush the others' entries. The cache's eviction listener
+/// and interceptor are used for the bookkeeping.
+///
+/// ```
+/// use nitro::NamespacedCache;
+///
+/// let mut cache = NamespacedCache::new(100)?;
+/// cache.add("users", 1, "alice")?;
+/// cache.add("orders", 1, "order #1")?;
+/// assert_eq!(cache.get(&"users", &1)?, Some("alice"));
+///
+/// cache.purge(&"orders")?;
+/// assert_eq!(cache.len(), 1);
+/// # Ok::<(), nitro::CacheError>(())
+/// ```
+pub struct NamespacedCache<N, K, V> {
+    cache: SieveCache<(N, K), V>,
+    namespaces: Arc<Mutex<Namespaces<N>>>,
+}
+
+impl<N, K, V> NamespacedCache<N, K, V>
+where
+    N: Eq + Hash + Clone + Send + 'static,
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    pub fn new(capacity: usize) -> Result<Self, CacheError> {
+        let namespaces: Arc<Mutex<Namespaces<N>>> = Arc::default();
+        let mut cache = SieveCache::new(capacity)?;
+        let removed = namespaces.clone();
+        cache.eviction_listener = Some(Box::new(
+            move |(namespace, _): (N, K), _: V, cause: RemovalCause| {
+                let mut namespaces = removed.lock_unpoisoned();
+                let stats = namespaces.stats.entry(namespace).or_default();
+                match cause {
+                    RemovalCause::Replaced => return,
+                    RemovalCause::Evicted => stats.evictions += 1,
+                    RemovalCause::Expired => stats.expirations += 1,
+                    RemovalCause::Explicit => {}
+                }
+                stats.len -= 1;
+            },
+        ));
+        let quotas = namespaces.clone();
+        cache.eviction_interceptor = Some(Box::new(move |(namespace, _): &(N, K), _: &V| {
+            quotas.lock_unpoisoned().may_evict_from(namespace)
+        }));
+        // Two laps of the hand, enough to reach any entry of a namespace
+        // over quota; past that, SIEVE picks as usual
+        cache.max_eviction_vetoes = capacity.saturating_mul(2);
+        Ok(NamespacedCache { cache, namespaces })
+    }
+
+    /// Retrieves a value from `namespace`.
+    pub fn get(&mut self, namespace: &N, key: &K) -> Result<Option<V>, CacheError> {
+        let value = self.cache.get(&(namespace.clone(), key.clone()))?;
+        let mut namespaces = self.namespaces.lock_unpoisoned();
+        let stats = namespaces.stats.entry(namespace.clone()).or_default();
+        match value {
+            Some(_) => stats.hits += 1,
+            None => stats.misses += 1,
+        }
+        Ok(value)
+    }
+
+    /// Adds a value to `namespace`, evicting from any namespace if the cache
+    /// is full.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the key already existed in the namespace
+    /// - `Ok(false)` if the key was newly inserted
+    /// - `Err(CacheError)` if there was a lock poisoning
+    pub fn add(&mut self, namespace: N, key: K, value: V) -> Result<bool, CacheError> {
+        self.namespaces.lock_unpoisoned().incoming = Some(namespace.clone());
+        let outcome = self.cache.put((namespace.clone(), key), value);
+        let mut namespaces = self.namespaces.lock_unpoisoned();
+        namespaces.incoming = None;
+        let inserted = !matches!(outcome?, InsertResult::Updated | InsertResult::Rejected);
+        if inserted {
+            namespaces.stats.entry(namespace).or_default().len += 1;
+        }
+        Ok(!inserted)
+    }
+
+    /// Limits `namespace` to `share` of the capacity, between 0 and 1.
+    ///
+    /// A namespace may go over its quota while there is room, but once the
+    /// cache is full, evictions take entries from namespaces over their quota
+    /// before touching anyone else's, SIEVE order deciding among them. An
+    /// insert already counts toward its namespace's quota, so a namespace at
+    /// its quota makes room for itself.
+    pub fn set_quota(&mut self, namespace: N, share: f64) {
+        let quota = (self.cache.capacity() as f64 * share.clamp(0.0, 1.0)) as usize;
+        self.namespaces
+            .lock_unpoisoned()
+            .quotas
+            .insert(namespace, quota);
+    }
+
+    /// Lifts the quota of `namespace`.
+    pub fn remove_quota(&mut self, namespace: &N) {
+        self.namespaces.lock_unpoisoned().quotas.remove(namespace);
+    }
+
+    /// The number of entries `namespace` may keep once the cache is full, if
+    /// it has a quota.
+    pub fn quota(&self, namespace: &N) -> Option<usize> {
+        self.namespaces
+            .lock_unpoisoned()
+            .quotas
+            .get(namespace)
+            .copied()
+    }
+
+    /// Removes `key` from `namespace` and hands back its value.
+    pub fn remove(&mut self, namespace: &N, key: &K) -> Result<Option<V>, CacheError> {
+        self.cache.remove(&(namespace.clone(), key.clone()))
+    }
+
+    /// Removes every entry of `namespace`, leaving the others alone.
+    ///
+    /// # Returns
+    /// The number of entries removed
+    pub fn purge(&mut self, namespace: &N) -> Result<usize, CacheError> {
+        let keys: Vec<(N, K)> = self
+            .cache
+            .iter_keys()
+            .filter(|(owner, _)| owner == namespace)
+            .cloned()
+            .collect();
+        for key in &keys {
+            self.cache.delete(key)?;
+        }
+        Ok(keys.len())
+    }
+
+    /// Removes every entry of every namespace.
+    pub fn purge_all(&mut self) {
+        self.cache.purge();
+    }
+
+    /// Number of entries across all namespaces.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// The capacity shared by all namespaces.
+    pub fn capacity(&self) -> usize {
+        self.cache.capacity()
+    }
+
+    /// Number of entries `namespace` holds.
+    pub fn namespace_len(&self, namespace: &N) -> usize {
+        self.namespace_stats(namespace).len
+    }
+
+    /// Counters for `namespace`, all zero for a namespace never used.
+    pub fn namespace_stats(&self, namespace: &N) -> NamespaceStats {
+        let namespaces = self.namespaces.lock_unpoisoned();
+        namespaces.stats.get(namespace).cloned().unwrap_or_default()
+    }
+
+    /// Statistics of the cache as a whole.
+    pub fn get_stats(&self) -> &CacheStats {
+        self.cache.get_stats()
+    }
+}
@@ -0,0 +1,122 @@
+//! Keys known to be absent from the backing store.
+//!
+//! Caching a miss saves going back to the store for a key that does not
+//! exist there, but a key can start existing at any time, so these markers
+//! carry their own, usually short, TTL. Only keys are kept, not values, along
+//! with an optional payload such as the error a failed load returned.
+
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+struct Marker<T> {
+    payload: T,
+    sequence: u64,
+    deadline: Instant,
+    // Cache epoch the marker was set in, see `SieveCache::bump_epoch`
+    epoch: u64,
+}
+
+pub(crate) struct NegativeCache<K, T = ()> {
+    pub(crate) ttl: Duration,
+    // Keys in marking order; entries whose sequence number no longer
+    // matches `markers` were marked again or removed and are skipped
+    order: VecDeque<(K, u64)>,
+    markers: HashMap<K, Marker<T>>,
+    sequence: u64,
+}
+
+impl<K: Eq + Hash + Clone, T> NegativeCache<K, T> {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        NegativeCache {
+            ttl,
+            order: VecDeque::new(),
+            markers: HashMap::new(),
+            sequence: 0,
+        }
+    }
+
+    /// Marks `key` as absent until the TTL elapses, forgetting the oldest
+    /// markers beyond `capacity`.
+    pub(crate) fn insert(&mut self, key: K, payload: T, now: Instant, epoch: u64, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        let Some(deadline) = now.checked_add(self.ttl) else {
+            return;
+        };
+        self.sequence += 1;
+        let marker = Marker {
+            payload,
+            sequence: self.sequence,
+            deadline,
+            epoch,
+        };
+        self.markers.insert(key.clone(), marker);
+        self.order.push_back((key, self.sequence));
+        while self.markers.len() > capacity {
+            self.pop_oldest();
+        }
+        if self.order.len() > capacity * 2 {
+            self.compact();
+        }
+    }
+
+    /// The payload of the marker for `key`, dropping the marker if it is
+    /// stale.
+    pub(crate) fn get<Q>(&mut self, key: &Q, now: Instant, epoch: u64) -> Option<&T>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let marker = self.markers.get(key)?;
+        if now >= marker.deadline || marker.epoch != epoch {
+            self.markers.remove(key);
+            return None;
+        }
+        self.markers.get(key).map(|marker| &marker.payload)
+    }
+
+    /// Whether `key` is marked as absent, dropping its marker if it is stale.
+    pub(crate) fn contains<Q>(&mut self, key: &Q, now: Instant, epoch: u64) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key, now, epoch).is_some()
+    }
+
+    pub(crate) fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.markers.remove(key);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.order.clear();
+        self.markers.clear();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.markers.len()
+    }
+
+    fn pop_oldest(&mut self) {
+        while let Some((key, sequence)) = self.order.pop_front() {
+            if self.markers.get(&key).map(|marker| marker.sequence) == Some(sequence) {
+                self.markers.remove(&key);
+                return;
+            }
+        }
+    }
+
+    fn compact(&mut self) {
+        let markers = &self.markers;
+        self.order.retain(|(key, sequence)| {
+            markers.get(key).map(|marker| marker.sequence) == Some(*sequence)
+        });
+    }
+}
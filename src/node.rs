@@ -1,15 +1,40 @@
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::types::Priority;
+
+// Position of a node in the cache's slab; the map, the list and the slot list
+// all refer to nodes by it. 32 bits keep the links small, so more nodes share
+// a cache line.
+pub(crate) type NodeId = u32;
 
 // Node represents a cache entry in the doubly-linked list
 #[derive(Debug)] // Added Debug derive
 pub(crate) struct Node<K, V> {
     pub(crate) key: K,
+    // Values are stored inline in the node, and nodes inline in the slab, so
+    // small values need no allocation of their own.
     pub(crate) value: V,
-    pub(crate) visited: AtomicBool,
-    // Using raw pointers instead of Box for the linked list
-    pub(crate) next: Option<Arc<Mutex<Node<K, V>>>>,
-    pub(crate) prev: Option<Arc<Mutex<Node<K, V>>>>,
+    visited: AtomicBool,
+    pub(crate) expires_at: Option<Instant>,
+    pub(crate) written_at: Instant,
+    // Nanoseconds between the last write and the last read, so lookups
+    // through a shared reference can move the idle deadline
+    accessed: AtomicU64,
+    // Cache generation of the last write, see `SieveCache::version`
+    pub(crate) version: u64,
+    // Cache epoch the node was inserted in, see `SieveCache::bump_epoch`
+    pub(crate) epoch: u64,
+    // Deadline this node is currently registered under in the timer wheel
+    pub(crate) timer: Option<Instant>,
+    // Position in the cache's dense slot list, used for random sampling
+    pub(crate) slot: usize,
+    // Pinned nodes are never chosen as eviction victims
+    pub(crate) pinned: bool,
+    pub(crate) priority: Priority,
+    // Neighbours towards the tail and towards the head
+    pub(crate) next: Option<NodeId>,
+    pub(crate) prev: Option<NodeId>,
 }
 
 impl<K: Clone, V: Clone> Clone for Node<K, V> {
@@ -17,21 +42,78 @@ impl<K: Clone, V: Clone> Clone for Node<K, V> {
         Node {
             key: self.key.clone(),
             value: self.value.clone(),
-            visited: AtomicBool::new(self.visited.load(Ordering::SeqCst)),
-            next: self.next.clone(),
-            prev: self.prev.clone(),
+            visited: AtomicBool::new(self.is_visited()),
+            expires_at: self.expires_at,
+            written_at: self.written_at,
+            accessed: AtomicU64::new(self.accessed.load(Ordering::Relaxed)),
+            version: self.version,
+            epoch: self.epoch,
+            timer: self.timer,
+            slot: self.slot,
+            pinned: self.pinned,
+            priority: self.priority,
+            next: self.next,
+            prev: self.prev,
         }
     }
 }
 
 impl<K, V> Node<K, V> {
-    pub(crate) fn new(key: K, value: V) -> Self {
+    pub(crate) fn new(key: K, value: V, now: Instant) -> Self {
         Node {
             key,
             value,
             visited: AtomicBool::new(false),
+            expires_at: None,
+            written_at: now,
+            accessed: AtomicU64::new(0),
+            version: 0,
+            epoch: 0,
+            timer: None,
+            slot: 0,
+            pinned: false,
+            priority: Priority::Normal,
             next: None,
             prev: None,
         }
     }
+
+    // The visited bit and the access time only use relaxed atomics. Both are
+    // advisory: nothing else is published through them, and the only
+    // concurrent writers are lookups through `SieveCache::read`, which all
+    // store the same thing. Every other access holds `&mut` on the cache, so
+    // it is already ordered with respect to those readers by whatever
+    // synchronisation handed out the exclusive borrow.
+    pub(crate) fn is_visited(&self) -> bool {
+        self.visited.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn mark_visited(&self) {
+        self.visited.store(true, Ordering::Relaxed);
+    }
+
+    // Sets the visited bit, returning its previous value
+    pub(crate) fn set_visited(&self, visited: bool) -> bool {
+        self.visited.swap(visited, Ordering::Relaxed)
+    }
+
+    pub(crate) fn accessed_at(&self) -> Instant {
+        self.written_at + Duration::from_nanos(self.accessed.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_accessed_at(&self, now: Instant) {
+        let since_write = now.saturating_duration_since(self.written_at).as_nanos();
+        self.accessed
+            .store(since_write.min(u64::MAX as u128) as u64, Ordering::Relaxed);
+    }
+
+    // Marks the node as written, and thereby accessed, at `now`
+    pub(crate) fn set_written_at(&mut self, now: Instant) {
+        self.written_at = now;
+        *self.accessed.get_mut() = 0;
+    }
+
+    pub(crate) fn into_entry(self) -> (K, V) {
+        (self.key, self.value)
+    }
 }
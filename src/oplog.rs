@@ -0,0 +1,328 @@
+//! Append-only operation log, enabled with the `snapshot` feature.
+//!
+//! A log file starts with an 8 byte magic string and a little-endian `u32`
+//! format version, followed by one MessagePack record per write. Records are
+//! only ever appended, so a crash can at worst cut the last one short, and
+//! reading stops before a record that was cut short.
+
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
+
+use crate::concurrent::ConcurrentSieveCache;
+use crate::eviction::EvictionPolicy;
+use crate::persist::{invalid, persist_error};
+use crate::sieve::SieveCache;
+use crate::types::{CacheError, RemovalCause};
+
+const MAGIC: &[u8; 8] = b"NITROLOG";
+const VERSION: u32 = 1;
+
+/// A write recorded in an [`OperationLog`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogRecord<K, V> {
+    /// `value` was written for `key`, to expire after `ttl` if the entry has
+    /// a deadline of its own, e.g. from [`SieveCache::add_with_ttl`]
+    Add {
+        key: K,
+        value: V,
+        ttl: Option<Duration>,
+    },
+    Delete {
+        key: K,
+    },
+    /// Every entry was removed, e.g. by [`SieveCache::purge`]
+    Clear,
+}
+
+// Encodes like `LogRecord` without cloning the key and value
+#[derive(Serialize)]
+pub(crate) enum RecordRef<'a, K, V> {
+    Add {
+        key: &'a K,
+        value: &'a V,
+        ttl: Option<Duration>,
+    },
+    Delete {
+        key: &'a K,
+    },
+    Clear,
+}
+
+/// A file that a cache appends its writes to, see
+/// [`SieveCache::set_operation_log`].
+#[derive(Debug)]
+pub struct OperationLog {
+    file: File,
+    // Length of the file up to the last complete record
+    len: u64,
+    sync: bool,
+}
+
+impl OperationLog {
+    /// Opens the log at `path` for appending, creating it if it does not
+    /// exist.
+    ///
+    /// # Returns
+    /// - `Ok(OperationLog)` positioned after the existing records
+    /// - `Err(CacheError::Persistence)` if the file cannot be opened or is
+    ///   not an operation log in this format version
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, CacheError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(persist_error)?;
+        let mut len = file.metadata().map_err(persist_error)?.len();
+        if len == 0 {
+            file.write_all(MAGIC).map_err(persist_error)?;
+            file.write_all(&VERSION.to_le_bytes())
+                .map_err(persist_error)?;
+            len = (MAGIC.len() + 4) as u64;
+        } else {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes).map_err(persist_error)?;
+            let complete = complete_len(&bytes)?;
+            // Drop a record cut short by a crash, so appends follow a
+            // complete one
+            if complete < bytes.len() {
+                file.set_len(complete as u64).map_err(persist_error)?;
+                len = complete as u64;
+            }
+        }
+        Ok(OperationLog {
+            file,
+            len,
+            sync: false,
+        })
+    }
+
+    /// Flushes each record to disk before the write it records returns, so
+    /// it survives a power loss and not just a crash of the process. Off by
+    /// default, as it makes every write wait for the disk.
+    pub fn sync_each(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Reads every record in the log at `path`, oldest first.
+    ///
+    /// A last record cut short by a crash is left out.
+    pub fn read<K, V>(path: impl AsRef<Path>) -> Result<Vec<LogRecord<K, V>>, CacheError>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(persist_error)?;
+        let complete = complete_len(&bytes)?;
+
+        let mut records = Vec::new();
+        let mut rest = &bytes[12..complete];
+        while !rest.is_empty() {
+            let record = rmp_serde::from_read(&mut rest).map_err(persist_error)?;
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    fn append(&mut self, record: &[u8]) -> Result<(), CacheError> {
+        let written = self.file.write_all(record).and_then(|()| {
+            if self.sync {
+                self.file.sync_data()
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(error) = written {
+            // Drop any part of the record that made it, so later records
+            // still follow a complete one
+            let _ = self.file.set_len(self.len);
+            return Err(persist_error(error));
+        }
+        self.len += record.len() as u64;
+        Ok(())
+    }
+}
+
+// Checks the header and finds where the last complete record ends
+fn complete_len(bytes: &[u8]) -> Result<usize, CacheError> {
+    if bytes.len() < 12 || &bytes[..8] != MAGIC {
+        return Err(invalid("not an operation log"));
+    }
+    let version = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+    if version != VERSION {
+        return Err(invalid(&format!(
+            "unsupported operation log version {}",
+            version
+        )));
+    }
+    let mut rest = &bytes[12..];
+    while !rest.is_empty() {
+        let before = rest;
+        match rmp_serde::from_read::<_, IgnoredAny>(&mut rest) {
+            Ok(_) => {}
+            Err(error) if is_cut_short(&error) => return Ok(bytes.len() - before.len()),
+            Err(error) => return Err(persist_error(error)),
+        }
+    }
+    Ok(bytes.len())
+}
+
+fn is_cut_short(error: &rmp_serde::decode::Error) -> bool {
+    use rmp_serde::decode::Error;
+    match error {
+        Error::InvalidMarkerRead(error) | Error::InvalidDataRead(error) => {
+            error.kind() == std::io::ErrorKind::UnexpectedEof
+        }
+        _ => false,
+    }
+}
+
+type EncodeFn<K, V> = fn(&RecordRef<'_, K, V>) -> Result<Vec<u8>, CacheError>;
+
+pub(crate) struct LogWriter<K, V> {
+    log: OperationLog,
+    // Needs `Serialize`, which the write paths do not require
+    encode: EncodeFn<K, V>,
+    // Set once a record was lost without the error reaching the caller,
+    // after which replaying the log would no longer give back the cache
+    lost: bool,
+}
+
+impl<K, V> LogWriter<K, V> {
+    pub(crate) fn append(&mut self, record: RecordRef<'_, K, V>) -> Result<(), CacheError> {
+        if self.lost {
+            return Err(invalid("operation log is missing a record"));
+        }
+        let record = (self.encode)(&record)?;
+        self.log.append(&record)
+    }
+}
+
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    // Appends `record` to the log, if one is set
+    pub(crate) fn log_operation(&mut self, record: RecordRef<'_, K, V>) -> Result<(), CacheError> {
+        match &mut self.oplog {
+            Some(writer) => writer.append(record),
+            None => Ok(()),
+        }
+    }
+
+    // Logs a change whose caller cannot report a failure. If the record is
+    // lost, every later write fails to log instead
+    pub(crate) fn log_unreported(&mut self, record: RecordRef<'_, K, V>) {
+        if let Some(writer) = &mut self.oplog {
+            if writer.append(record).is_err() {
+                writer.lost = true;
+            }
+        }
+    }
+
+    /// Applies the records in the log at `path` to the cache, oldest first.
+    ///
+    /// Replaying into an empty cache, or into one loaded from a
+    /// [snapshot](Self::load_from) taken when the log was started, rebuilds
+    /// the entries the logging cache held. Lookups, evictions and
+    /// expirations are not logged, so a cache too small for every logged key
+    /// may evict different entries than the original did. An entry's own
+    /// TTL counts again from the replay, while cache-wide timeouts and
+    /// [TTL functions](Self::set_ttl_fn) apply as set on this cache.
+    ///
+    /// Replayed writes do not go to a [write backend](Self::set_write_backend)
+    /// or to this cache's own operation log.
+    ///
+    /// # Returns
+    /// - `Ok(usize)` with the number of records applied
+    /// - `Err(CacheError)` if the log cannot be read or a record cannot be
+    ///   applied, in which case the records before it stay applied
+    pub fn replay(&mut self, path: impl AsRef<Path>) -> Result<usize, CacheError>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        let records = OperationLog::read::<K, V>(path)?;
+        for record in &records {
+            match record {
+                LogRecord::Add { key, value, ttl } => {
+                    let expires_at = ttl.and_then(|ttl| self.now().checked_add(ttl));
+                    self.add_entry(key.clone(), value.clone(), expires_at)?;
+                }
+                LogRecord::Delete { key } => {
+                    self.remove_node(key, RemovalCause::Explicit)?;
+                }
+                LogRecord::Clear => {
+                    self.clear(RemovalCause::Explicit);
+                }
+            }
+        }
+        Ok(records.len())
+    }
+}
+
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Serialize + Eq + Hash + Clone,
+    V: Serialize + Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Appends every write to `log`, replacing any previous log, or stops
+    /// logging with `None`.
+    ///
+    /// Everything that reaches a [write backend](Self::set_write_backend)
+    /// is logged, along with the TTL an entry was written with, and so are
+    /// entries [primed](Self::prime) or [merged](Self::merge) in and every
+    /// [`purge`](Self::purge) or [`drain`](Self::drain). Each write is logged
+    /// before the cache changes, and one that cannot be logged fails with
+    /// [`CacheError::Persistence`], leaving the cache unchanged. A value
+    /// changed through [`get_mut`](Self::get_mut) is logged when the guard
+    /// is dropped, like it reaches the backend then. As `purge` and `drain`
+    /// cannot fail, a clear that cannot be logged makes every later write
+    /// fail to log instead. Values fetched by a [loader](Self::set_loader)
+    /// are not logged, as they can be fetched again.
+    ///
+    /// Between periodic [snapshots](Self::set_auto_snapshot), the log keeps
+    /// every write durable; start a new log after each snapshot and
+    /// [`replay`](Self::replay) it over the snapshot to recover.
+    pub fn set_operation_log(&mut self, log: Option<OperationLog>) {
+        self.oplog = log.map(|log| LogWriter {
+            log,
+            encode: |record| rmp_serde::to_vec(record).map_err(persist_error),
+            lost: false,
+        });
+    }
+}
+
+impl<K, V, P, S> ConcurrentSieveCache<K, V, P, S>
+where
+    K: Serialize + DeserializeOwned + Eq + Hash + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// See [`SieveCache::set_operation_log`].
+    pub fn set_operation_log(&self, log: Option<OperationLog>) -> Result<(), CacheError> {
+        self.inner.write()?.set_operation_log(log);
+        Ok(())
+    }
+
+    /// See [`SieveCache::replay`].
+    pub fn replay(&self, path: impl AsRef<Path>) -> Result<usize, CacheError> {
+        self.inner.write()?.replay(path)
+    }
+}
@@ -0,0 +1,26 @@
+//! Parallel scans over a cache's entries, enabled with the `rayon` feature.
+
+use std::hash::{BuildHasher, Hash};
+
+use rayon::prelude::*;
+
+use crate::eviction::EvictionPolicy;
+use crate::sieve::SieveCache;
+use crate::types::CacheError;
+
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone + Send,
+    V: Clone + Send,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// A parallel iterator over a [snapshot](Self::snapshot) of the entries,
+    /// for scans and aggregations over large caches.
+    ///
+    /// The entries are copied sequentially first; the work done on them is
+    /// then spread over rayon's thread pool.
+    pub fn par_iter(&self) -> Result<rayon::vec::IntoIter<(K, V)>, CacheError> {
+        Ok(self.snapshot()?.collect::<Vec<_>>().into_par_iter())
+    }
+}
@@ -0,0 +1,218 @@
+//! Binary snapshots, enabled with the `snapshot` feature.
+//!
+//! A snapshot file starts with an 8 byte magic string and a little-endian
+//! `u32` format version, followed by the cache's serde image, as written
+//! with the `serde` feature, encoded as MessagePack. Loading checks the
+//! header first, so a file written by an incompatible version is rejected
+//! rather than misread.
+
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hash};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::concurrent::ConcurrentSieveCache;
+use crate::eviction::{EvictionPolicy, Sieve};
+use crate::sieve::SieveCache;
+use crate::types::CacheError;
+
+const MAGIC: &[u8; 8] = b"NITROSNP";
+const VERSION: u32 = 1;
+
+pub(crate) fn persist_error<E: std::error::Error + Send + Sync + 'static>(error: E) -> CacheError {
+    CacheError::Persistence(Arc::new(error))
+}
+
+pub(crate) fn invalid(message: &str) -> CacheError {
+    persist_error(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        message.to_string(),
+    ))
+}
+
+/// When to write snapshots automatically, see
+/// [`SieveCache::set_auto_snapshot`].
+#[derive(Debug, Clone)]
+pub struct AutoSnapshot {
+    path: PathBuf,
+    every: Option<Duration>,
+    after_writes: Option<u64>,
+}
+
+impl AutoSnapshot {
+    /// Snapshots to `path`. Set [`every`](Self::every),
+    /// [`after_writes`](Self::after_writes) or both, as without either no
+    /// snapshot is ever due.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        AutoSnapshot {
+            path: path.into(),
+            every: None,
+            after_writes: None,
+        }
+    }
+
+    /// Snapshots once `interval` has passed since the last snapshot.
+    pub fn every(mut self, interval: Duration) -> Self {
+        self.every = Some(interval);
+        self
+    }
+
+    /// Snapshots once `writes` inserts, updates and removals have happened
+    /// since the last snapshot.
+    pub fn after_writes(mut self, writes: u64) -> Self {
+        self.after_writes = Some(writes.max(1));
+        self
+    }
+}
+
+type SaveFn<K, V, P, S> = fn(&SieveCache<K, V, P, S>, &Path) -> Result<(), CacheError>;
+
+pub(crate) struct SnapshotSchedule<K, V, P, S> {
+    config: AutoSnapshot,
+    // `save_to`, which needs bounds that `run_maintenance` does not have
+    save: SaveFn<K, V, P, S>,
+    last_at: Instant,
+    // Cache generation the last snapshot was taken at
+    last_generation: u64,
+}
+
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    // Writes a snapshot if the auto-snapshot schedule says one is due
+    pub(crate) fn snapshot_if_due(&mut self) -> Result<(), CacheError> {
+        let now = self.clock.peek();
+        let generation = self.generation;
+        let Some(schedule) = &self.auto_snapshot else {
+            return Ok(());
+        };
+        let writes = generation - schedule.last_generation;
+        let config = &schedule.config;
+        let due = writes > 0
+            && (config
+                .every
+                .is_some_and(|every| now - schedule.last_at >= every)
+                || config.after_writes.is_some_and(|after| writes >= after));
+        if !due {
+            return Ok(());
+        }
+        (schedule.save)(self, &config.path)?;
+        if let Some(schedule) = &mut self.auto_snapshot {
+            schedule.last_at = now;
+            schedule.last_generation = generation;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V, S> SieveCache<K, V, Sieve<K>, S>
+where
+    K: Serialize + DeserializeOwned + Eq + Hash + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+    S: BuildHasher + Default,
+{
+    /// Writes snapshots on the schedule in `auto`, or stops with `None`.
+    ///
+    /// Whether a snapshot is due is checked by
+    /// [`run_maintenance`](Self::run_maintenance), typically from the task
+    /// started with
+    /// [`ConcurrentSieveCache::spawn_maintenance`](crate::ConcurrentSieveCache::spawn_maintenance),
+    /// and only a cache that changed since the last snapshot is written.
+    /// Each snapshot replaces the previous file atomically, as with
+    /// [`save_to`](Self::save_to), and a failed one is retried on the next
+    /// pass.
+    pub fn set_auto_snapshot(&mut self, auto: Option<AutoSnapshot>) {
+        let now = self.clock.peek();
+        self.auto_snapshot = auto.map(|config| SnapshotSchedule {
+            config,
+            save: |cache, path| cache.save_to(path),
+            last_at: now,
+            last_generation: self.generation,
+        });
+    }
+
+    /// Writes the entries, their eviction state and the capacity to `path`,
+    /// so a later [`load_from`](Self::load_from) starts out warm.
+    ///
+    /// The snapshot is written to a temporary file next to `path` and moved
+    /// into place once complete, so a crash mid-write leaves any previous
+    /// snapshot intact.
+    pub fn save_to(&self, path: impl AsRef<Path>) -> Result<(), CacheError> {
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let file = File::create(&temp).map_err(persist_error)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(MAGIC).map_err(persist_error)?;
+        writer
+            .write_all(&VERSION.to_le_bytes())
+            .map_err(persist_error)?;
+        rmp_serde::encode::write(&mut writer, self).map_err(persist_error)?;
+        let file = writer
+            .into_inner()
+            .map_err(|e| persist_error(e.into_error()))?;
+        file.sync_all().map_err(persist_error)?;
+        fs::rename(&temp, path).map_err(persist_error)
+    }
+
+    /// Reads a cache written by [`save_to`](Self::save_to).
+    ///
+    /// The cache has the saved capacity and default settings otherwise;
+    /// entries come back without their expiry deadlines.
+    ///
+    /// # Returns
+    /// - `Ok(SieveCache)` with the saved entries in their saved order
+    /// - `Err(CacheError::Persistence)` if the file cannot be read, is not a
+    ///   snapshot, was written in another format version or is corrupt
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Self, CacheError> {
+        let file = File::open(path).map_err(persist_error)?;
+        let mut reader = BufReader::new(file);
+        let mut header = [0; 12];
+        reader.read_exact(&mut header).map_err(persist_error)?;
+        if &header[..8] != MAGIC {
+            return Err(invalid("not a cache snapshot"));
+        }
+        let version = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        if version != VERSION {
+            return Err(invalid(&format!(
+                "unsupported snapshot version {}",
+                version
+            )));
+        }
+        rmp_serde::decode::from_read(reader).map_err(persist_error)
+    }
+}
+
+impl<K, V, S> ConcurrentSieveCache<K, V, Sieve<K>, S>
+where
+    K: Serialize + DeserializeOwned + Eq + Hash + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+    S: BuildHasher + Default,
+{
+    /// See [`SieveCache::save_to`]. Writers wait while the snapshot is
+    /// written.
+    pub fn save_to(&self, path: impl AsRef<Path>) -> Result<(), CacheError> {
+        self.inner.read()?.save_to(path)
+    }
+
+    /// See [`SieveCache::set_auto_snapshot`]. Writers wait while a snapshot
+    /// is written.
+    pub fn set_auto_snapshot(&self, auto: Option<AutoSnapshot>) -> Result<(), CacheError> {
+        self.inner.write()?.set_auto_snapshot(auto);
+        Ok(())
+    }
+
+    /// See [`SieveCache::load_from`].
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Self, CacheError> {
+        SieveCache::load_from(path).map(Self::from)
+    }
+}
@@ -0,0 +1,395 @@
+//! Classic replacement strategies for comparison with [`Sieve`](crate::Sieve).
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+use crate::eviction::{EntryList, EvictionPolicy};
+use crate::types::CacheError;
+
+/// Least recently used: every read or update moves the entry to the front,
+/// and the entry at the back is evicted.
+///
+/// Unlike SIEVE this reorders the list on every hit, which is what makes LRU
+/// more expensive under concurrent reads.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LruPolicy;
+
+impl LruPolicy {
+    pub fn new() -> Self {
+        LruPolicy
+    }
+}
+
+impl<K> EvictionPolicy<K> for LruPolicy {
+    fn on_access(&mut self, key: &K, entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        entries.move_to_newest(key)
+    }
+
+    fn select_victim(&mut self, entries: &mut dyn EntryList<K>) -> Result<Option<K>, CacheError> {
+        oldest_evictable(entries)
+    }
+}
+
+/// First in, first out: evicts the entry that was inserted first, however
+/// often it was read since. Updates do not change an entry's position.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FifoPolicy;
+
+impl FifoPolicy {
+    pub fn new() -> Self {
+        FifoPolicy
+    }
+}
+
+impl<K> EvictionPolicy<K> for FifoPolicy {
+    fn observes_lookups(&self) -> bool {
+        false
+    }
+
+    fn select_victim(&mut self, entries: &mut dyn EntryList<K>) -> Result<Option<K>, CacheError> {
+        oldest_evictable(entries)
+    }
+}
+
+/// CLOCK, also known as second-chance FIFO: the oldest entry is evicted
+/// unless it was used since it was last considered, in which case it loses
+/// its visited bit and goes back to the front of the queue.
+///
+/// The difference to SIEVE is where survivors end up: CLOCK requeues them as
+/// the newest entries, while SIEVE leaves them in place and moves the hand.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClockPolicy;
+
+impl ClockPolicy {
+    pub fn new() -> Self {
+        ClockPolicy
+    }
+}
+
+impl<K> EvictionPolicy<K> for ClockPolicy {
+    fn observes_lookups(&self) -> bool {
+        false
+    }
+
+    fn select_victim(&mut self, entries: &mut dyn EntryList<K>) -> Result<Option<K>, CacheError> {
+        while let Some(key) = entries.oldest()? {
+            if !entries.visited(&key)? && entries.may_evict(&key)? {
+                return Ok(Some(key));
+            }
+            entries.set_visited(&key, false)?;
+            entries.move_to_newest(&key)?;
+        }
+        Ok(None)
+    }
+}
+
+// Walks from the oldest entry towards the newest, wrapping around, until the
+// interceptor lets an entry go
+fn oldest_evictable<K>(entries: &mut dyn EntryList<K>) -> Result<Option<K>, CacheError> {
+    let mut candidate = entries.oldest()?;
+    while let Some(key) = candidate {
+        if entries.may_evict(&key)? {
+            return Ok(Some(key));
+        }
+        candidate = match entries.newer(&key)? {
+            Some(newer) => Some(newer),
+            None => entries.oldest()?,
+        };
+    }
+    Ok(None)
+}
+
+/// Evicts an entry picked at random.
+///
+/// The policy keeps no state and never walks the entry list, so an eviction
+/// costs a single random draw however large the cache is. For enormous caches
+/// the hit rate lost against SIEVE can be worth less than the pointer chasing
+/// saved.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomPolicy;
+
+impl RandomPolicy {
+    pub fn new() -> Self {
+        RandomPolicy
+    }
+}
+
+impl<K> EvictionPolicy<K> for RandomPolicy {
+    fn observes_lookups(&self) -> bool {
+        false
+    }
+
+    fn select_victim(&mut self, entries: &mut dyn EntryList<K>) -> Result<Option<K>, CacheError> {
+        while let Some(key) = entries.random()? {
+            if entries.may_evict(&key)? {
+                return Ok(Some(key));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Least frequently used: evicts the entry with the fewest reads and updates
+/// since it was inserted, breaking ties in favour of the more recently used.
+///
+/// Counts are kept per entry and forgotten when the entry leaves the cache.
+#[derive(Debug, Clone)]
+pub struct LfuPolicy<K> {
+    // Key -> (access count, sequence number of the last access)
+    counts: HashMap<K, (u64, u64)>,
+    // The same entries ordered by count, then by last access
+    order: BTreeMap<(u64, u64), K>,
+    sequence: u64,
+}
+
+impl<K> LfuPolicy<K> {
+    pub fn new() -> Self {
+        LfuPolicy {
+            counts: HashMap::new(),
+            order: BTreeMap::new(),
+            sequence: 0,
+        }
+    }
+}
+
+impl<K> Default for LfuPolicy<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash + Clone> LfuPolicy<K> {
+    /// How often `key` was used while cached, counting its insertion.
+    pub fn frequency(&self, key: &K) -> Option<u64> {
+        self.counts.get(key).map(|&(count, _)| count)
+    }
+
+    fn bump(&mut self, key: &K) {
+        self.sequence += 1;
+        let count = match self.counts.get(key) {
+            Some(&(count, last)) => {
+                self.order.remove(&(count, last));
+                count + 1
+            }
+            None => 1,
+        };
+        self.counts.insert(key.clone(), (count, self.sequence));
+        self.order.insert((count, self.sequence), key.clone());
+    }
+}
+
+impl<K: Eq + Hash + Clone> EvictionPolicy<K> for LfuPolicy<K> {
+    fn on_insert(&mut self, key: &K, _entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        self.bump(key);
+        Ok(())
+    }
+
+    fn on_access(&mut self, key: &K, _entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        self.bump(key);
+        Ok(())
+    }
+
+    fn on_remove(&mut self, key: &K, _entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        if let Some(position) = self.counts.remove(key) {
+            self.order.remove(&position);
+        }
+        Ok(())
+    }
+
+    fn select_victim(&mut self, entries: &mut dyn EntryList<K>) -> Result<Option<K>, CacheError> {
+        // The interceptor's veto budget guarantees a pass eventually succeeds
+        while !self.order.is_empty() {
+            for key in self.order.values() {
+                if entries.may_evict(key)? {
+                    return Ok(Some(key.clone()));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn clear(&mut self) {
+        self.counts.clear();
+        self.order.clear();
+    }
+}
+
+/// The 2Q algorithm known from database buffer pools.
+///
+/// New entries enter a FIFO probation queue (A1in). Entries evicted from it
+/// are remembered by key only in a ghost queue (A1out); a key that comes back
+/// while it is still remembered has proven itself and goes to the main LRU
+/// queue (Am). Hits in A1in do not promote, so a burst of accesses right
+/// after insertion is not mistaken for long-term popularity.
+#[derive(Debug, Clone)]
+pub struct TwoQueuePolicy<K> {
+    a1in: KeyQueue<K>,
+    a1out: KeyQueue<K>,
+    am: KeyQueue<K>,
+    max_a1in: usize,
+    max_a1out: usize,
+    // The victim handed out last, so its removal can be told apart from
+    // explicit deletes
+    victim: Option<K>,
+}
+
+impl<K: Eq + Hash + Clone> TwoQueuePolicy<K> {
+    /// Creates a policy for a cache of `capacity` entries, with A1in sized at
+    /// a quarter of the capacity and A1out remembering half as many keys as
+    /// the cache holds.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_queue_sizes((capacity / 4).max(1), (capacity / 2).max(1))
+    }
+
+    /// Creates a policy with explicit A1in and A1out sizes.
+    pub fn with_queue_sizes(max_a1in: usize, max_a1out: usize) -> Self {
+        TwoQueuePolicy {
+            a1in: KeyQueue::new(),
+            a1out: KeyQueue::new(),
+            am: KeyQueue::new(),
+            max_a1in,
+            max_a1out,
+            victim: None,
+        }
+    }
+
+    /// Whether `key` is cached in the main queue, i.e. was re-requested after
+    /// leaving probation.
+    pub fn is_main(&self, key: &K) -> bool {
+        self.am.contains(key)
+    }
+
+    // First entry in `queue` the interceptor lets go, if any
+    fn first_evictable(
+        queue: &KeyQueue<K>,
+        entries: &mut dyn EntryList<K>,
+    ) -> Result<Option<K>, CacheError> {
+        for key in queue.iter() {
+            if entries.may_evict(key)? {
+                return Ok(Some(key.clone()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<K: Eq + Hash + Clone> EvictionPolicy<K> for TwoQueuePolicy<K> {
+    fn on_insert(&mut self, key: &K, _entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        if self.a1out.remove(key) {
+            self.am.push(key.clone());
+        } else {
+            self.a1in.push(key.clone());
+        }
+        Ok(())
+    }
+
+    fn on_access(&mut self, key: &K, _entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        if self.am.remove(key) {
+            self.am.push(key.clone());
+        }
+        Ok(())
+    }
+
+    fn on_remove(&mut self, key: &K, _entries: &mut dyn EntryList<K>) -> Result<(), CacheError> {
+        let evicted = self.victim.as_ref() == Some(key);
+        if evicted {
+            self.victim = None;
+        }
+        if self.a1in.remove(key) && evicted {
+            self.a1out.push(key.clone());
+            if self.a1out.len() > self.max_a1out {
+                self.a1out.pop();
+            }
+        }
+        self.am.remove(key);
+        Ok(())
+    }
+
+    fn select_victim(&mut self, entries: &mut dyn EntryList<K>) -> Result<Option<K>, CacheError> {
+        // Probation gives way first once it outgrows its share
+        let probation_first = self.a1in.len() > self.max_a1in || self.am.len() == 0;
+        while self.a1in.len() + self.am.len() > 0 {
+            let (first, second) = if probation_first {
+                (&self.a1in, &self.am)
+            } else {
+                (&self.am, &self.a1in)
+            };
+            let victim = match Self::first_evictable(first, entries)? {
+                Some(victim) => Some(victim),
+                None => Self::first_evictable(second, entries)?,
+            };
+            if victim.is_some() {
+                self.victim = victim.clone();
+                return Ok(victim);
+            }
+        }
+        Ok(None)
+    }
+
+    fn clear(&mut self) {
+        self.a1in.clear();
+        self.a1out.clear();
+        self.am.clear();
+        self.victim = None;
+    }
+}
+
+// KeyQueue is an insertion-ordered set of keys with removal from anywhere
+#[derive(Debug, Clone)]
+struct KeyQueue<K> {
+    order: BTreeMap<u64, K>,
+    positions: HashMap<K, u64>,
+    next: u64,
+}
+
+impl<K: Eq + Hash + Clone> KeyQueue<K> {
+    fn new() -> Self {
+        KeyQueue {
+            order: BTreeMap::new(),
+            positions: HashMap::new(),
+            next: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    // Appends `key` at the back
+    fn push(&mut self, key: K) {
+        self.next += 1;
+        self.order.insert(self.next, key.clone());
+        self.positions.insert(key, self.next);
+    }
+
+    // Drops the key at the front
+    fn pop(&mut self) {
+        if let Some((_, key)) = self.order.pop_first() {
+            self.positions.remove(&key);
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> bool {
+        match self.positions.remove(key) {
+            Some(position) => {
+                self.order.remove(&position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Front to back
+    fn iter(&self) -> impl Iterator<Item = &K> {
+        self.order.values()
+    }
+
+    fn clear(&mut self) {
+        self.order.clear();
+        self.positions.clear();
+    }
+}
@@ -0,0 +1,66 @@
+//! Lookups made through a shared reference.
+//!
+//! [`SieveCache::get`](crate::SieveCache::get) only takes `&self`, so all it
+//! can change in place is the entry's visited bit and the atomic hit and miss
+//! counters. Whatever else a lookup feeds, such as the eviction policy, the
+//! admission filter, the ghost list and the removal of an entry found
+//! expired, is queued here and applied by the next call that takes
+//! `&mut self`.
+
+use std::time::Instant;
+
+// Lookups queued beyond this many are only counted as hits or misses, so a
+// cache that is read for a long time without a write does not grow the queue
+// without bound
+const MAX_PENDING: usize = 4096;
+
+// What a queued lookup found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Hit,
+    Miss,
+    // The entry had expired and still has to be removed
+    Expired,
+}
+
+pub(crate) struct PendingLookup<K> {
+    pub(crate) key: K,
+    pub(crate) outcome: Outcome,
+    // Lookups while the cache is warming are left out of the statistics
+    pub(crate) counted: bool,
+}
+
+pub(crate) struct ReadBuffer<K> {
+    lookups: Vec<PendingLookup<K>>,
+    // Time of the latest lookup, which keeps an idle cache from being purged
+    last_read: Option<Instant>,
+}
+
+impl<K> ReadBuffer<K> {
+    pub(crate) fn new() -> Self {
+        ReadBuffer {
+            lookups: Vec::new(),
+            last_read: None,
+        }
+    }
+
+    pub(crate) fn push(&mut self, lookup: PendingLookup<K>) {
+        if self.lookups.len() < MAX_PENDING {
+            self.lookups.push(lookup);
+        }
+    }
+
+    pub(crate) fn read_at(&mut self, now: Instant) {
+        self.last_read = Some(self.last_read.map_or(now, |last| last.max(now)));
+    }
+
+    pub(crate) fn last_read(&self) -> Option<Instant> {
+        self.last_read
+    }
+
+    /// Hands back the queued lookups, oldest first, and the time of the
+    /// latest one, leaving the buffer empty.
+    pub(crate) fn take(&mut self) -> (Vec<PendingLookup<K>>, Option<Instant>) {
+        (std::mem::take(&mut self.lookups), self.last_read.take())
+    }
+}
@@ -0,0 +1,74 @@
+//! Background removal of expired entries.
+
+use std::hash::{BuildHasher, Hash};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::eviction::EvictionPolicy;
+use crate::sieve::SieveCache;
+
+/// Stops the reaper thread when dropped.
+pub struct ReaperHandle {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ReaperHandle {
+    /// Stops the reaper and waits for an in-progress sweep to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        // Dropping the sender wakes the thread immediately
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ReaperHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Spawns a thread that calls [`SieveCache::remove_expired`] every `interval`.
+///
+/// The thread only holds a weak reference, so it also exits once the last
+/// `Arc` to the cache is dropped. A poisoned cache lock stops the reaper.
+pub fn start_reaper<K, V, P, S>(
+    cache: &Arc<Mutex<SieveCache<K, V, P, S>>>,
+    interval: Duration,
+) -> ReaperHandle
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+    P: EvictionPolicy<K> + Send + 'static,
+    S: BuildHasher + Send + 'static,
+{
+    let (stop, stopped) = mpsc::channel::<()>();
+    let cache = Arc::downgrade(cache);
+    let thread = thread::spawn(move || loop {
+        match stopped.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => return,
+        }
+        let Some(cache) = cache.upgrade() else {
+            return;
+        };
+        let Ok(mut cache) = cache.lock() else {
+            return;
+        };
+        if cache.remove_expired().is_err() {
+            return;
+        }
+    });
+    ReaperHandle {
+        stop: Some(stop),
+        thread: Some(thread),
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+/// Receives cache statistics as they happen, e.g. to forward them to an
+/// in-house telemetry system.
+///
+/// Calls are batched: the cache accumulates counts and hands them over once
+/// the configured number of events has been reached, when
+/// [`SieveCache::flush_stats`](crate::SieveCache::flush_stats) is called, and
+/// when the cache is dropped. Every method receives the number of events since
+/// the previous call and is only invoked for non-zero counts.
+pub trait StatsRecorder: Send + Sync {
+    fn record_hits(&self, count: usize) {
+        let _ = count;
+    }
+
+    fn record_misses(&self, count: usize) {
+        let _ = count;
+    }
+
+    fn record_evictions(&self, count: usize) {
+        let _ = count;
+    }
+
+    fn record_expirations(&self, count: usize) {
+        let _ = count;
+    }
+}
+
+// Lets one recorder be shared by several caches
+impl<R: StatsRecorder + ?Sized> StatsRecorder for Arc<R> {
+    fn record_hits(&self, count: usize) {
+        (**self).record_hits(count)
+    }
+
+    fn record_misses(&self, count: usize) {
+        (**self).record_misses(count)
+    }
+
+    fn record_evictions(&self, count: usize) {
+        (**self).record_evictions(count)
+    }
+
+    fn record_expirations(&self, count: usize) {
+        (**self).record_expirations(count)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum StatEvent {
+    Hit,
+    Miss,
+    Eviction,
+    Expiration,
+}
+
+// RecorderBatch buffers events for a StatsRecorder and flushes them once
+// `batch_size` events have accumulated
+pub(crate) struct RecorderBatch {
+    recorder: Box<dyn StatsRecorder>,
+    batch_size: usize,
+    pending: [usize; 4],
+    total: usize,
+}
+
+impl RecorderBatch {
+    pub(crate) fn new(recorder: Box<dyn StatsRecorder>, batch_size: usize) -> Self {
+        RecorderBatch {
+            recorder,
+            batch_size: batch_size.max(1),
+            pending: [0; 4],
+            total: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, event: StatEvent) {
+        self.record_many(event, 1);
+    }
+
+    pub(crate) fn record_many(&mut self, event: StatEvent, count: usize) {
+        self.pending[event as usize] += count;
+        self.total += count;
+        if self.total >= self.batch_size {
+            self.flush();
+        }
+    }
+
+    pub(crate) fn flush(&mut self) {
+        let [hits, misses, evictions, expirations] = std::mem::take(&mut self.pending);
+        self.total = 0;
+        if hits > 0 {
+            self.recorder.record_hits(hits);
+        }
+        if misses > 0 {
+            self.recorder.record_misses(misses);
+        }
+        if evictions > 0 {
+            self.recorder.record_evictions(evictions);
+        }
+        if expirations > 0 {
+            self.recorder.record_expirations(expirations);
+        }
+    }
+}
+
+impl Drop for RecorderBatch {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Rng is a small xorshift64* generator for sampling decisions. It is not
+// suitable for anything security related, but it is fast, needs no external
+// crate, and only takes `&self` so it can be used from shared references.
+pub(crate) struct Rng {
+    state: AtomicU64,
+}
+
+impl Rng {
+    pub(crate) fn new() -> Self {
+        // RandomState is seeded from the OS, which makes a good enough seed
+        let seed = RandomState::new().build_hasher().finish();
+        Self::with_seed(seed)
+    }
+
+    pub(crate) fn with_seed(seed: u64) -> Self {
+        Rng {
+            // xorshift must never start from zero
+            state: AtomicU64::new(seed | 1),
+        }
+    }
+
+    pub(crate) fn next_u64(&self) -> u64 {
+        let mut x = self.state.load(Ordering::Relaxed);
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state.store(x, Ordering::Relaxed);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Returns a value in `0..bound`. `bound` must be non-zero.
+    pub(crate) fn below(&self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    /// Returns a value in `[0, 1)`.
+    pub(crate) fn unit(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,135 @@
+//! Serde support, enabled with the `serde` feature.
+//!
+//! A cache is written as its capacity, its entries from the oldest to the
+//! newest with their visited bits, pins and priorities, and the position of
+//! the SIEVE hand, so a deserialized cache evicts exactly as the original
+//! would have. Expiry deadlines, statistics and settings such as listeners
+//! or loaders are not part of the format.
+
+use std::hash::{BuildHasher, Hash};
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::eviction::Sieve;
+use crate::sieve::SieveCache;
+use crate::types::Priority;
+
+#[derive(Serialize)]
+struct CacheRef<'a, K, V> {
+    capacity: usize,
+    max_scan: Option<usize>,
+    entries: Vec<EntryRef<'a, K, V>>,
+    // Index into `entries`
+    hand: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct EntryRef<'a, K, V> {
+    key: &'a K,
+    value: &'a V,
+    visited: bool,
+    pinned: bool,
+    priority: Priority,
+}
+
+#[derive(Deserialize)]
+struct CacheImage<K, V> {
+    capacity: usize,
+    max_scan: Option<usize>,
+    entries: Vec<EntryImage<K, V>>,
+    hand: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct EntryImage<K, V> {
+    key: K,
+    value: V,
+    visited: bool,
+    pinned: bool,
+    priority: Priority,
+}
+
+impl<K, V, S> Serialize for SieveCache<K, V, Sieve<K>, S>
+where
+    K: Serialize + Eq + Hash + Clone,
+    V: Serialize + Clone,
+    S: BuildHasher,
+{
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let mut entries = Vec::with_capacity(self.size);
+        let mut hand = None;
+        let mut current = self.tail;
+        while let Some(id) = current {
+            let node = &self.nodes[id];
+            if self.policy.hand() == Some(&node.key) {
+                hand = Some(entries.len());
+            }
+            entries.push(EntryRef {
+                key: &node.key,
+                value: &node.value,
+                visited: node.is_visited(),
+                pinned: node.pinned,
+                priority: node.priority,
+            });
+            current = node.prev;
+        }
+        CacheRef {
+            capacity: self.capacity,
+            max_scan: self.policy.max_scan(),
+            entries,
+            hand,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for SieveCache<K, V, Sieve<K>, S>
+where
+    K: Deserialize<'de> + Eq + Hash + Clone,
+    V: Deserialize<'de> + Clone,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let image = CacheImage::<K, V>::deserialize(deserializer)?;
+        if image.entries.len() > image.capacity {
+            return Err(D::Error::custom(format!(
+                "{} entries exceed the capacity of {}",
+                image.entries.len(),
+                image.capacity
+            )));
+        }
+        let policy = match image.max_scan {
+            Some(max_scan) => Sieve::with_max_scan(max_scan),
+            None => Sieve::new(),
+        };
+        let mut cache = SieveCache::with_policy_and_hasher(image.capacity, policy, S::default())
+            .map_err(D::Error::custom)?;
+        let hand = match image.hand {
+            Some(index) => match image.entries.get(index) {
+                Some(entry) => Some(entry.key.clone()),
+                None => return Err(D::Error::custom("hand points past the last entry")),
+            },
+            None => None,
+        };
+
+        let now = cache.touch();
+        for entry in image.entries {
+            if cache.cache.contains_key(&entry.key) {
+                return Err(D::Error::custom("duplicate key"));
+            }
+            let key = entry.key.clone();
+            cache
+                .link_new(entry.key, entry.value, None, now)
+                .map_err(D::Error::custom)?;
+            let id = cache.cache[&key];
+            cache.nodes[id].set_visited(entry.visited);
+            if entry.pinned {
+                cache.pin(&key);
+            }
+            cache.set_priority(&key, entry.priority);
+        }
+        cache.policy.set_hand(hand);
+        Ok(cache)
+    }
+}
@@ -0,0 +1,37 @@
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+use crate::eviction::{EvictionPolicy, Sieve};
+use crate::sieve::{DefaultHashBuilder, SieveCache};
+use crate::types::CacheError;
+
+/// A cache that stores values behind [`Arc`], so `V` need not implement
+/// `Clone` and a hit hands out another reference instead of a deep copy.
+///
+/// Created with [`SieveCache::new_shared`]; every other method works as on
+/// any cache, with `Arc<V>` as the value type.
+pub type SharedSieveCache<K, V, P = Sieve<K>, S = DefaultHashBuilder> = SieveCache<K, Arc<V>, P, S>;
+
+impl<K, V> SieveCache<K, Arc<V>>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Creates a cache that stores values behind [`Arc`], see
+    /// [`SharedSieveCache`].
+    pub fn new_shared(capacity: usize) -> Result<Self, CacheError> {
+        Self::new(capacity)
+    }
+}
+
+impl<K, V, P, S> SieveCache<K, Arc<V>, P, S>
+where
+    K: Eq + Hash + Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Wraps `value` in an [`Arc`] and adds it, see [`add`](Self::add).
+    #[must_use = "The returned value indicates whether the key already existed"]
+    pub fn add_shared(&mut self, key: K, value: V) -> Result<bool, CacheError> {
+        self.add(key, Arc::new(value))
+    }
+}
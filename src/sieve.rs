@@ -1,24 +1,143 @@
-use std::collections::HashMap;
+use std::borrow::Borrow;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::Debug;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
 use std::marker::PhantomData;
-use std::sync::atomic::Ordering;
-use std::sync::{Arc, Mutex};
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::eviction::EvictionPolicy;
-use crate::iter::CacheIterator;
+use crate::admission::TinyLfu;
+use crate::advisor::ReuseTracker;
+use crate::backend::Backend;
+use crate::builder::SieveCacheBuilder;
+use crate::clock::{Clock, ClockJumpPolicy, SystemClock, TimeBasis};
+use crate::entry::{Entry, OccupiedEntry, VacantEntry};
+use crate::eviction::{EvictionPolicy, Sieve};
+use crate::expiry::{TimerWheel, DEFAULT_TICK};
+use crate::ghost::{AdaptiveCapacity, GhostList};
+use crate::guard::{EntryGuard, ValueGuard, ValueMut, WriteHook};
+use crate::history::{StatsBucket, StatsHistory, StatsPeriod};
+use crate::iter::{CacheIterator, Drain, Keys, Snapshot};
+use crate::key_index::{is_empty_range, KeyIndex};
 use crate::linked_list::LinkedListOps;
-use crate::node::Node;
-use crate::types::{CacheError, CacheStats};
-
-pub struct SieveCache<K, V> {
-    pub(crate) cache: HashMap<K, Arc<Mutex<Node<K, V>>>>,
-    pub(crate) head: Option<Arc<Mutex<Node<K, V>>>>,
-    pub(crate) tail: Option<Arc<Mutex<Node<K, V>>>>,
-    pub(crate) hand: Option<Arc<Mutex<Node<K, V>>>>,
+use crate::loader::SharedLoader;
+use crate::negative::NegativeCache;
+use crate::node::{Node, NodeId};
+#[cfg(feature = "snapshot")]
+use crate::oplog::{LogWriter, RecordRef};
+#[cfg(feature = "snapshot")]
+use crate::persist::SnapshotSchedule;
+use crate::read_buffer::{Outcome, PendingLookup, ReadBuffer};
+use crate::recorder::{RecorderBatch, StatEvent};
+use crate::rng::Rng;
+use crate::slab::{self, Slab};
+use crate::sync::Mutex;
+use crate::types::{
+    CacheError, CacheStats, Cached, ClassStats, ConflictPolicy, InsertResult, Priority,
+    RemovalCause, StatsSnapshot,
+};
+
+/// The hasher a cache uses unless it is built with
+/// [`with_hasher`](SieveCache::with_hasher): aHash with the `ahash` feature,
+/// the standard library's SipHash otherwise.
+#[cfg(feature = "ahash")]
+pub type DefaultHashBuilder = ahash::RandomState;
+/// The hasher a cache uses unless it is built with
+/// [`with_hasher`](SieveCache::with_hasher): aHash with the `ahash` feature,
+/// the standard library's SipHash otherwise.
+#[cfg(not(feature = "ahash"))]
+pub type DefaultHashBuilder = std::collections::hash_map::RandomState;
+
+/// Decides whether an unvisited entry may be evicted; returning `false` vetoes it.
+pub type EvictionInterceptor<K, V> = Box<dyn Fn(&K, &V) -> bool + Send + Sync>;
+
+/// Measures a value, in whatever unit the configured maximum uses.
+pub type SizeFn<V> = Box<dyn Fn(&V) -> usize + Send + Sync>;
+
+/// Receives every entry that leaves the cache together with the reason.
+pub type EvictionListener<K, V> = Box<dyn Fn(K, V, RemovalCause) + Send + Sync>;
+
+/// Names the class a key belongs to for per-class statistics.
+pub type KeyClassifier<K> = Box<dyn Fn(&K) -> &'static str + Send + Sync>;
+
+/// Derives an entry's time to live from its key and value.
+pub type TtlFn<K, V> = Box<dyn Fn(&K, &V) -> Option<Duration> + Send + Sync>;
+
+pub struct SieveCache<K, V, P = Sieve<K>, S = DefaultHashBuilder> {
+    pub(crate) cache: HashMap<K, NodeId, S>,
+    // Every entry lives here; the map and the list refer to it by position
+    pub(crate) nodes: Slab<Node<K, V>>,
+    pub(crate) head: Option<NodeId>,
+    pub(crate) tail: Option<NodeId>,
     pub(crate) size: usize,
+    // Number of nodes with their pinned flag set
+    pub(crate) pinned: usize,
+    // Number of nodes at each priority, indexed by `Priority as usize`
+    pub(crate) priorities: [usize; 3],
     pub(crate) capacity: usize,
     pub(crate) stats: CacheStats,
+    pub(crate) expire_after_write: Option<Duration>,
+    pub(crate) expire_after_access: Option<Duration>,
+    pub(crate) eviction_interceptor: Option<EvictionInterceptor<K, V>>,
+    pub(crate) max_eviction_vetoes: usize,
+    // Entries evicted at once when an insert finds the cache full
+    pub(crate) eviction_batch: usize,
+    pub(crate) timers: TimerWheel<K>,
+    // Set when the cache-wide timeouts change, so every entry's timer is rebuilt
+    pub(crate) timers_stale: bool,
+    pub(crate) clock: TimeBasis,
+    pub(crate) history: StatsHistory,
+    pub(crate) eviction_listener: Option<EvictionListener<K, V>>,
+    pub(crate) ttl_fn: Option<TtlFn<K, V>>,
+    pub(crate) loader: Option<SharedLoader<K, V>>,
+    pub(crate) refresh_ahead: Option<Duration>,
+    // Keys with a refresh-ahead in progress
+    pub(crate) refreshing: HashSet<K>,
+    // Store that writes go through to, see `set_write_backend`
+    pub(crate) backend: Option<Backend<K, V>>,
+    pub(crate) max_value_size: Option<(usize, SizeFn<V>)>,
+    // Every node in no particular order, so entries can be picked at random
+    pub(crate) slots: Vec<NodeId>,
+    pub(crate) rng: Rng,
+    pub(crate) recorder: Option<RecorderBatch>,
+    pub(crate) collect_stats: bool,
+    pub(crate) classifier: Option<KeyClassifier<K>>,
+    pub(crate) class_stats: HashMap<&'static str, ClassStats>,
+    // While warming, lookups through `get` report misses so a cold cache does
+    // not skew metrics
+    pub(crate) warming: bool,
+    pub(crate) max_idle: Option<Duration>,
+    pub(crate) last_activity: Instant,
+    pub(crate) policy: P,
+    pub(crate) admission: Option<TinyLfu>,
+    pub(crate) reuse: Option<ReuseTracker>,
+    // Bumped on every insert, update and removal, so a copy taken earlier can
+    // tell whether it is still current
+    pub(crate) generation: u64,
+    // Entries inserted in an earlier epoch read as expired
+    pub(crate) epoch: u64,
+    // Epoch up to which `run_maintenance` removed the stale entries
+    pub(crate) swept_epoch: u64,
+    #[cfg(feature = "snapshot")]
+    pub(crate) auto_snapshot: Option<SnapshotSchedule<K, V, P, S>>,
+    #[cfg(feature = "snapshot")]
+    pub(crate) oplog: Option<LogWriter<K, V>>,
+    pub(crate) ghosts: Option<GhostList<K>>,
+    // Keys known to be absent, see `set_negative_ttl`
+    pub(crate) negatives: Option<NegativeCache<K>>,
+    // Recent loader failures, see `set_load_error_ttl`
+    pub(crate) load_errors: Option<NegativeCache<K, CacheError>>,
+    pub(crate) adaptive: Option<AdaptiveCapacity>,
+    // Ordered copy of the keys, see `enable_key_index`
+    pub(crate) key_index: Option<Box<dyn KeyIndex<K> + Send + Sync>>,
+    // Lookups through `get`, folded into `stats` by the next `&mut` call
+    pub(crate) read_hits: AtomicUsize,
+    pub(crate) read_misses: AtomicUsize,
+    pub(crate) read_anomalies: AtomicUsize,
+    // What those lookups leave for the next `&mut` call to apply
+    pub(crate) reads: Mutex<ReadBuffer<K>>,
 }
 
 impl<K, V> SieveCache<K, V>
@@ -31,61 +150,1111 @@ where
     // The size field keeps track of the number of elements in the cache, and the capacity field
     // specifies the maximum number of elements that the cache can hold.
     pub fn new(capacity: usize) -> Result<Self, CacheError> {
-        if capacity < 1 {
-            return Err(CacheError::CapacityError(
-                "Cache capacity cannot be zero".to_string(),
-            ));
+        Self::with_clock(capacity, SystemClock)
+    }
+
+    /// Returns a builder for configuring every aspect of a cache in one place.
+    pub fn builder() -> SieveCacheBuilder<K, V> {
+        SieveCacheBuilder::new()
+    }
+
+    /// Creates a cache that measures TTLs and timeouts against `clock`.
+    pub fn with_clock(capacity: usize, clock: impl Clock + 'static) -> Result<Self, CacheError> {
+        Self::with_shared_clock(
+            capacity,
+            Arc::new(clock),
+            Sieve::new(),
+            DefaultHashBuilder::default(),
+        )
+    }
+}
+
+impl<K, V, S> SieveCache<K, V, Sieve<K>, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    /// Creates a cache that hashes keys with `hasher`, like
+    /// [`HashMap::with_hasher`], e.g. for deterministic or faster hashing.
+    pub fn with_hasher(capacity: usize, hasher: S) -> Result<Self, CacheError> {
+        Self::with_shared_clock(capacity, Arc::new(SystemClock), Sieve::new(), hasher)
+    }
+
+    /// Lists the entries in the order SIEVE would evict them if nothing else
+    /// happened in the meantime, starting with the next victim.
+    ///
+    /// The hand evicts unvisited entries as it reaches them and clears the
+    /// visited bit of the others, which then go in its second round. The
+    /// entries are cloned up front, so the preview does not change the cache.
+    /// A [scan bound](Sieve::with_max_scan) is not taken into account.
+    pub fn iter_by_eviction_order(&self) -> Result<impl Iterator<Item = (K, V)>, CacheError> {
+        let start = match self.policy.hand().and_then(|hand| self.cache.get(hand)) {
+            Some(&id) => Some(id),
+            None => self.tail,
+        };
+        let mut first_round = Vec::with_capacity(self.size);
+        let mut second_round = Vec::new();
+        let mut current = start;
+        while let Some(id) = current {
+            let node = &self.nodes[id];
+            let entry = (node.key.clone(), node.value.clone());
+            if node.is_visited() {
+                second_round.push(entry);
+            } else {
+                first_round.push(entry);
+            }
+            // The hand moves towards the head and wraps around to the tail
+            current = node.prev.or(self.tail);
+            if current == start {
+                break;
+            }
         }
+        Ok(first_round.into_iter().chain(second_round))
+    }
+}
+
+impl<K, V, P> SieveCache<K, V, P>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+{
+    /// Creates a cache that evicts according to `policy` instead of SIEVE.
+    pub fn with_policy(capacity: usize, policy: P) -> Result<Self, CacheError> {
+        Self::with_policy_and_hasher(capacity, policy, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Creates a cache that evicts according to `policy` and hashes keys with
+    /// `hasher`.
+    pub fn with_policy_and_hasher(
+        capacity: usize,
+        policy: P,
+        hasher: S,
+    ) -> Result<Self, CacheError> {
+        Self::with_shared_clock(capacity, Arc::new(SystemClock), policy, hasher)
+    }
+
+    pub(crate) fn with_shared_clock(
+        capacity: usize,
+        clock: Arc<dyn Clock>,
+        policy: P,
+        hasher: S,
+    ) -> Result<Self, CacheError> {
+        check_capacity(capacity)?;
+        let clock = TimeBasis::new(clock);
+        let (start, _) = clock.now();
         Ok(SieveCache {
-            cache: HashMap::with_capacity(capacity),
+            cache: HashMap::with_capacity_and_hasher(capacity, hasher),
+            nodes: Slab::with_capacity(capacity),
             head: None,
             tail: None,
-            hand: None,
             size: 0,
+            pinned: 0,
+            priorities: [0; 3],
             capacity,
-            stats: CacheStats { hits: 0, misses: 0 },
+            stats: CacheStats::default(),
+            expire_after_write: None,
+            expire_after_access: None,
+            eviction_interceptor: None,
+            max_eviction_vetoes: 0,
+            eviction_batch: 1,
+            timers: TimerWheel::new(start, DEFAULT_TICK),
+            timers_stale: false,
+            clock,
+            history: StatsHistory::new(),
+            eviction_listener: None,
+            ttl_fn: None,
+            loader: None,
+            refresh_ahead: None,
+            refreshing: HashSet::new(),
+            backend: None,
+            max_value_size: None,
+            slots: Vec::with_capacity(capacity),
+            rng: Rng::new(),
+            recorder: None,
+            collect_stats: true,
+            classifier: None,
+            class_stats: HashMap::new(),
+            warming: false,
+            max_idle: None,
+            last_activity: start,
+            policy,
+            admission: None,
+            reuse: None,
+            generation: 0,
+            epoch: 0,
+            swept_epoch: 0,
+            #[cfg(feature = "snapshot")]
+            auto_snapshot: None,
+            #[cfg(feature = "snapshot")]
+            oplog: None,
+            ghosts: None,
+            negatives: None,
+            load_errors: None,
+            adaptive: None,
+            key_index: None,
+            read_hits: AtomicUsize::new(0),
+            read_misses: AtomicUsize::new(0),
+            read_anomalies: AtomicUsize::new(0),
+            reads: Mutex::new(ReadBuffer::new()),
         })
     }
 
     /// Retrieves a value from the cache if it exists.
     ///
+    /// Takes `&self`, so a cache behind an `Arc` can serve concurrent readers.
+    /// The entry is marked as visited and the hit or miss is counted right
+    /// away. Whatever else the lookup affects, such as the eviction policy,
+    /// the admission filter, the ghost list, per-class statistics and the
+    /// removal of an entry found expired, is applied by the next call that
+    /// takes `&mut self`, and [`get_stats`](Self::get_stats) counts the lookup
+    /// from then on. Expired entries read as misses. While the cache is
+    /// [warming](Self::is_warming), every lookup returns `None` and is left
+    /// out of the statistics.
+    ///
+    /// The key may be any borrowed form of `K`, e.g. `&str` for `String`
+    /// keys. It is only converted to an owned `K` on a miss, and only when
+    /// something besides the hit and miss counters, such as a
+    /// [key classifier](SieveCacheBuilder::key_classifier), takes note of
+    /// misses.
+    ///
     /// # Returns
     /// - `Ok(Some(V))` if the key exists
-    /// - `Ok(None)` if the key doesn't exist
-    /// - `Err(CacheError)` if there was a lock poisoning
-    pub fn get(&mut self, key: &K) -> Result<Option<V>, CacheError> {
-        if let Some(node) = self.cache.get_mut(key) {
-            let guard = node
-                .lock()
-                .map_err(|e| CacheError::LockError(e.to_string()))?;
-            guard.visited.store(true, Ordering::SeqCst);
-            self.stats.hits += 1;
-            Ok(Some(guard.value.clone()))
+    /// - `Ok(None)` if the key doesn't exist or has expired
+    pub fn get<Q>(&self, key: &Q) -> Result<Option<V>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let (now, anomaly) = self.clock.now();
+        if anomaly {
+            self.read_anomalies.fetch_add(1, Ordering::Relaxed);
+        }
+        // A cache idle for too long is purged by the next `&mut` call, so it
+        // holds nothing until then
+        let idle = self.idle_at(now);
+        let found = self.cache.get_key_value(key).filter(|_| !idle);
+        let (value, outcome) = match found.map(|(_, &id)| &self.nodes[id]) {
+            Some(node) if self.is_node_expired(node, now) => (None, Outcome::Expired),
+            Some(node) => {
+                node.set_accessed_at(now);
+                node.mark_visited();
+                (Some(node.value.clone()), Outcome::Hit)
+            }
+            None => (None, Outcome::Miss),
+        };
+        self.queue_lookup(key, found.map(|(cached, _)| cached), outcome, now, idle);
+        if self.warming {
+            return Ok(None);
+        }
+        if self.collect_stats {
+            let counter = match value {
+                Some(_) => &self.read_hits,
+                None => &self.read_misses,
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(value)
+    }
+
+    // Queues what a lookup through `get` leaves for the next `&mut` call
+    fn queue_lookup<Q>(
+        &self,
+        key: &Q,
+        cached: Option<&K>,
+        outcome: Outcome,
+        now: Instant,
+        idle: bool,
+    ) where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + ?Sized,
+    {
+        let counted = !self.warming;
+        let queued = match outcome {
+            Outcome::Hit => self.policy.observes_lookups() || (counted && self.observes_lookups()),
+            Outcome::Miss => counted && self.observes_lookups(),
+            Outcome::Expired => true,
+        };
+        if !queued && self.max_idle.is_none() {
+            return;
+        }
+        // The buffer is consistent after every push, so a panic elsewhere
+        // cannot leave it half-updated
+        let mut reads = self.reads.lock_unpoisoned();
+        if !idle {
+            reads.read_at(now);
+        }
+        if queued {
+            let key = cached.cloned().unwrap_or_else(|| key.to_owned());
+            reads.push(PendingLookup {
+                key,
+                outcome,
+                counted,
+            });
+        }
+    }
+
+    // Whether the cache sat idle past `max_idle`, counting lookups not
+    // folded in yet
+    fn idle_at(&self, now: Instant) -> bool {
+        let Some(max_idle) = self.max_idle else {
+            return false;
+        };
+        let last_read = self.reads.lock_unpoisoned().last_read();
+        let last = last_read.map_or(self.last_activity, |at| at.max(self.last_activity));
+        now.saturating_duration_since(last) >= max_idle
+    }
+
+    // Whether anything besides the policy and the hit and miss counters
+    // takes note of lookups
+    fn observes_lookups(&self) -> bool {
+        self.admission.is_some()
+            || self.reuse.is_some()
+            || self.ghosts.is_some()
+            || self.adaptive.is_some()
+            || (self.collect_stats && self.classifier.is_some())
+    }
+
+    /// Retrieves the values for several keys at once, in the order given.
+    ///
+    /// Each key counts like a [`get`](Self::get), but the clock is read and
+    /// the statistics history updated once for the whole batch.
+    pub fn get_many(&mut self, keys: &[K]) -> Result<Vec<Option<V>>, CacheError> {
+        let now = self.touch();
+        self.history.observe(now, &self.stats, self.size);
+        keys.iter().map(|key| self.lookup(key, now)).collect()
+    }
+
+    /// Records that the backing store has no value for `key`, so
+    /// [`get_cached`](Self::get_cached) can answer for it without going back
+    /// to the store until the [negative TTL](Self::set_negative_ttl) elapses.
+    ///
+    /// Any cached entry for `key` is removed. Adding a value for the key
+    /// clears the marker. Without negative caching enabled, only the removal
+    /// happens.
+    pub fn mark_absent(&mut self, key: K) -> Result<(), CacheError> {
+        let now = self.touch();
+        self.remove_node(&key, RemovalCause::Explicit)?;
+        if let Some(negatives) = &mut self.negatives {
+            negatives.insert(key, (), now, self.epoch, self.capacity);
+            self.stats.negative_entries = negatives.len();
+        }
+        Ok(())
+    }
+
+    /// Looks up `key`, telling a key known to be absent apart from one the
+    /// cache knows nothing about.
+    ///
+    /// A value or a lookup that finds nothing counts like [`get`](Self::get).
+    /// A key marked with [`mark_absent`](Self::mark_absent) counts as a
+    /// [`negative_hit`](CacheStats::negative_hits) rather than a miss.
+    /// Plain `get` ignores the markers.
+    pub fn get_cached(&mut self, key: &K) -> Result<Cached<V>, CacheError> {
+        let now = self.clock.peek();
+        let epoch = self.epoch;
+        if let Some(negatives) = &mut self.negatives {
+            let absent = negatives.contains(key, now, epoch);
+            self.stats.negative_entries = negatives.len();
+            if absent {
+                if self.collect_stats {
+                    self.stats.negative_hits += 1;
+                }
+                return Ok(Cached::Absent);
+            }
+        }
+        let now = self.touch();
+        self.history.observe(now, &self.stats, self.size);
+        Ok(match self.lookup(key, now)? {
+            Some(value) => Cached::Present(value),
+            None => Cached::Unknown,
+        })
+    }
+
+    /// Returns a guard that derefs to the value for `key`, so it can be read
+    /// without being cloned.
+    ///
+    /// The lookup counts like [`get`](Self::get). The cache stays borrowed
+    /// while the guard is alive.
+    pub fn get_ref<Q>(&mut self, key: &Q) -> Result<Option<ValueGuard<'_, K, V>>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let now = self.touch();
+        self.history.observe(now, &self.stats, self.size);
+        if self.lookup_with(key, now, |_| ())?.is_none() {
+            return Ok(None);
+        }
+        // An adaptive capacity may have shrunk the cache while counting the hit
+        let Some(&id) = self.cache.get(key) else {
+            return Ok(None);
+        };
+        Ok(Some(ValueGuard {
+            node: &self.nodes[id],
+        }))
+    }
+
+    fn lookup<Q>(&mut self, key: &Q, now: Instant) -> Result<Option<V>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.lookup_with(key, now, V::clone)
+    }
+
+    // Counts a lookup of `key`, reading the value with `read` on a hit
+    fn lookup_with<Q, R>(
+        &mut self,
+        key: &Q,
+        now: Instant,
+        read: impl FnOnce(&V) -> R,
+    ) -> Result<Option<R>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if let Some(&id) = self.cache.get(key) {
+            let node = &self.nodes[id];
+            if self.is_node_expired(node, now) {
+                self.expire(key)?;
+                if !self.warming {
+                    self.record_lookup(key, false);
+                }
+                return Ok(None);
+            }
+            node.set_accessed_at(now);
+            node.mark_visited();
+            let value = read(&node.value);
+            self.with_cached_key(key, |policy, entries, key| policy.on_access(key, entries))
+                .transpose()?;
+            if self.warming {
+                return Ok(None);
+            }
+            self.record_lookup(key, true);
+            Ok(Some(value))
         } else {
-            self.stats.misses += 1;
+            if !self.warming {
+                self.record_lookup(key, false);
+            }
             Ok(None)
         }
     }
 
+    /// Returns a guard through which the value for `key` can be changed in
+    /// place, instead of cloning it out and adding it back.
+    ///
+    /// The lookup counts like [`get`](Self::get). When the guard is dropped
+    /// the entry is treated as freshly written: its write time and any
+    /// [TTL function](Self::set_ttl_fn) deadline are renewed, and the value
+    /// goes to the [write backend](Self::set_write_backend). If the backend
+    /// fails then, the entry expires at once; use [`modify`](Self::modify)
+    /// to see the error.
+    pub fn get_mut(&mut self, key: &K) -> Result<Option<ValueMut<'_, K, V>>, CacheError> {
+        let now = self.touch();
+        if self.is_expired(key, now) {
+            self.expire(key)?;
+        }
+        let Some(&id) = self.cache.get(key) else {
+            self.record_lookup(key, false);
+            return Ok(None);
+        };
+        self.nodes[id].mark_visited();
+        self.with_entries(|policy, entries| policy.on_access(key, entries))?;
+        self.record_lookup(key, true);
+
+        // Borrow the fields the guard updates separately from the slab that
+        // holds the node
+        let SieveCache {
+            cache,
+            nodes,
+            timers,
+            generation,
+            ttl_fn,
+            expire_after_write,
+            expire_after_access,
+            backend,
+            #[cfg(feature = "snapshot")]
+            oplog,
+            ..
+        } = self;
+        // An adaptive capacity may have shrunk the cache while counting the hit
+        let Some(&id) = cache.get(key) else {
+            return Ok(None);
+        };
+        let node = &mut nodes[id];
+        *generation += 1;
+        node.version = *generation;
+        #[cfg(feature = "snapshot")]
+        let logging = oplog.is_some();
+        #[cfg(not(feature = "snapshot"))]
+        let logging = false;
+        let write = (backend.is_some() || logging).then(|| {
+            Box::new(move |key: &K, value: &V, expires_at: Option<Instant>| {
+                #[cfg(feature = "snapshot")]
+                if let Some(writer) = oplog {
+                    let ttl = expires_at.map(|at| at.saturating_duration_since(now));
+                    writer.append(RecordRef::Add { key, value, ttl })?;
+                }
+                #[cfg(not(feature = "snapshot"))]
+                let _ = expires_at;
+                match backend {
+                    Some(backend) => backend.put(key, Some(value), now),
+                    None => Ok(()),
+                }
+            }) as WriteHook<'_, K, V>
+        });
+        Ok(Some(ValueMut {
+            node,
+            timers,
+            ttl_fn: ttl_fn.as_ref(),
+            expire_after_write: *expire_after_write,
+            expire_after_access: *expire_after_access,
+            now,
+            write,
+            committed: false,
+        }))
+    }
+
+    /// Changes the value for `key` in place with `f`.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the key was cached and `f` ran
+    /// - `Ok(false)` if the key was absent or expired
+    /// - `Err(CacheError)` if the write backend failed to store the new
+    ///   value, which expires the entry
+    pub fn modify<F: FnOnce(&mut V)>(&mut self, key: &K, f: F) -> Result<bool, CacheError> {
+        match self.get_mut(key)? {
+            Some(mut value) => {
+                f(&mut value);
+                value.commit()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Replaces the value for `key` with `new`, but only if it currently
+    /// equals `expected`.
+    ///
+    /// The swap counts as a write, exactly like [`add`](Self::add). Lets a
+    /// wrapper that shares the cache read a value, compute a new one without
+    /// holding its lock, and write it back only if nobody got there first.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the value matched and was replaced
+    /// - `Ok(false)` if the key was absent, expired or held another value
+    /// - `Err(CacheError)` if the write backend failed to store the new value
+    pub fn compare_and_swap(&mut self, key: &K, expected: &V, new: V) -> Result<bool, CacheError>
+    where
+        V: PartialEq,
+    {
+        self.replace_if(key, new, |node| node.value == *expected)
+    }
+
+    /// Like [`compare_and_swap`](Self::compare_and_swap), but compares the
+    /// entry's [`version`](Self::version) instead of its value, so `V` need
+    /// not be comparable and a value that changed and changed back is still
+    /// noticed.
+    pub fn compare_and_swap_version(
+        &mut self,
+        key: &K,
+        expected: u64,
+        new: V,
+    ) -> Result<bool, CacheError> {
+        self.replace_if(key, new, |node| node.version == expected)
+    }
+
+    /// Returns the version of the entry for `key`, without counting as a use
+    /// of it.
+    ///
+    /// Every write to an entry gives it a new version, and versions are never
+    /// reused within a cache, even across removing and re-adding a key.
+    /// Returns `None` if the key is absent or expired.
+    pub fn version(&self, key: &K) -> Result<Option<u64>, CacheError> {
+        let Some(&id) = self.cache.get(key) else {
+            return Ok(None);
+        };
+        let node = &self.nodes[id];
+        if self.is_node_expired(node, self.clock.peek()) {
+            return Ok(None);
+        }
+        Ok(Some(node.version))
+    }
+
+    /// Retrieves the value for `key` together with its [`version`](Self::version),
+    /// counting as a use of it like [`get`](Self::get).
+    ///
+    /// The version can be handed out like an ETag and checked on the way back
+    /// in with [`put_if_version`](Self::put_if_version).
+    pub fn get_versioned(&self, key: &K) -> Result<Option<(V, u64)>, CacheError> {
+        let value = self.get(key)?;
+        Ok(value.zip(self.version(key)?))
+    }
+
+    /// Writes `value` for `key` only if the entry is still at version
+    /// `expected`, or with `None`, only if the key is absent or expired.
+    ///
+    /// The write counts as a write, exactly like [`add`](Self::add).
+    ///
+    /// # Returns
+    /// - `Ok(Some(version))` with the entry's new version if the write went through
+    /// - `Ok(None)` if the entry was at another version, or the admission
+    ///   filter turned a new key away
+    /// - `Err(CacheError)` if the value is too large or the write backend failed
+    pub fn put_if_version(
+        &mut self,
+        key: K,
+        value: V,
+        expected: Option<u64>,
+    ) -> Result<Option<u64>, CacheError> {
+        if self.version(&key)? != expected {
+            return Ok(None);
+        }
+        let written = match expected {
+            Some(version) => self.compare_and_swap_version(&key, version, value)?,
+            None => !matches!(self.put(key.clone(), value)?, InsertResult::Rejected),
+        };
+        if !written {
+            return Ok(None);
+        }
+        self.version(&key)
+    }
+
+    fn replace_if<F>(&mut self, key: &K, new: V, matches: F) -> Result<bool, CacheError>
+    where
+        F: FnOnce(&Node<K, V>) -> bool,
+    {
+        let now = self.touch();
+        if self.is_expired(key, now) {
+            self.expire(key)?;
+            return Ok(false);
+        }
+        let Some(&id) = self.cache.get(key) else {
+            return Ok(false);
+        };
+        let current = matches(&self.nodes[id]);
+        if current {
+            // The entry keeps the time it has left, as with `OccupiedEntry`
+            let ttl = self.ttl_left(id);
+            self.write_through_with_ttl(key, &new, ttl)?;
+            let expires_at = self.kept_deadline(key);
+            self.add_entry(key.clone(), new, expires_at)?;
+        }
+        Ok(current)
+    }
+
+    /// Whether `key` is cached, without cloning its value or counting as a
+    /// use of it.
+    ///
+    /// An entry that has expired but was not removed yet still counts; use
+    /// [`peek`](Self::peek) when that matters.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.contains_key(key)
+    }
+
+    /// Returns the value for `key` without counting as a use of the entry.
+    ///
+    /// Unlike [`get`](Self::get), the visited bit, the statistics and the
+    /// entry's idle deadline are left alone, so monitoring and debugging reads
+    /// do not change what gets evicted or the reported hit rate. Expired
+    /// entries read as absent but are not removed.
+    pub fn peek<Q>(&self, key: &Q) -> Result<Option<V>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(&id) = self.cache.get(key) else {
+            return Ok(None);
+        };
+        let node = &self.nodes[id];
+        if self.is_node_expired(node, self.clock.peek()) {
+            return Ok(None);
+        }
+        Ok(Some(node.value.clone()))
+    }
+
+    /// Invalidates every entry in the cache in constant time.
+    ///
+    /// Entries inserted before the call are treated as expired from now on:
+    /// lookups miss, and the entries are removed, counted and reported to the
+    /// eviction listener as expired when they are next touched or evicted
+    /// through the usual path. Until then they still take up room and count
+    /// toward [`len`](Self::len) and [`contains_key`](Self::contains_key), like
+    /// any expired entry. Use [`purge`](Self::purge) to free the memory
+    /// right away instead.
+    ///
+    /// # Returns
+    /// The new epoch
+    pub fn bump_epoch(&mut self) -> u64 {
+        self.epoch += 1;
+        self.epoch
+    }
+
+    /// The current epoch, see [`bump_epoch`](Self::bump_epoch).
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Pins `key`, so the eviction policy never picks it as a victim.
+    ///
+    /// A pinned entry still counts toward the capacity, expires and can be
+    /// removed as usual; only eviction passes it over. Once every entry is
+    /// pinned, inserts into the full cache fail with
+    /// [`CacheError::CapacityError`].
+    ///
+    /// # Returns
+    /// `true` if `key` is cached, `false` if there was nothing to pin
+    pub fn pin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.set_pinned(key, true)
+    }
+
+    /// Makes a pinned entry evictable again.
+    ///
+    /// # Returns
+    /// `true` if `key` is cached, `false` otherwise
+    pub fn unpin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.set_pinned(key, false)
+    }
+
+    pub fn is_pinned<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.get(key).is_some_and(|&id| self.nodes[id].pinned)
+    }
+
+    fn set_pinned<Q>(&mut self, key: &Q, pinned: bool) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(&id) = self.cache.get(key) else {
+            return false;
+        };
+        let node = &mut self.nodes[id];
+        if node.pinned != pinned {
+            node.pinned = pinned;
+            if pinned {
+                self.pinned += 1;
+            } else {
+                self.pinned -= 1;
+            }
+            self.stats.pinned = self.pinned;
+        }
+        true
+    }
+
+    /// Whether the cache is still being warmed up, see
+    /// [`SieveCacheBuilder::warming`].
+    pub fn is_warming(&self) -> bool {
+        self.warming
+    }
+
+    /// Ends the warm-up phase: from now on `get` returns cached values and
+    /// counts hits and misses.
+    pub fn activate(&mut self) {
+        self.warming = false;
+    }
+
     /// Adds a value to the cache.
     ///
     /// # Returns
     /// - `Ok(true)` if the key already existed and the value was updated
     /// - `Ok(false)` if the key was newly inserted
-    /// - `Err(CacheError)` if there was a lock poisoning
+    /// - `Err(CacheError)` if the value is too large, no entry could be evicted
+    ///   to make room, or the write backend failed
     #[must_use = "The returned value indicates whether the key already existed"]
     pub fn add(&mut self, key: K, value: V) -> Result<bool, CacheError> {
-        if let Some(node) = self.cache.get_mut(&key) {
-            let mut node_guard = node
-                .lock()
-                .map_err(|e| CacheError::LockError(e.to_string()))?;
-            node_guard.visited.store(true, Ordering::SeqCst);
-            node_guard.value = value;
-            drop(node_guard);
-            Ok(true)
+        self.write_through(&key, &value)?;
+        let outcome = self.add_entry(key, value, None)?;
+        Ok(matches!(outcome, InsertResult::Updated))
+    }
+
+    /// Adds a value to the cache, handing back whatever the insert displaced.
+    ///
+    /// Unlike [`add`](Self::add), an entry evicted to make room is returned
+    /// as [`InsertResult::Evicted`], so callers can spill it to a secondary
+    /// store without installing an eviction listener. A listener, if any, is
+    /// still notified.
+    #[must_use = "The returned value may hold an evicted entry"]
+    pub fn put(&mut self, key: K, value: V) -> Result<InsertResult<K, V>, CacheError> {
+        self.write_through(&key, &value)?;
+        self.add_entry(key, value, None)
+    }
+
+    /// Adds a value to the cache that expires once `ttl` has elapsed.
+    ///
+    /// Expiry is independent of SIEVE eviction: an expired entry is removed the
+    /// next time it is looked up, even if the cache is not full.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the key already existed and the value was updated
+    /// - `Ok(false)` if the key was newly inserted
+    /// - `Err(CacheError)` if the value is too large, no entry could be evicted
+    ///   to make room, or the write backend failed
+    #[must_use = "The returned value indicates whether the key already existed"]
+    pub fn add_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Result<bool, CacheError> {
+        self.write_through_with_ttl(&key, &value, Some(ttl))?;
+        let expires_at = self.now().checked_add(ttl);
+        let outcome = self.add_entry(key, value, expires_at)?;
+        Ok(matches!(outcome, InsertResult::Updated))
+    }
+
+    /// Adds a value that the SIEVE hand evicts later or sooner than others,
+    /// see [`Sieve`] for how priorities are weighed. Entries added any other
+    /// way have [`Priority::Normal`]; updating an entry keeps its priority.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the key already existed and the value was updated
+    /// - `Ok(false)` if the key was newly inserted
+    /// - `Err(CacheError)` if the value is too large, no entry could be evicted
+    ///   to make room, or the write backend failed
+    #[must_use = "The returned value indicates whether the key already existed"]
+    pub fn add_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        priority: Priority,
+    ) -> Result<bool, CacheError> {
+        let existed = self.add(key.clone(), value)?;
+        self.set_priority(&key, priority);
+        Ok(existed)
+    }
+
+    /// Changes the priority of a cached entry.
+    ///
+    /// # Returns
+    /// `true` if `key` is cached, `false` otherwise
+    pub fn set_priority<Q>(&mut self, key: &Q, priority: Priority) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(&id) = self.cache.get(key) else {
+            return false;
+        };
+        let node = &mut self.nodes[id];
+        self.priorities[node.priority as usize] -= 1;
+        self.priorities[priority as usize] += 1;
+        node.priority = priority;
+        true
+    }
+
+    /// The priority of `key`, or `None` if it is not cached.
+    pub fn priority<Q>(&self, key: &Q) -> Option<Priority>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.get(key).map(|&id| self.nodes[id].priority)
+    }
+
+    /// Adds many values at once, e.g. to warm up a cache.
+    ///
+    /// Keys already cached are updated as by [`add`](Self::add). For the new
+    /// ones, the number of evictions needed is worked out up front and the
+    /// victims are taken in a single sweep before all new entries are linked,
+    /// instead of alternating between evicting and inserting. If a key
+    /// repeats, its last value wins; if there are more new keys than the
+    /// cache holds, only the last `capacity` of them are kept.
+    ///
+    /// With an admission filter configured, every new key still has to be
+    /// admitted on its own, so the values are added one by one.
+    ///
+    /// A value over the [maximum size](Self::set_max_value_size) fails the
+    /// whole call before anything is added.
+    pub fn add_many<I>(&mut self, items: I) -> Result<(), CacheError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let items: Vec<(K, V)> = items.into_iter().collect();
+        for (_, value) in &items {
+            self.check_value_size(value)?;
+        }
+        self.write_many_through(&items)?;
+        let now = self.touch();
+        self.history.observe(now, &self.stats, self.size);
+        let mut fresh: Vec<(K, V)> = Vec::new();
+        let mut positions: HashMap<K, usize> = HashMap::new();
+        for (key, value) in items {
+            if self.is_expired(&key, now) {
+                self.expire(&key)?;
+            }
+            if self.admission.is_some() || self.cache.contains_key(&key) {
+                self.add_entry(key, value, None)?;
+            } else if let Some(&position) = positions.get(&key) {
+                fresh[position].1 = value;
+            } else {
+                positions.insert(key.clone(), fresh.len());
+                fresh.push((key, value));
+            }
+        }
+        drop(positions);
+
+        // Earlier new entries would only be evicted by later ones
+        let skip = fresh.len().saturating_sub(self.capacity);
+        let needed = (self.size + fresh.len() - skip).saturating_sub(self.capacity);
+        for _ in 0..needed {
+            let victim = self.select_victim()?;
+            self.evict_entry(&victim)?;
+        }
+        for (key, value) in fresh.into_iter().skip(skip) {
+            self.link_new(key, value, None, now)?;
+        }
+        self.history.observe(now, &self.stats, self.size);
+        Ok(())
+    }
+
+    /// Pre-warms a cold cache with entries from a previous run or a scan of
+    /// the backing store.
+    ///
+    /// Unlike [`add_many`](Self::add_many), keys already cached keep their
+    /// value, and primed values skip the admission filter and any
+    /// [write backend](Self::set_write_backend), which they came from to
+    /// begin with. Primed entries start out unvisited and count as neither
+    /// hits nor misses, so they only survive the hand once they are read.
+    /// If more keys are primed than fit, later ones evict earlier ones.
+    ///
+    /// # Returns
+    /// - `Ok(usize)` with the number of entries primed
+    /// - `Err(CacheError)` if a value is too large to cache or every entry
+    ///   is pinned, in which case the entries before it stay primed
+    pub fn prime<I>(&mut self, items: I) -> Result<usize, CacheError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let now = self.touch();
+        let mut primed = 0;
+        for (key, value) in items {
+            if self.is_expired(&key, now) {
+                self.expire(&key)?;
+            }
+            if self.cache.contains_key(&key) {
+                continue;
+            }
+            self.check_value_size(&value)?;
+            #[cfg(feature = "snapshot")]
+            self.log_operation(RecordRef::Add {
+                key: &key,
+                value: &value,
+                ttl: None,
+            })?;
+            if self.size == self.capacity {
+                let victim = self.select_victim()?;
+                self.evict_entry(&victim)?;
+            }
+            self.link_new(key, value, None, now)?;
+            primed += 1;
+        }
+        self.history.observe(now, &self.stats, self.size);
+        Ok(primed)
+    }
+
+    /// Absorbs the entries of `other`, e.g. to collapse per-thread caches
+    /// into one shared cache, with `policy` deciding between two values for
+    /// the same key.
+    ///
+    /// Entries move over from the oldest to the newest, keeping their
+    /// visited bits, expiry deadlines, [priorities](Self::set_priority) and
+    /// [pins](Self::pin), and evict entries of this cache if it runs out of
+    /// room. An entry overwritten by `policy` keeps its own priority and
+    /// pin. Entries of `other` that already expired are dropped, without
+    /// notifying its eviction listener. With [`ConflictPolicy::NewestWins`],
+    /// the value written last by either cache's clock wins, which only makes
+    /// sense if both use the same clock.
+    ///
+    /// Merged values skip the admission filter and any
+    /// [write backend](Self::set_write_backend).
+    ///
+    /// # Returns
+    /// - `Ok(usize)` with the number of entries added or overwritten
+    /// - `Err(CacheError)` if a value is too large to cache or every entry
+    ///   is pinned, in which case the entries before it stay merged
+    pub fn merge<P2, S2>(
+        &mut self,
+        mut other: SieveCache<K, V, P2, S2>,
+        policy: ConflictPolicy,
+    ) -> Result<usize, CacheError>
+    where
+        P2: EvictionPolicy<K>,
+        S2: BuildHasher,
+    {
+        let now = self.touch();
+        let other_now = other.clock.peek();
+        let mut merged = 0;
+        let mut current = other.tail;
+        while let Some(id) = current {
+            let expired = other.is_expired(&other.nodes[id].key, other_now);
+            // Move the entries out of the slab rather than cloning them
+            let node = other.nodes.remove(id);
+            current = node.prev;
+            if expired {
+                continue;
+            }
+            if self.is_expired(&node.key, now) {
+                self.expire(&node.key)?;
+            }
+            #[cfg(feature = "snapshot")]
+            let record = RecordRef::Add {
+                key: &node.key,
+                value: &node.value,
+                ttl: node.expires_at.map(|at| at.saturating_duration_since(now)),
+            };
+            if let Some(&existing) = self.cache.get(&node.key) {
+                let overwrite = match policy {
+                    ConflictPolicy::KeepExisting => false,
+                    ConflictPolicy::Overwrite => true,
+                    ConflictPolicy::NewestWins => node.written_at > self.nodes[existing].written_at,
+                };
+                if overwrite {
+                    #[cfg(feature = "snapshot")]
+                    self.log_operation(record)?;
+                    self.add_entry(node.key, node.value, node.expires_at)?;
+                    merged += 1;
+                }
+                continue;
+            }
+            self.check_value_size(&node.value)?;
+            #[cfg(feature = "snapshot")]
+            self.log_operation(record)?;
+            if self.size == self.capacity {
+                let victim = self.select_victim()?;
+                self.evict_entry(&victim)?;
+            }
+            let (visited, priority, pinned) = (node.is_visited(), node.priority, node.pinned);
+            let key = node.key.clone();
+            self.link_new(node.key, node.value, node.expires_at, now)?;
+            let id = self.cache[&key];
+            self.nodes[id].set_visited(visited);
+            self.set_priority(&key, priority);
+            self.set_pinned(&key, pinned);
+            merged += 1;
+        }
+        self.history.observe(now, &self.stats, self.size);
+        Ok(merged)
+    }
+
+    /// Sets how long entries live after they were last written.
+    ///
+    /// Applies to every entry, including those inserted before the call.
+    /// Entries added with [`add_with_ttl`](Self::add_with_ttl) expire at
+    /// whichever deadline comes first. `None` disables write expiry.
+    pub fn set_expire_after_write(&mut self, ttl: Option<Duration>) {
+        self.expire_after_write = ttl;
+        self.timers_stale = true;
+    }
+
+    /// Sets how long entries live after they were last read or written.
+    ///
+    /// Applies to every entry, including those inserted before the call.
+    /// `None` disables access expiry.
+    pub fn set_expire_after_access(&mut self, ttl: Option<Duration>) {
+        self.expire_after_access = ttl;
+        self.timers_stale = true;
+    }
+
+    /// Computes a TTL for every entry written without an explicit one.
+    ///
+    /// `ttl_fn` runs on every insert and update through `add`, `probe` and
+    /// the entry API, so values can carry their own deadline. Returning
+    /// `None` leaves the entry to the cache-wide timeouts. Entries added with
+    /// [`add_with_ttl`](Self::add_with_ttl) keep the TTL they were given.
+    pub fn set_ttl_fn<F>(&mut self, ttl_fn: F)
+    where
+        F: Fn(&K, &V) -> Option<Duration> + Send + Sync + 'static,
+    {
+        self.ttl_fn = Some(Box::new(ttl_fn));
+    }
+
+    /// Rejects values whose size, as measured by `size_fn`, exceeds `max`.
+    ///
+    /// Inserts and updates of such values fail with
+    /// [`CacheError::ValueTooLarge`] and leave the cache untouched, so one
+    /// huge value cannot push out many small ones. Values changed in place
+    /// through [`get_mut`](Self::get_mut) are not measured.
+    pub fn set_max_value_size<F>(&mut self, max: usize, size_fn: F)
+    where
+        F: Fn(&V) -> usize + Send + Sync + 'static,
+    {
+        self.max_value_size = Some((max, Box::new(size_fn)));
+    }
+
+    pub(crate) fn check_value_size(&self, value: &V) -> Result<(), CacheError> {
+        let Some((max, size_fn)) = &self.max_value_size else {
+            return Ok(());
+        };
+        let size = size_fn(value);
+        if size > *max {
+            return Err(CacheError::ValueTooLarge { size, max: *max });
+        }
+        Ok(())
+    }
+
+    /// Installs a callback that can veto evicting a specific victim.
+    ///
+    /// The SIEVE hand consults `interceptor` for every unvisited candidate and
+    /// skips the ones it rejects, e.g. because the application knows they are
+    /// still in use. After `max_vetoes` rejections within a single eviction the
+    /// next candidate is evicted without asking, so inserts always make room.
+    pub fn set_eviction_interceptor<F>(&mut self, max_vetoes: usize, interceptor: F)
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        self.eviction_interceptor = Some(Box::new(interceptor));
+        self.max_eviction_vetoes = max_vetoes;
+    }
+
+    pub(crate) fn add_entry(
+        &mut self,
+        key: K,
+        value: V,
+        expires_at: Option<Instant>,
+    ) -> Result<InsertResult<K, V>, CacheError> {
+        // An expired entry is logically absent, so replace it outright
+        let now = self.touch();
+        if self.is_expired(&key, now) {
+            self.expire(&key)?;
+        }
+        if let Some(&id) = self.cache.get(&key) {
+            self.check_value_size(&value)?;
+            let expires_at = expires_at.or_else(|| self.derived_expiry(&key, &value, now));
+            self.generation += 1;
+            let node = &mut self.nodes[id];
+            node.mark_visited();
+            let replaced = std::mem::replace(&mut node.value, value);
+            node.expires_at = expires_at;
+            node.set_written_at(now);
+            node.version = self.generation;
+            self.with_entries(|policy, entries| policy.on_access(&key, entries))?;
+            self.schedule_expiry(&key)?;
+            if let Some(listener) = &self.eviction_listener {
+                listener(key, replaced, RemovalCause::Replaced);
+            }
+            Ok(InsertResult::Updated)
         } else {
-            self.insert(key, value)?;
-            Ok(false)
+            self.insert(key, value, expires_at)
         }
     }
 
@@ -96,38 +1265,331 @@ where
     /// - A boolean indicating whether the key already existed
     #[must_use = "This returns the probed value and whether it existed"]
     pub fn probe(&mut self, key: K, value: V) -> Result<(V, bool), CacheError> {
+        let now = self.touch();
+        if self.is_expired(&key, now) {
+            self.expire(&key)?;
+        }
         match self.cache.get(&key) {
+            Some(&id) => Ok((self.nodes[id].value.clone(), true)),
+            None => {
+                self.write_through(&key, &value)?;
+                self.insert(key, value.clone(), None)?;
+                Ok((value, false))
+            }
+        }
+    }
+
+    /// Adds a value that is removed again when the returned guard is dropped.
+    ///
+    /// Useful for per-request data that must not outlive the request. Call
+    /// [`EntryGuard::keep`] to leave the entry in the cache instead.
+    pub fn insert_scoped(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> Result<EntryGuard<'_, K, V, P, S>, CacheError> {
+        self.add(key.clone(), value)?;
+        Ok(EntryGuard {
+            cache: self,
+            key: Some(key),
+        })
+    }
+
+    /// Returns the entry for `key` for in-place manipulation.
+    ///
+    /// The lookup counts as a hit or miss and an occupied entry is marked as
+    /// visited, exactly like [`get`](Self::get), but the node is only looked
+    /// up once however the entry is used afterwards.
+    pub fn entry(&mut self, key: K) -> Result<Entry<'_, K, V, P, S>, CacheError> {
+        let now = self.touch();
+        if self.is_expired(&key, now) {
+            self.expire(&key)?;
+        }
+        match self.cache.get(&key).copied() {
             Some(node) => {
-                let guard = node
-                    .lock()
-                    .map_err(|e| CacheError::LockError(e.to_string()))?;
-                let result = guard.value.clone();
-                drop(guard);
-                Ok((result, true))
+                self.nodes[node].set_accessed_at(now);
+                self.nodes[node].mark_visited();
+                self.with_entries(|policy, entries| policy.on_access(&key, entries))?;
+                self.record_lookup(&key, true);
+                Ok(Entry::Occupied(OccupiedEntry {
+                    cache: self,
+                    key,
+                    node,
+                }))
             }
             None => {
-                self.insert(key, value.clone())?;
-                Ok((value, false))
+                self.record_lookup(&key, false);
+                Ok(Entry::Vacant(VacantEntry { cache: self, key }))
             }
         }
     }
 
-    pub fn delete(&mut self, key: &K) -> Result<bool, CacheError> {
-        if let Some(node) = self.cache.remove(key) {
-            self.unlink_node(node)?;
-            self.size -= 1;
-            Ok(true)
-        } else {
-            Ok(false)
+    /// Returns the cached value for `key`, computing and inserting it with `f`
+    /// on a miss.
+    ///
+    /// `f` only runs when the key is absent or expired, and the lookup is
+    /// recorded as a hit or miss like [`get`](Self::get).
+    pub fn get_or_insert_with<F>(&mut self, key: K, f: F) -> Result<V, CacheError>
+    where
+        F: FnOnce() -> V,
+    {
+        self.entry(key)?.or_insert_with(f)
+    }
+
+    /// Removes `key`, which may be any borrowed form of `K`.
+    ///
+    /// # Returns
+    /// Whether the key was cached.
+    pub fn delete<Q>(&mut self, key: &Q) -> Result<bool, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.touch();
+        if self.writes_through() {
+            if let Some((key, _)) = self.cache.get_key_value(key) {
+                self.delete_through(&key.clone())?;
+            }
+        }
+        self.remove_node(key, RemovalCause::Explicit)
+    }
+
+    /// Removes `key` and hands back its value.
+    ///
+    /// An expired entry is removed as expired and reads as absent. The value
+    /// is only cloned when an eviction listener needs a copy as well.
+    ///
+    /// # Returns
+    /// - `Ok(Some(V))` with the value if the key was cached
+    /// - `Ok(None)` if the key was absent or expired
+    /// - `Err(CacheError)` if the eviction policy reported an error
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, CacheError> {
+        if self.cache.contains_key(key) {
+            self.delete_through(key)?;
+        }
+        let now = self.touch();
+        if self.is_expired(key, now) {
+            self.expire(key)?;
+            return Ok(None);
+        }
+        let Some(node) = self.take_node(key)? else {
+            return Ok(None);
+        };
+        let (key, value) = node.into_entry();
+        if let Some(listener) = &self.eviction_listener {
+            listener(key, value.clone(), RemovalCause::Explicit);
+        }
+        Ok(Some(value))
+    }
+
+    /// Keeps only the entries for which `f` returns `true`, removing the rest
+    /// in a single walk from the newest to the oldest entry.
+    ///
+    /// Removed entries are reported to the eviction listener as explicit
+    /// removals.
+    ///
+    /// # Returns
+    /// The number of entries removed.
+    pub fn retain<F>(&mut self, mut f: F) -> Result<usize, CacheError>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.touch();
+        let mut removed = 0;
+        let mut current = self.head;
+        while let Some(id) = current {
+            let node = &self.nodes[id];
+            let doomed = (!f(&node.key, &node.value)).then(|| node.key.clone());
+            // Read the successor before the node is unlinked
+            current = node.next;
+            if let Some(key) = doomed {
+                self.delete_through(&key)?;
+                if self.remove_node(&key, RemovalCause::Explicit)? {
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Removes every expired entry.
+    ///
+    /// Expired entries are otherwise only dropped when they are looked up, so
+    /// this reclaims memory held by keys that are never touched again. Due
+    /// entries are found through a timer wheel, so the cost depends on how many
+    /// timers fired rather than on the size of the cache.
+    ///
+    /// Also purges the whole cache once it has been idle for longer than
+    /// [`set_max_idle`](Self::set_max_idle) allows. Calling this does not count
+    /// as activity.
+    ///
+    /// # Returns
+    /// The number of entries removed.
+    pub fn remove_expired(&mut self) -> Result<usize, CacheError> {
+        // Lookups through `get` count as activity
+        self.fold_reads();
+        let now = self.now();
+        let purged = self.purge_if_idle(now);
+        if purged > 0 {
+            return Ok(purged);
+        }
+
+        if self.timers_stale {
+            let keys: Vec<K> = self.cache.keys().cloned().collect();
+            for key in &keys {
+                self.schedule_expiry(key)?;
+            }
+            self.timers_stale = false;
+        }
+
+        let mut expired = Vec::new();
+        let mut moved = Vec::new();
+        for (key, deadline) in self.timers.advance(now) {
+            let Some(&id) = self.cache.get(&key) else {
+                continue;
+            };
+            // Timers left behind by updates no longer match the node
+            if self.nodes[id].timer != Some(deadline) {
+                continue;
+            }
+            if self.is_node_expired(&self.nodes[id], now) {
+                expired.push(key);
+            } else {
+                // The timer fired early within its tick, or reads pushed the idle
+                // deadline back since it was scheduled
+                self.nodes[id].timer = None;
+                moved.push(key);
+            }
+        }
+        for key in &moved {
+            self.schedule_expiry(key)?;
+        }
+        for key in &expired {
+            self.expire(key)?;
+        }
+        Ok(expired.len())
+    }
+
+    /// Does the housekeeping that lookups and inserts otherwise leave for
+    /// later, so it can run off the request path.
+    ///
+    /// Removes expired entries like [`remove_expired`](Self::remove_expired)
+    /// and, after a [`bump_epoch`](Self::bump_epoch), every entry from an
+    /// earlier epoch. Stores [write-behind](Self::set_write_behind) writes
+    /// and writes an automatic snapshot, with the `snapshot` feature, if
+    /// they are due. Then folds lookups made through [`get`](Self::get)
+    /// into the statistics and [flushes](Self::flush_stats) them.
+    ///
+    /// # Returns
+    /// The number of entries removed.
+    pub fn run_maintenance(&mut self) -> Result<usize, CacheError> {
+        let mut removed = self.remove_expired()?;
+        if self.swept_epoch != self.epoch {
+            let stale: Vec<K> = self
+                .cache
+                .iter()
+                .filter(|(_, &id)| self.nodes[id].epoch != self.epoch)
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in &stale {
+                self.expire(key)?;
+            }
+            removed += stale.len();
+            self.swept_epoch = self.epoch;
         }
+        // Writes that fail to flush stay queued for the next pass
+        let _ = self.flush_due();
+        // A failed snapshot leaves the previous file in place to retry later
+        #[cfg(feature = "snapshot")]
+        let _ = self.snapshot_if_due();
+        self.flush_stats();
+        Ok(removed)
     }
 
     pub fn purge(&mut self) {
+        #[cfg(feature = "snapshot")]
+        self.log_unreported(RecordRef::Clear);
+        self.clear(RemovalCause::Explicit);
+    }
+
+    /// Purges every entry once the cache has gone `max_idle` without a lookup,
+    /// insert or delete.
+    ///
+    /// Meant for caches owned by tenants or features that may go quiet for
+    /// long stretches: their memory is released without any bookkeeping in
+    /// the application. The purge happens on the next operation, or earlier
+    /// if [`remove_expired`](Self::remove_expired) runs periodically, e.g.
+    /// from a reaper. Purged entries are reported as expired. To cover only
+    /// part of the data, give that part its own cache. `None` disables it.
+    pub fn set_max_idle(&mut self, max_idle: Option<Duration>) {
+        self.max_idle = max_idle;
+    }
+
+    // Removes every entry, notifying the listener with `cause`, and returns
+    // how many there were
+    pub(crate) fn clear(&mut self, cause: RemovalCause) -> usize {
+        let count = self.size;
+        let removed: Vec<(K, V)> = if self.eviction_listener.is_some() {
+            self.iter().collect()
+        } else {
+            Vec::new()
+        };
+        self.clear_quietly();
+        if let Some(listener) = &self.eviction_listener {
+            for (key, value) in removed {
+                listener(key, value, cause);
+            }
+        }
+        count
+    }
+
+    // Drops every entry without notifying the eviction listener
+    pub(crate) fn clear_quietly(&mut self) {
         self.cache.clear();
+        self.nodes.clear();
+        self.slots.clear();
+        self.timers.clear();
         self.head = None;
         self.tail = None;
-        self.hand = None;
         self.size = 0;
+        self.pinned = 0;
+        self.stats.pinned = 0;
+        self.priorities = [0; 3];
+        if let Some(index) = &mut self.key_index {
+            index.clear();
+        }
+        if let Some(negatives) = &mut self.negatives {
+            negatives.clear();
+            self.stats.negative_entries = 0;
+        }
+        if let Some(errors) = &mut self.load_errors {
+            errors.clear();
+        }
+        self.generation += 1;
+        self.policy.clear();
+    }
+
+    // Records an operation, first purging the cache if it sat idle for too long
+    pub(crate) fn touch(&mut self) -> Instant {
+        self.fold_reads();
+        let now = self.now();
+        self.purge_if_idle(now);
+        self.last_activity = now;
+        now
+    }
+
+    fn purge_if_idle(&mut self, now: Instant) -> usize {
+        let Some(max_idle) = self.max_idle else {
+            return 0;
+        };
+        if now.saturating_duration_since(self.last_activity) < max_idle {
+            return 0;
+        }
+        let purged = self.clear(RemovalCause::Expired);
+        for _ in 0..purged {
+            self.record(StatEvent::Expiration);
+        }
+        purged
     }
 
     #[inline]
@@ -143,32 +1605,749 @@ where
         self.capacity
     }
 
-    fn insert(&mut self, key: K, value: V) -> Result<(), CacheError> {
+    pub(crate) fn insert(
+        &mut self,
+        key: K,
+        value: V,
+        expires_at: Option<Instant>,
+    ) -> Result<InsertResult<K, V>, CacheError> {
+        self.check_value_size(&value)?;
+        let now = self.now();
+        // Observe before evicting so the eviction lands in the current period,
+        // and again afterwards so the new entry counts toward the peak size
+        self.history.observe(now, &self.stats, self.size);
+        if let Some(filter) = &mut self.admission {
+            filter.increment(&key);
+        }
+        let mut outcome = InsertResult::Inserted;
         if self.size == self.capacity {
-            self.evict()?;
+            let victim = if self.admission.is_some() {
+                // A rejected key leaves the eviction order as it found it
+                let admitted = |cache: &Self, victim: &K| {
+                    let filter = cache.admission.as_ref();
+                    filter.is_some_and(|filter| filter.admit(&key, victim))
+                };
+                let Some(victim) = self.select_victim_if(admitted)? else {
+                    self.stats.admission_rejections += 1;
+                    return Ok(InsertResult::Rejected);
+                };
+                victim
+            } else {
+                self.select_victim()?
+            };
+            if let Some((key, value)) = self.evict_entry(&victim)? {
+                outcome = InsertResult::Evicted(key, value);
+            }
+            // Make room for the next few inserts while the hand is here
+            let extra = (self.eviction_batch - 1).min(self.size);
+            self.evict_n(extra)?;
+        }
+        self.link_new(key, value, expires_at, now)?;
+        self.history.observe(now, &self.stats, self.size);
+        Ok(outcome)
+    }
+
+    // Links an entry for a key that is known to be absent; the caller has
+    // already made room for it
+    pub(crate) fn link_new(
+        &mut self,
+        key: K,
+        value: V,
+        expires_at: Option<Instant>,
+        now: Instant,
+    ) -> Result<(), CacheError> {
+        if let Some(ghosts) = &mut self.ghosts {
+            ghosts.remove(&key);
+        }
+        if let Some(negatives) = &mut self.negatives {
+            negatives.remove(&key);
+            self.stats.negative_entries = negatives.len();
+        }
+        if let Some(errors) = &mut self.load_errors {
+            errors.remove(&key);
+        }
+        let expires_at = expires_at.or_else(|| self.derived_expiry(&key, &value, now));
+        let mut node = Node::new(key.clone(), value, now);
+        node.expires_at = expires_at;
+        self.insert_node(node);
+        self.with_entries(|policy, entries| policy.on_insert(&key, entries))?;
+        self.schedule_expiry(&key)
+    }
+
+    // The deadline the TTL function assigns to a value written at `now`
+    pub(crate) fn derived_expiry(&self, key: &K, value: &V, now: Instant) -> Option<Instant> {
+        let ttl_fn = self.ttl_fn.as_ref()?;
+        now.checked_add(ttl_fn(key, value)?)
+    }
+
+    fn is_expired<Q>(&self, key: &Q, now: Instant) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache
+            .get(key)
+            .is_some_and(|&id| self.is_node_expired(&self.nodes[id], now))
+    }
+
+    pub(crate) fn is_node_expired(&self, node: &Node<K, V>, now: Instant) -> bool {
+        node.epoch != self.epoch
+            || self
+                .node_deadline(node)
+                .is_some_and(|deadline| now >= deadline)
+    }
+
+    // Time left until the entry's own deadline, as written to the operation
+    // log for writes that keep it
+    pub(crate) fn ttl_left(&self, id: NodeId) -> Option<Duration> {
+        let now = self.clock.peek();
+        self.nodes[id]
+            .expires_at
+            .map(|at| at.saturating_duration_since(now))
+    }
+
+    // Deadline a value written by the cache itself, e.g. a reload, gives
+    // `key`: the live entry's own one, unless a TTL function derives it anew
+    pub(crate) fn kept_deadline(&self, key: &K) -> Option<Instant> {
+        if self.ttl_fn.is_some() || self.is_expired(key, self.clock.peek()) {
+            return None;
+        }
+        self.cache
+            .get(key)
+            .and_then(|&id| self.nodes[id].expires_at)
+    }
+
+    fn node_deadline(&self, node: &Node<K, V>) -> Option<Instant> {
+        node_deadline(node, self.expire_after_write, self.expire_after_access)
+    }
+
+    // Registers the node's current deadline with the timer wheel, unless it is
+    // already scheduled under it
+    pub(crate) fn schedule_expiry(&mut self, key: &K) -> Result<(), CacheError> {
+        let Some(&id) = self.cache.get(key) else {
+            return Ok(());
+        };
+        let deadline = self.node_deadline(&self.nodes[id]);
+        let node = &mut self.nodes[id];
+        if node.timer != deadline {
+            node.timer = deadline;
+            if let Some(deadline) = deadline {
+                self.timers.schedule(key.clone(), deadline);
+            }
         }
-        self.insert_node(key, value)?;
         Ok(())
     }
 
+    // Unlinks the node for `key` and reports it to the eviction listener
+    pub(crate) fn remove_node<Q>(
+        &mut self,
+        key: &Q,
+        cause: RemovalCause,
+    ) -> Result<bool, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(node) = self.take_node(key)? else {
+            return Ok(false);
+        };
+        if let Some(listener) = &self.eviction_listener {
+            let (key, value) = node.into_entry();
+            listener(key, value, cause);
+        }
+        Ok(true)
+    }
+
+    // Unlinks the node for `key` without notifying anyone
+    pub(crate) fn take_node<Q>(&mut self, key: &Q) -> Result<Option<Node<K, V>>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // The policy sees the entry while it is still linked
+        let Some(notified) =
+            self.with_cached_key(key, |policy, entries, key| policy.on_remove(key, entries))
+        else {
+            return Ok(None);
+        };
+        notified?;
+        let Some(id) = self.cache.remove(key) else {
+            return Ok(None);
+        };
+        let node = self.unlink_node(id);
+        self.size -= 1;
+        self.generation += 1;
+        Ok(Some(node))
+    }
+
+    fn expire<Q>(&mut self, key: &Q) -> Result<(), CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.remove_node(key, RemovalCause::Expired)? {
+            self.record(StatEvent::Expiration);
+        }
+        Ok(())
+    }
+
+    /// Returns the statistics collected so far. Lookups through
+    /// [`get`](Self::get) are counted from the next call that takes
+    /// `&mut self`; use [`stats`](Self::stats) to include them right away.
     pub fn get_stats(&self) -> &CacheStats {
         &self.stats
     }
 
-    pub fn iter(&self) -> CacheIterator<K, V> {
+    /// Returns a copy of the statistics collected so far, including lookups
+    /// through [`get`](Self::get) not counted by
+    /// [`get_stats`](Self::get_stats) yet.
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = self.stats.clone();
+        stats.hits += self.read_hits.load(Ordering::Relaxed);
+        stats.misses += self.read_misses.load(Ordering::Relaxed);
+        stats.clock_anomalies += self.read_anomalies.load(Ordering::Relaxed);
+        stats
+    }
+
+    // Moves the lookups counted by `get` into the regular statistics and
+    // applies what they queued
+    pub(crate) fn fold_reads(&mut self) {
+        let hits = std::mem::take(self.read_hits.get_mut());
+        let misses = std::mem::take(self.read_misses.get_mut());
+        self.stats.hits += hits;
+        self.stats.misses += misses;
+        self.stats.clock_anomalies += std::mem::take(self.read_anomalies.get_mut());
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record_many(StatEvent::Hit, hits);
+            recorder.record_many(StatEvent::Miss, misses);
+        }
+
+        let (lookups, last_read) = self.reads.lock_unpoisoned().take();
+        if let Some(at) = last_read {
+            self.last_activity = self.last_activity.max(at);
+        }
+        let now = self.clock.peek();
+        for lookup in lookups {
+            let key = &lookup.key;
+            // Neither the policy nor a removal has a caller to report a
+            // failure to here; the next write runs into the same error
+            match lookup.outcome {
+                Outcome::Hit => {
+                    let _ = self.with_cached_key(key, |policy, entries, key| {
+                        policy.on_access(key, entries)
+                    });
+                }
+                // Unless the key was written again since
+                Outcome::Expired if self.is_expired(key, now) => {
+                    let _ = self.expire(key);
+                }
+                Outcome::Expired | Outcome::Miss => {}
+            }
+            if lookup.counted {
+                self.note_lookup(key, lookup.outcome == Outcome::Hit);
+            }
+        }
+    }
+
+    /// Hands any batched events to the configured
+    /// [`StatsRecorder`](crate::StatsRecorder) right away.
+    pub fn flush_stats(&mut self) {
+        self.fold_reads();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.flush();
+        }
+    }
+
+    /// Changes how clock readings that jump backwards or far forward are
+    /// handled. Defaults to [`ClockJumpPolicy::Monotonic`].
+    pub fn set_clock_jump_policy(&mut self, policy: ClockJumpPolicy) {
+        self.clock.policy = policy;
+    }
+
+    // The cache's notion of the current time, see `ClockJumpPolicy`
+    pub(crate) fn now(&mut self) -> Instant {
+        let (now, anomaly) = self.clock.now();
+        if anomaly {
+            self.stats.clock_anomalies += 1;
+        }
+        now
+    }
+
+    pub(crate) fn record(&mut self, event: StatEvent) {
+        if !self.collect_stats {
+            return;
+        }
+        match event {
+            StatEvent::Hit => self.stats.hits += 1,
+            StatEvent::Miss => self.stats.misses += 1,
+            StatEvent::Eviction => self.stats.evictions += 1,
+            StatEvent::Expiration => self.stats.expirations += 1,
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(event);
+        }
+    }
+
+    /// Remembers the keys of the last `capacity` evicted entries, so misses on
+    /// them are counted as [`ghost_hits`](CacheStats::ghost_hits). Passing
+    /// `None` stops tracking.
+    pub fn set_ghost_capacity(&mut self, capacity: Option<usize>) {
+        self.ghosts = capacity.map(GhostList::new);
+    }
+
+    /// Enables caching of misses: keys marked with
+    /// [`mark_absent`](Self::mark_absent) read as known to be absent for
+    /// `ttl`. Up to [`capacity`](Self::capacity) markers are kept, the oldest
+    /// giving way first; they hold no value and do not count toward
+    /// [`len`](Self::len). Passing `None` drops every marker and disables it.
+    pub fn set_negative_ttl(&mut self, ttl: Option<Duration>) {
+        match (&mut self.negatives, ttl) {
+            (Some(negatives), Some(ttl)) => negatives.ttl = ttl,
+            (negatives, ttl) => *negatives = ttl.map(NegativeCache::new),
+        }
+        self.stats.negative_entries = self.negatives.as_ref().map_or(0, NegativeCache::len);
+    }
+
+    /// Lets the capacity adapt to the workload within the bounds of
+    /// `adaptive`, or keeps it fixed with `None`.
+    ///
+    /// The current capacity is clamped to the bounds right away. A ghost list
+    /// covering the maximum capacity is enabled if none is configured yet.
+    pub fn set_adaptive_capacity(
+        &mut self,
+        adaptive: Option<AdaptiveCapacity>,
+    ) -> Result<(), CacheError> {
+        if let Some(adaptive) = &adaptive {
+            if self.ghosts.is_none() {
+                self.ghosts = Some(GhostList::new(adaptive.max()));
+            }
+            let clamped = self.capacity.clamp(adaptive.min(), adaptive.max());
+            self.set_capacity(clamped)?;
+        }
+        self.adaptive = adaptive;
+        Ok(())
+    }
+
+    /// Changes the capacity at runtime.
+    ///
+    /// Growing only reserves room. Shrinking below the current size evicts
+    /// entries through the eviction policy, as inserts into a full cache
+    /// would, and reports them to the eviction listener as evicted.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<(K, V)>)` with the evicted entries in eviction order
+    /// - `Err(CacheError)` if `capacity` is zero
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<Vec<(K, V)>, CacheError> {
+        check_capacity(capacity)?;
+        self.capacity = capacity;
+        #[cfg(feature = "lock_stats")]
+        let table = self.cache.capacity();
+        self.cache
+            .reserve(capacity.saturating_sub(self.cache.len()));
+        #[cfg(feature = "lock_stats")]
+        if self.cache.capacity() != table {
+            self.stats.map_resizes += 1;
+        }
+        let mut evicted = Vec::new();
+        while self.size > self.capacity {
+            let victim = self.select_victim()?;
+            evicted.extend(self.evict_entry(&victim)?);
+        }
+        Ok(evicted)
+    }
+
+    /// Evicts up to `n` entries through the eviction policy, as if `n` inserts
+    /// had found the cache full, and reports them to the eviction listener as
+    /// evicted.
+    ///
+    /// Useful for freeing room ahead of a burst of inserts, e.g. from a
+    /// maintenance task, so the inserts themselves do not pause to evict.
+    /// Stops early once only pinned entries are left.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<(K, V)>)` with the evicted entries in eviction order
+    /// - `Err(CacheError)` if the policy found no victim or there was a lock
+    ///   poisoning
+    pub fn evict_n(&mut self, n: usize) -> Result<Vec<(K, V)>, CacheError> {
+        let mut evicted = Vec::with_capacity(n.min(self.size));
+        while evicted.len() < n && self.size > self.pinned {
+            let victim = self.select_victim()?;
+            evicted.extend(self.evict_entry(&victim)?);
+        }
+        Ok(evicted)
+    }
+
+    /// Sets how many entries an insert evicts when it finds the cache full,
+    /// 1 by default.
+    ///
+    /// Larger batches sweep the hand less often under sustained insert
+    /// pressure, trading a little capacity right after each eviction for
+    /// fewer eviction pauses. [`put`](Self::put) still returns only the first
+    /// victim; the rest go to the eviction listener. Values are clamped to
+    /// at least 1.
+    pub fn set_eviction_batch(&mut self, batch: usize) {
+        self.eviction_batch = batch.max(1);
+    }
+
+    /// Starts measuring the working set to back
+    /// [`recommended_capacity`](Self::recommended_capacity).
+    ///
+    /// Only about `sample_rate` (between 0 and 1) of the keys are tracked,
+    /// which keeps the overhead small on busy caches; 0.01 is plenty for key
+    /// spaces in the millions, small caches can afford 1.0. Passing `None`
+    /// stops tracking and discards what was measured.
+    pub fn set_working_set_sampling(&mut self, sample_rate: Option<f64>) {
+        self.reuse = sample_rate.map(ReuseTracker::new);
+    }
+
+    /// Suggests the smallest capacity that would have reached
+    /// `target_hit_rate` (between 0 and 1) on the lookups seen so far.
+    ///
+    /// The estimate assumes recency-based eviction, which SIEVE approximates
+    /// well. Returns `None` without working-set sampling, before any lookups,
+    /// or when the target is out of reach because too many lookups were for
+    /// keys never seen before.
+    pub fn recommended_capacity(&self, target_hit_rate: f64) -> Option<usize> {
+        self.reuse.as_ref()?.capacity_for(target_hit_rate)
+    }
+
+    /// The hit rate a cache of `capacity` entries would have achieved on the
+    /// lookups seen so far, or `None` without working-set sampling.
+    pub fn predicted_hit_rate(&self, capacity: usize) -> Option<f64> {
+        Some(self.reuse.as_ref()?.hit_rate(capacity))
+    }
+
+    /// Returns the overall statistics together with the per-class lookup
+    /// counters, as of the same moment.
+    pub fn stats_snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            overall: self.stats(),
+            classes: self.class_stats.clone(),
+        }
+    }
+
+    // Counts a lookup as a hit or miss, overall and for the key's class
+    fn record_lookup<Q>(&mut self, key: &Q, hit: bool)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.record(if hit { StatEvent::Hit } else { StatEvent::Miss });
+        self.note_lookup(key, hit);
+    }
+
+    // Feeds a lookup of `key` to everything but the hit and miss counters
+    fn note_lookup<Q>(&mut self, key: &Q, hit: bool)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if let Some(filter) = &mut self.admission {
+            filter.increment(key);
+        }
+        if let Some(reuse) = &mut self.reuse {
+            reuse.record(key);
+        }
+        let ghost_hit = !hit
+            && self
+                .ghosts
+                .as_mut()
+                .is_some_and(|ghosts| ghosts.remove(key));
+        let resize = self
+            .adaptive
+            .as_mut()
+            .and_then(|adaptive| adaptive.observe(ghost_hit, self.capacity));
+        if let Some(capacity) = resize {
+            // A lookup has nothing to report a failed eviction to; the next
+            // insert runs into the same error
+            let _ = self.set_capacity(capacity);
+        }
+        if !self.collect_stats {
+            return;
+        }
+        if ghost_hit {
+            self.stats.ghost_hits += 1;
+        }
+        if let Some(classifier) = &self.classifier {
+            // A miss has no cached key to borrow
+            let class = match self.cache.get_key_value(key) {
+                Some((key, _)) => classifier(key),
+                None => classifier(&key.to_owned()),
+            };
+            let class = self.class_stats.entry(class).or_default();
+            if hit {
+                class.hits += 1;
+            } else {
+                class.misses += 1;
+            }
+        }
+    }
+
+    /// Returns hit, miss, eviction and peak-size figures aggregated per hour or
+    /// per day, oldest first. The last bucket covers the period in progress.
+    ///
+    /// Only a day of hourly and a week of daily buckets are kept, so this is
+    /// meant for answering capacity questions after the fact rather than as a
+    /// replacement for a metrics pipeline. Periods without any cache activity
+    /// do not produce a bucket.
+    pub fn stats_history(&self, period: StatsPeriod) -> Vec<StatsBucket> {
+        self.history.buckets(period, &self.stats())
+    }
+
+    /// Returns up to `n` distinct entries chosen uniformly at random.
+    ///
+    /// Entries are picked directly rather than by walking the cache, so this
+    /// stays cheap on huge caches. Sampling does not mark entries as visited
+    /// and is not recorded in the stats.
+    pub fn sample(&self, n: usize) -> Result<Vec<(K, V)>, CacheError> {
+        let len = self.slots.len();
+        if n >= len {
+            return Ok(self.slots.iter().map(|&id| self.entry_of(id)).collect());
+        }
+        // Floyd's algorithm picks n distinct slots with exactly n draws
+        let mut picked = HashSet::with_capacity(n);
+        let mut sample = Vec::with_capacity(n);
+        for upper in len - n..len {
+            let slot = self.rng.below(upper + 1);
+            let slot = if picked.insert(slot) {
+                slot
+            } else {
+                picked.insert(upper);
+                upper
+            };
+            sample.push(self.entry_of(self.slots[slot]));
+        }
+        Ok(sample)
+    }
+
+    /// Returns up to `n` entries drawn with probability proportional to
+    /// `weight`, with replacement.
+    ///
+    /// Uses rejection sampling against `max_weight`, which must be at least the
+    /// largest weight any entry can have. Each candidate costs one draw, and
+    /// the number of draws is capped, so fewer than `n` entries come back when
+    /// most weights are far below `max_weight`.
+    pub fn sample_weighted<F>(
+        &self,
+        n: usize,
+        max_weight: f64,
+        weight: F,
+    ) -> Result<Vec<(K, V)>, CacheError>
+    where
+        F: Fn(&K, &V) -> f64,
+    {
+        let mut sample = Vec::with_capacity(n);
+        if self.slots.is_empty() || max_weight <= 0.0 {
+            return Ok(sample);
+        }
+        let max_draws = n.saturating_mul(64);
+        for _ in 0..max_draws {
+            if sample.len() == n {
+                break;
+            }
+            let id = self.slots[self.rng.below(self.slots.len())];
+            let node = &self.nodes[id];
+            if self.rng.unit() * max_weight < weight(&node.key, &node.value) {
+                sample.push(self.entry_of(id));
+            }
+        }
+        Ok(sample)
+    }
+
+    /// The eviction policy, e.g. to inspect state a custom policy keeps.
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    pub fn iter(&self) -> CacheIterator<'_, K, V> {
         CacheIterator {
-            current: self.head.clone(),
+            nodes: &self.nodes,
+            current: self.head,
+            back: self.tail,
+            remaining: self.size,
+        }
+    }
+
+    /// Removes every entry, yielding them in the order the eviction policy
+    /// would have evicted them.
+    ///
+    /// Entries are moved out rather than cloned where possible, and are not
+    /// reported to the eviction listener. The eviction interceptor is not
+    /// consulted. Entries the iterator is dropped before reaching are removed
+    /// all the same.
+    pub fn drain(&mut self) -> Drain<'_, K, V, P, S> {
+        #[cfg(feature = "snapshot")]
+        self.log_unreported(RecordRef::Clear);
+        Drain::new(self)
+    }
+
+    /// Copies every entry, newest first, into an iterator that no longer
+    /// depends on the cache.
+    ///
+    /// Unlike [`iter`](Self::iter), which borrows the cache, the copy is
+    /// taken in one go, so the snapshot can be consumed while the cache keeps
+    /// changing.
+    pub fn snapshot(&self) -> Result<Snapshot<K, V>, CacheError> {
+        Ok(Snapshot {
+            inner: self.iter().collect::<Vec<_>>().into_iter(),
+        })
+    }
+
+    /// Iterates over the cached keys without cloning them, in no particular
+    /// order. Expired entries that were not removed yet are included.
+    pub fn iter_keys(&self) -> Keys<'_, K, V> {
+        Keys {
+            inner: self.cache.keys(),
             _phantom: PhantomData,
         }
     }
+
+    /// Calls `f` with every entry from the newest to the oldest, lending the
+    /// key and value instead of cloning them.
+    pub fn for_each_entry<F>(&self, mut f: F) -> Result<(), CacheError>
+    where
+        F: FnMut(&K, &V),
+    {
+        let mut current = self.head;
+        while let Some(id) = current {
+            let node = &self.nodes[id];
+            f(&node.key, &node.value);
+            current = node.next;
+        }
+        Ok(())
+    }
+
+    fn entry_of(&self, id: NodeId) -> (K, V) {
+        let node = &self.nodes[id];
+        (node.key.clone(), node.value.clone())
+    }
+}
+
+fn check_capacity(capacity: usize) -> Result<(), CacheError> {
+    if capacity < 1 {
+        return Err(CacheError::CapacityError(
+            "Cache capacity cannot be zero".to_string(),
+        ));
+    }
+    // Nodes are addressed by 32-bit ids
+    if capacity > slab::MAX_LEN {
+        return Err(CacheError::CapacityError(format!(
+            "Cache capacity cannot exceed {}",
+            slab::MAX_LEN
+        )));
+    }
+    Ok(())
 }
 
-impl<K, V> Debug for SieveCache<K, V>
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Ord + Hash + Clone + Send + Sync + 'static,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Keeps the keys in an ordered index as well, so [`range`](Self::range)
+    /// and [`invalidate_prefix`](Self::invalidate_prefix) visit only the keys
+    /// they return instead of sorting or scanning every key.
+    ///
+    /// The index holds a copy of every key and adds a tree update to each
+    /// insert and removal.
+    pub fn enable_key_index(&mut self) {
+        if self.key_index.is_none() {
+            let index: BTreeSet<K> = self.cache.keys().cloned().collect();
+            self.key_index = Some(Box::new(index));
+        }
+    }
+
+    pub fn disable_key_index(&mut self) {
+        self.key_index = None;
+    }
+
+    fn ordered_keys(&self) -> Option<&BTreeSet<K>> {
+        self.key_index.as_ref()?.as_any().downcast_ref()
+    }
+
+    /// Returns the entries whose keys fall into `range`, in key order,
+    /// without counting as a use of them. Expired entries are left out.
+    pub fn range<Q, R>(&self, range: R) -> Result<Vec<(K, V)>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let keys: Vec<&K> = match self.ordered_keys() {
+            Some(_) if is_empty_range(&range) => Vec::new(),
+            Some(index) => index.range(range).collect(),
+            None => {
+                let mut keys: Vec<&K> = self
+                    .cache
+                    .keys()
+                    .filter(|key| range.contains((*key).borrow()))
+                    .collect();
+                keys.sort_unstable();
+                keys
+            }
+        };
+        let now = self.clock.peek();
+        Ok(keys
+            .into_iter()
+            .filter_map(|key| self.cache.get::<K>(key).map(|&id| &self.nodes[id]))
+            .filter(|node| !self.is_node_expired(node, now))
+            .map(|node| (node.key.clone(), node.value.clone()))
+            .collect())
+    }
+
+    /// Removes every entry whose key starts with `prefix`, e.g. all of a
+    /// user's `"session:42:"` keys, reporting them to the eviction listener
+    /// as explicit removals.
+    ///
+    /// # Returns
+    /// The number of entries removed
+    pub fn invalidate_prefix(&mut self, prefix: &str) -> Result<usize, CacheError>
+    where
+        K: Borrow<str>,
+    {
+        let starts_with = |key: &&K| (*key).borrow().starts_with(prefix);
+        let keys: Vec<K> = match self.ordered_keys() {
+            Some(index) => index
+                .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+                .take_while(starts_with)
+                .cloned()
+                .collect(),
+            None => self.cache.keys().filter(starts_with).cloned().collect(),
+        };
+        self.touch();
+        for key in &keys {
+            self.delete_through(key)?;
+            self.remove_node::<K>(key, RemovalCause::Explicit)?;
+        }
+        Ok(keys.len())
+    }
+}
+
+// A node expires at the earliest of its own deadline and the cache-wide write
+// and access timeouts
+pub(crate) fn node_deadline<K, V>(
+    node: &Node<K, V>,
+    expire_after_write: Option<Duration>,
+    expire_after_access: Option<Duration>,
+) -> Option<Instant> {
+    let after_write = expire_after_write.and_then(|ttl| node.written_at.checked_add(ttl));
+    let after_access = expire_after_access.and_then(|ttl| node.accessed_at().checked_add(ttl));
+    [node.expires_at, after_write, after_access]
+        .into_iter()
+        .flatten()
+        .min()
+}
+
+impl<K, V, P, S> Debug for SieveCache<K, V, P, S>
 where
     K: Debug + Eq + Hash,
     V: Debug,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hits = self.stats.hits + self.read_hits.load(Ordering::Relaxed);
+        let misses = self.stats.misses + self.read_misses.load(Ordering::Relaxed);
         f.debug_struct("SieveCache")
             .field("size", &self.size)
             .field("capacity", &self.capacity)
@@ -176,19 +2355,29 @@ where
                 "cache_usage",
                 &format!("{}%", (self.size * 100) / self.capacity),
             )
-            .field("hits", &self.stats.hits)
-            .field("misses", &self.stats.misses)
+            .field("hits", &hits)
+            .field("misses", &misses)
+            .field("evictions", &self.stats.evictions)
+            .field("expirations", &self.stats.expirations)
+            .field(
+                "clock_anomalies",
+                &(self.stats.clock_anomalies + self.read_anomalies.load(Ordering::Relaxed)),
+            )
             .field(
                 "hit_rate",
-                &format!(
-                    "{}%",
-                    if self.stats.hits + self.stats.misses > 0 {
-                        (self.stats.hits * 100) / (self.stats.hits + self.stats.misses)
-                    } else {
-                        0
-                    }
-                ),
+                &format!("{}%", (hits * 100).checked_div(hits + misses).unwrap_or(0)),
             )
             .finish()
     }
 }
+
+impl<K, V, P, S> Drop for SieveCache<K, V, P, S> {
+    // The recorder flushes when it is dropped; hand it the lookups through
+    // `get` no `&mut` call has counted yet
+    fn drop(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record_many(StatEvent::Hit, *self.read_hits.get_mut());
+            recorder.record_many(StatEvent::Miss, *self.read_misses.get_mut());
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Deduplication of concurrent loads of the same key.
+//!
+//! When a hot key is missing, every thread that asks for it at once would
+//! otherwise hit the backing store. The first thread to miss loads the key;
+//! the others wait for its result instead of loading it again.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crate::sync::{Condvar, Mutex};
+use crate::types::CacheError;
+
+type Outcome<V> = Result<Option<V>, CacheError>;
+
+struct Flight<V> {
+    outcome: Mutex<Option<Outcome<V>>>,
+    landed: Condvar,
+}
+
+pub(crate) struct InFlight<K, V> {
+    flights: Mutex<HashMap<K, Arc<Flight<V>>>>,
+}
+
+impl<K, V> Default for InFlight<K, V> {
+    fn default() -> Self {
+        InFlight {
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K, V> InFlight<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Runs `load` for `key` unless another thread is already loading it, in
+    /// which case this waits for and shares that thread's outcome.
+    pub(crate) fn run<F>(&self, key: &K, load: F) -> Outcome<V>
+    where
+        F: FnOnce() -> Outcome<V>,
+    {
+        let flight = {
+            let mut flights = self.flights.lock()?;
+            if let Some(flight) = flights.get(key) {
+                let flight = flight.clone();
+                drop(flights);
+                return flight.wait();
+            }
+            let flight = Arc::new(Flight {
+                outcome: Mutex::new(None),
+                landed: Condvar::new(),
+            });
+            flights.insert(key.clone(), flight.clone());
+            flight
+        };
+        // Lands the flight even if `load` panics, so waiters are not stranded
+        let landing = Landing {
+            flights: &self.flights,
+            key,
+            flight: &flight,
+        };
+        let outcome = load();
+        landing.land(outcome.clone());
+        outcome
+    }
+}
+
+impl<V: Clone> Flight<V> {
+    fn wait(&self) -> Outcome<V> {
+        let mut outcome = self.outcome.lock()?;
+        loop {
+            if let Some(landed) = &*outcome {
+                return landed.clone();
+            }
+            outcome = self.landed.wait(outcome)?;
+        }
+    }
+}
+
+struct Landing<'a, K: Eq + Hash, V> {
+    flights: &'a Mutex<HashMap<K, Arc<Flight<V>>>>,
+    key: &'a K,
+    flight: &'a Flight<V>,
+}
+
+impl<K: Eq + Hash, V> Landing<'_, K, V> {
+    fn land(self, outcome: Outcome<V>) {
+        *self.flight.outcome.lock_unpoisoned() = Some(outcome);
+    }
+}
+
+impl<K: Eq + Hash, V> Drop for Landing<'_, K, V> {
+    fn drop(&mut self) {
+        self.flights.lock_unpoisoned().remove(self.key);
+        let mut outcome = self.flight.outcome.lock_unpoisoned();
+        if outcome.is_none() {
+            *outcome = Some(Err(CacheError::LoaderPanicked));
+        }
+        drop(outcome);
+        self.flight.landed.notify_all();
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use crate::sieve::SieveCache;
+use crate::types::CacheError;
+
+/// The size class an entry is routed to by a [`SizeTieredCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SizeClass {
+    Small,
+    Medium,
+    Large,
+}
+
+impl SizeClass {
+    fn index(self) -> usize {
+        match self {
+            SizeClass::Small => 0,
+            SizeClass::Medium => 1,
+            SizeClass::Large => 2,
+        }
+    }
+}
+
+// SizeTieredCache keeps one SieveCache per size class so that a few large values
+// can only ever displace other large values, never the small ones.
+pub struct SizeTieredCache<K, V> {
+    tiers: [SieveCache<K, V>; 3],
+    small_max: usize,
+    medium_max: usize,
+    size_of: Box<dyn Fn(&V) -> usize + Send + Sync>,
+}
+
+impl<K, V> SizeTieredCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Creates a cache that routes values by the size reported by `size_of`.
+    ///
+    /// Values up to `small_max` go to the small tier, values up to `medium_max`
+    /// to the medium tier, and everything else to the large tier. `capacities`
+    /// holds the entry capacity of the small, medium and large tiers in order.
+    pub fn new<F>(
+        small_max: usize,
+        medium_max: usize,
+        capacities: [usize; 3],
+        size_of: F,
+    ) -> Result<Self, CacheError>
+    where
+        F: Fn(&V) -> usize + Send + Sync + 'static,
+    {
+        Ok(SizeTieredCache {
+            tiers: [
+                SieveCache::new(capacities[0])?,
+                SieveCache::new(capacities[1])?,
+                SieveCache::new(capacities[2])?,
+            ],
+            small_max,
+            medium_max,
+            size_of: Box::new(size_of),
+        })
+    }
+
+    /// Returns the size class `value` would be stored in.
+    pub fn classify(&self, value: &V) -> SizeClass {
+        let size = (self.size_of)(value);
+        if size <= self.small_max {
+            SizeClass::Small
+        } else if size <= self.medium_max {
+            SizeClass::Medium
+        } else {
+            SizeClass::Large
+        }
+    }
+
+    /// Retrieves a value from whichever tier currently holds the key.
+    pub fn get(&mut self, key: &K) -> Result<Option<V>, CacheError> {
+        match self.locate(key) {
+            Some(class) => self.tiers[class.index()].get(key),
+            // Record the miss once, against the small tier
+            None => self.tiers[SizeClass::Small.index()].get(key),
+        }
+    }
+
+    /// Adds a value to the tier matching its size class.
+    ///
+    /// If the key is already stored in a different tier (because its value
+    /// changed size), the old entry is moved out of that tier.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the key already existed and the value was updated
+    /// - `Ok(false)` if the key was newly inserted
+    /// - `Err(CacheError)` if there was a lock poisoning
+    #[must_use = "The returned value indicates whether the key already existed"]
+    pub fn add(&mut self, key: K, value: V) -> Result<bool, CacheError> {
+        let class = self.classify(&value);
+        match self.locate(&key) {
+            Some(current) if current != class => {
+                self.tiers[current.index()].delete(&key)?;
+                self.tiers[class.index()].add(key, value)?;
+                Ok(true)
+            }
+            _ => self.tiers[class.index()].add(key, value),
+        }
+    }
+
+    pub fn delete(&mut self, key: &K) -> Result<bool, CacheError> {
+        match self.locate(key) {
+            Some(class) => self.tiers[class.index()].delete(key),
+            None => Ok(false),
+        }
+    }
+
+    pub fn purge(&mut self) {
+        for tier in &mut self.tiers {
+            tier.purge();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiers.iter().map(SieveCache::len).sum()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the underlying cache for a size class, e.g. to inspect its stats.
+    pub fn tier(&self, class: SizeClass) -> &SieveCache<K, V> {
+        &self.tiers[class.index()]
+    }
+
+    fn locate(&self, key: &K) -> Option<SizeClass> {
+        [SizeClass::Small, SizeClass::Medium, SizeClass::Large]
+            .into_iter()
+            .find(|class| self.tiers[class.index()].cache.contains_key(key))
+    }
+}
+
+impl<K, V> Debug for SizeTieredCache<K, V>
+where
+    K: Debug + Eq + Hash,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SizeTieredCache")
+            .field("small_max", &self.small_max)
+            .field("medium_max", &self.medium_max)
+            .field("small", &self.tiers[0])
+            .field("medium", &self.tiers[1])
+            .field("large", &self.tiers[2])
+            .finish()
+    }
+}
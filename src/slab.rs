@@ -0,0 +1,115 @@
+use std::ops::{Index, IndexMut};
+
+// Slab stores values in one contiguous vector and hands out their positions
+// as stable 32-bit ids. Vacant positions form a free list threaded through
+// the vector itself and are reused by later inserts, so ids stay small and
+// the vector only grows to the peak number of values.
+pub(crate) struct Slab<T> {
+    entries: Vec<Entry<T>>,
+    // Most recently vacated position, the head of the free list
+    next_free: Option<u32>,
+    len: usize,
+}
+
+enum Entry<T> {
+    Occupied(T),
+    // Links to the next vacant position
+    Vacant(Option<u32>),
+}
+
+/// The largest number of values a slab can hold at once.
+pub(crate) const MAX_LEN: usize = u32::MAX as usize;
+
+impl<T> Slab<T> {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Slab {
+            entries: Vec::with_capacity(capacity),
+            next_free: None,
+            len: 0,
+        }
+    }
+
+    /// # Panics
+    /// If the slab already holds [`MAX_LEN`] values.
+    pub(crate) fn insert(&mut self, value: T) -> u32 {
+        self.len += 1;
+        match self.next_free {
+            Some(id) => {
+                let entry =
+                    std::mem::replace(&mut self.entries[id as usize], Entry::Occupied(value));
+                let Entry::Vacant(next_free) = entry else {
+                    unreachable!("free list links to an occupied entry");
+                };
+                self.next_free = next_free;
+                id
+            }
+            None => {
+                let id = u32::try_from(self.entries.len()).expect("slab is full");
+                self.entries.push(Entry::Occupied(value));
+                id
+            }
+        }
+    }
+
+    /// Takes the value out of `id`, leaving the position free for reuse.
+    ///
+    /// # Panics
+    /// If `id` is vacant.
+    pub(crate) fn remove(&mut self, id: u32) -> T {
+        let entry = std::mem::replace(
+            &mut self.entries[id as usize],
+            Entry::Vacant(self.next_free),
+        );
+        let Entry::Occupied(value) = entry else {
+            panic!("slab id is vacant");
+        };
+        self.next_free = Some(id);
+        self.len -= 1;
+        value
+    }
+
+    #[cfg(any(feature = "testing", feature = "stream"))]
+    pub(crate) fn get(&self, id: u32) -> Option<&T> {
+        match self.entries.get(id as usize)? {
+            Entry::Occupied(value) => Some(value),
+            Entry::Vacant(_) => None,
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// One past the highest position ever used; every id is below it.
+    #[cfg(feature = "stream")]
+    pub(crate) fn bound(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.next_free = None;
+        self.len = 0;
+    }
+}
+
+impl<T> Index<u32> for Slab<T> {
+    type Output = T;
+
+    fn index(&self, id: u32) -> &T {
+        match &self.entries[id as usize] {
+            Entry::Occupied(value) => value,
+            Entry::Vacant(_) => panic!("slab id is vacant"),
+        }
+    }
+}
+
+impl<T> IndexMut<u32> for Slab<T> {
+    fn index_mut(&mut self, id: u32) -> &mut T {
+        match &mut self.entries[id as usize] {
+            Entry::Occupied(value) => value,
+            Entry::Vacant(_) => panic!("slab id is vacant"),
+        }
+    }
+}
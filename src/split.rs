@@ -0,0 +1,227 @@
+//! A cache split into one writer and any number of lock-free readers.
+//!
+//! Readers look values up in a read-only view the writer publishes, so they
+//! never wait on a write; a reader only ever swaps an atomic pointer to pick
+//! up the latest view. Writes go to the cache behind the [`WriteHandle`] and
+//! stay invisible to readers until [`WriteHandle::publish`].
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use arc_swap::ArcSwap;
+
+use crate::clock::TimeBasis;
+use crate::eviction::{EvictionPolicy, Sieve};
+use crate::sieve::{node_deadline, DefaultHashBuilder, SieveCache};
+
+struct Published<V> {
+    value: V,
+    // Deadline as of the publish; reads do not extend it
+    deadline: Option<Instant>,
+    visited: AtomicBool,
+}
+
+struct View<K, V> {
+    entries: HashMap<K, Published<V>, DefaultHashBuilder>,
+    clock: TimeBasis,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+/// The writing half of a split cache, see [`SieveCache::split`].
+///
+/// Dereferences to the cache itself, so every method is available; call
+/// [`publish`](Self::publish) to make the changes visible to readers.
+pub struct WriteHandle<K, V, P = Sieve<K>, S = DefaultHashBuilder> {
+    cache: SieveCache<K, V, P, S>,
+    view: Arc<ArcSwap<View<K, V>>>,
+}
+
+/// A reading half of a split cache. Clones are cheap and share the view.
+pub struct ReadHandle<K, V> {
+    view: Arc<ArcSwap<View<K, V>>>,
+}
+
+impl<K, V> Clone for ReadHandle<K, V> {
+    fn clone(&self) -> Self {
+        ReadHandle {
+            view: self.view.clone(),
+        }
+    }
+}
+
+impl<K, V, P, S> SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Splits the cache into a writer and a reader for read-mostly workloads.
+    ///
+    /// Each publish copies the live entries into a fresh view, so batch
+    /// writes and publish once per batch. Visits and hits that readers record
+    /// are handed back to the cache on the next publish, which keeps the
+    /// eviction order and the statistics close to those of a single cache.
+    ///
+    /// ```
+    /// let (mut writer, reader) = nitro::SieveCache::new(100)?.split();
+    /// writer.add("answer", 42)?;
+    /// assert_eq!(reader.get("answer"), None);
+    ///
+    /// writer.publish();
+    /// assert_eq!(reader.get("answer"), Some(42));
+    /// # Ok::<(), nitro::CacheError>(())
+    /// ```
+    pub fn split(mut self) -> (WriteHandle<K, V, P, S>, ReadHandle<K, V>) {
+        let view = Arc::new(ArcSwap::from_pointee(publish(&mut self)));
+        let reader = ReadHandle { view: view.clone() };
+        (WriteHandle { cache: self, view }, reader)
+    }
+}
+
+fn publish<K, V, P, S>(cache: &mut SieveCache<K, V, P, S>) -> View<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    let now = cache.now();
+    let mut entries = HashMap::with_capacity_and_hasher(cache.len(), DefaultHashBuilder::default());
+    for (key, &id) in cache.cache.iter() {
+        let node = &cache.nodes[id];
+        if cache.is_node_expired(node, now) {
+            continue;
+        }
+        let deadline = node_deadline(node, cache.expire_after_write, cache.expire_after_access);
+        entries.insert(
+            key.clone(),
+            Published {
+                value: node.value.clone(),
+                deadline,
+                visited: AtomicBool::new(false),
+            },
+        );
+    }
+    View {
+        entries,
+        clock: cache.clock.clone(),
+        hits: AtomicUsize::new(0),
+        misses: AtomicUsize::new(0),
+    }
+}
+
+impl<K, V, P, S> WriteHandle<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Makes every write so far visible to readers.
+    ///
+    /// Readers in the middle of a lookup finish it against the previous view;
+    /// visits they record after this call are lost.
+    pub fn publish(&mut self) {
+        let view = publish(&mut self.cache);
+        let previous = self.view.swap(Arc::new(view));
+
+        let cache = &mut self.cache;
+        for (key, published) in &previous.entries {
+            if !published.visited.load(Ordering::Relaxed) {
+                continue;
+            }
+            if let Some(&id) = cache.cache.get(key) {
+                cache.nodes[id].mark_visited();
+            }
+        }
+        if cache.collect_stats {
+            *cache.read_hits.get_mut() += previous.hits.load(Ordering::Relaxed);
+            *cache.read_misses.get_mut() += previous.misses.load(Ordering::Relaxed);
+            cache.fold_reads();
+        }
+    }
+
+    /// Creates another reader for the same view.
+    pub fn reader(&self) -> ReadHandle<K, V> {
+        ReadHandle {
+            view: self.view.clone(),
+        }
+    }
+
+    /// Publishes the pending writes and gives back the cache.
+    pub fn into_inner(mut self) -> SieveCache<K, V, P, S> {
+        self.publish();
+        self.cache
+    }
+}
+
+impl<K, V, P, S> Deref for WriteHandle<K, V, P, S> {
+    type Target = SieveCache<K, V, P, S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cache
+    }
+}
+
+impl<K, V, P, S> DerefMut for WriteHandle<K, V, P, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.cache
+    }
+}
+
+impl<K, V> ReadHandle<K, V>
+where
+    K: Eq + Hash,
+    V: Clone,
+{
+    /// Looks `key` up in the latest published view, marking it as visited.
+    /// Entries whose deadline passed since the publish read as absent.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let view = self.view.load();
+        let value = view.entries.get(key).and_then(|published| {
+            if published
+                .deadline
+                .is_some_and(|deadline| view.clock.peek() >= deadline)
+            {
+                return None;
+            }
+            published.visited.store(true, Ordering::Relaxed);
+            Some(published.value.clone())
+        });
+        let counter = match value {
+            Some(_) => &view.hits,
+            None => &view.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    /// Whether the latest view holds `key`, without counting as a lookup.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.view.load().entries.contains_key(key)
+    }
+
+    /// Number of entries in the latest view.
+    pub fn len(&self) -> usize {
+        self.view.load().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
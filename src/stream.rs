@@ -0,0 +1,137 @@
+//! Async iteration over a shared cache, enabled with the `stream` feature.
+
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hash};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use crate::concurrent::ConcurrentSieveCache;
+use crate::eviction::EvictionPolicy;
+
+/// Entries copied per read lock unless set with
+/// [`chunk_size`](EntryStream::chunk_size).
+const DEFAULT_CHUNK_SIZE: usize = 256;
+
+/// A stream of copies of a cache's entries, see
+/// [`ConcurrentSieveCache::iter_stream`].
+pub struct EntryStream<'a, K, V, P, S> {
+    cache: &'a ConcurrentSieveCache<K, V, P, S>,
+    chunk_size: usize,
+    // Next slab position to copy from
+    position: usize,
+    buffer: VecDeque<(K, V)>,
+    // Whether the stream gave way to other tasks since the last chunk
+    yielded: bool,
+    done: bool,
+}
+
+// Nothing in the stream is pinned structurally
+impl<K, V, P, S> Unpin for EntryStream<'_, K, V, P, S> {}
+
+impl<K, V, P, S> EntryStream<'_, K, V, P, S> {
+    /// Copies at most `chunk_size` entries per read lock. Smaller chunks
+    /// hold writers up for less time; larger ones finish sooner.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+}
+
+impl<K, V, P, S> EntryStream<'_, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    fn fill(&mut self) {
+        let cache = self.cache.inner.read_unpoisoned();
+        let bound = cache.nodes.bound();
+        while self.position < bound && self.buffer.len() < self.chunk_size {
+            if let Some(node) = cache.nodes.get(self.position as u32) {
+                self.buffer
+                    .push_back((node.key.clone(), node.value.clone()));
+            }
+            self.position += 1;
+        }
+        if self.position >= bound {
+            self.done = true;
+        }
+    }
+}
+
+impl<K, V, P, S> Stream for EntryStream<'_, K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    type Item = (K, V);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(entry) = this.buffer.pop_front() {
+            return Poll::Ready(Some(entry));
+        }
+        if this.done {
+            return Poll::Ready(None);
+        }
+        // Let other tasks on this executor thread run between chunks
+        if this.position > 0 && !this.yielded {
+            this.yielded = true;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        this.yielded = false;
+        this.fill();
+        Poll::Ready(this.buffer.pop_front())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.buffer.len(), None)
+    }
+}
+
+impl<K, V, P, S> ConcurrentSieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    /// Streams copies of the entries, in no particular order, taking the
+    /// read lock for one chunk at a time rather than for the whole walk.
+    ///
+    /// Writers can get in between chunks, so the stream is weakly
+    /// consistent: every entry present from start to end is yielded once,
+    /// while entries added or removed meanwhile may or may not be, and an
+    /// entry removed and added again can show up twice. Like
+    /// [`snapshot`](crate::SieveCache::snapshot), expired entries that were
+    /// not removed yet are included.
+    ///
+    /// ```
+    /// use futures::StreamExt;
+    ///
+    /// let cache = nitro::ConcurrentSieveCache::new(100)?;
+    /// cache.add("a", 1)?;
+    /// cache.add("b", 2)?;
+    /// let stream = cache.iter_stream().chunk_size(1);
+    /// let mut entries = futures::executor::block_on(stream.collect::<Vec<_>>());
+    /// entries.sort();
+    /// assert_eq!(entries, [("a", 1), ("b", 2)]);
+    /// # Ok::<(), nitro::CacheError>(())
+    /// ```
+    pub fn iter_stream(&self) -> EntryStream<'_, K, V, P, S> {
+        EntryStream {
+            cache: self,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            position: 0,
+            buffer: VecDeque::new(),
+            yielded: false,
+            done: false,
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Locks used inside the crate.
+//!
+//! Everything that locks internally goes through these wrappers, so the
+//! backing implementation can be chosen at build time: the standard library
+//! by default, `parking_lot` with the `parking_lot` feature, and loom's
+//! model-checked types when built with `RUSTFLAGS="--cfg loom"`. Locking
+//! reports a poisoned lock as [`CacheError::LockError`]; `parking_lot` locks
+//! never poison.
+//!
+//! With the `lock_stats` feature, every lock counts the acquisitions that had
+//! to wait and how long they waited.
+//!
+//! Types that appear in the public API, such as the `Arc<Mutex<SieveCache>>`
+//! handed out by the global registry and taken by the reaper, stay on the
+//! standard library's locks regardless.
+
+#[cfg(feature = "lock_stats")]
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+#[cfg(any(loom, not(feature = "parking_lot")))]
+use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+#[cfg(feature = "lock_stats")]
+use std::time::{Duration, Instant};
+
+use crate::types::CacheError;
+#[cfg(feature = "lock_stats")]
+use crate::types::CacheStats;
+
+#[cfg(loom)]
+use loom::sync as imp;
+#[cfg(all(not(loom), feature = "parking_lot"))]
+use parking_lot as imp;
+#[cfg(all(not(loom), not(feature = "parking_lot")))]
+use std::sync as imp;
+
+pub(crate) type MutexGuard<'a, T> = imp::MutexGuard<'a, T>;
+pub(crate) type RwLockReadGuard<'a, T> = imp::RwLockReadGuard<'a, T>;
+pub(crate) type RwLockWriteGuard<'a, T> = imp::RwLockWriteGuard<'a, T>;
+
+// What a blocking acquisition returns: a guard that may be poisoned with the
+// standard library and loom, a plain guard with parking_lot
+#[cfg(any(loom, not(feature = "parking_lot")))]
+type Locked<G> = LockResult<G>;
+#[cfg(all(not(loom), feature = "parking_lot"))]
+type Locked<G> = G;
+
+pub(crate) struct Mutex<T> {
+    inner: imp::Mutex<T>,
+    stats: LockStats,
+}
+
+impl<T> Mutex<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Mutex {
+            inner: imp::Mutex::new(value),
+            stats: LockStats::default(),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> Result<MutexGuard<'_, T>, CacheError> {
+        checked(self.acquire())
+    }
+
+    // Locks even if another thread panicked while holding the lock, for data
+    // that cannot be left inconsistent
+    pub(crate) fn lock_unpoisoned(&self) -> MutexGuard<'_, T> {
+        unpoisoned(self.acquire())
+    }
+
+    fn acquire(&self) -> Locked<MutexGuard<'_, T>> {
+        self.stats
+            .acquire(|| attempt(self.inner.try_lock()), || self.inner.lock())
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Mutex")
+            .field(&*self.lock_unpoisoned())
+            .finish()
+    }
+}
+
+pub(crate) struct RwLock<T> {
+    inner: imp::RwLock<T>,
+    stats: LockStats,
+}
+
+impl<T> RwLock<T> {
+    pub(crate) fn new(value: T) -> Self {
+        RwLock {
+            inner: imp::RwLock::new(value),
+            stats: LockStats::default(),
+        }
+    }
+
+    pub(crate) fn read(&self) -> Result<RwLockReadGuard<'_, T>, CacheError> {
+        checked(self.acquire_read())
+    }
+
+    pub(crate) fn write(&self) -> Result<RwLockWriteGuard<'_, T>, CacheError> {
+        checked(
+            self.stats
+                .acquire(|| attempt(self.inner.try_write()), || self.inner.write()),
+        )
+    }
+
+    pub(crate) fn read_unpoisoned(&self) -> RwLockReadGuard<'_, T> {
+        unpoisoned(self.acquire_read())
+    }
+
+    fn acquire_read(&self) -> Locked<RwLockReadGuard<'_, T>> {
+        self.stats
+            .acquire(|| attempt(self.inner.try_read()), || self.inner.read())
+    }
+
+    #[cfg(feature = "lock_stats")]
+    pub(crate) fn stats(&self) -> &LockStats {
+        &self.stats
+    }
+
+    #[cfg(any(loom, not(feature = "parking_lot")))]
+    pub(crate) fn into_inner(self) -> Result<T, CacheError> {
+        checked(self.inner.into_inner())
+    }
+
+    #[cfg(all(not(loom), feature = "parking_lot"))]
+    pub(crate) fn into_inner(self) -> Result<T, CacheError> {
+        Ok(self.inner.into_inner())
+    }
+}
+
+pub(crate) struct Condvar(imp::Condvar);
+
+impl Condvar {
+    pub(crate) fn new() -> Self {
+        Condvar(imp::Condvar::new())
+    }
+
+    /// Releases `guard` until notified, then locks again.
+    #[cfg(any(loom, not(feature = "parking_lot")))]
+    pub(crate) fn wait<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+    ) -> Result<MutexGuard<'a, T>, CacheError> {
+        checked(self.0.wait(guard))
+    }
+
+    /// Releases `guard` until notified, then locks again.
+    #[cfg(all(not(loom), feature = "parking_lot"))]
+    pub(crate) fn wait<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+    ) -> Result<MutexGuard<'a, T>, CacheError> {
+        self.0.wait(&mut guard);
+        Ok(guard)
+    }
+
+    pub(crate) fn notify_all(&self) {
+        self.0.notify_all();
+    }
+}
+
+// Contention counters of one lock. Empty without the `lock_stats` feature, in
+// which case locking goes straight to the blocking acquisition.
+#[derive(Default)]
+pub(crate) struct LockStats {
+    #[cfg(feature = "lock_stats")]
+    contentions: AtomicUsize,
+    #[cfg(feature = "lock_stats")]
+    wait_nanos: AtomicU64,
+}
+
+impl LockStats {
+    // Tries `try_lock` first and only falls back to the blocking `lock`, timed,
+    // if the lock is held elsewhere
+    #[cfg(feature = "lock_stats")]
+    fn acquire<G>(&self, try_lock: impl FnOnce() -> Option<G>, lock: impl FnOnce() -> G) -> G {
+        if let Some(guard) = try_lock() {
+            return guard;
+        }
+        let start = Instant::now();
+        let guard = lock();
+        let waited = start.elapsed().as_nanos().min(u64::MAX as u128) as u64;
+        self.contentions.fetch_add(1, Ordering::Relaxed);
+        self.wait_nanos.fetch_add(waited, Ordering::Relaxed);
+        guard
+    }
+
+    #[cfg(not(feature = "lock_stats"))]
+    fn acquire<G>(&self, _try_lock: impl FnOnce() -> Option<G>, lock: impl FnOnce() -> G) -> G {
+        lock()
+    }
+
+    // Adds this lock's counters to `stats`
+    #[cfg(feature = "lock_stats")]
+    pub(crate) fn added_to(&self, mut stats: CacheStats) -> CacheStats {
+        stats.lock_contentions += self.contentions.load(Ordering::Relaxed);
+        stats.lock_wait += Duration::from_nanos(self.wait_nanos.load(Ordering::Relaxed));
+        stats
+    }
+}
+
+// Turns a non-blocking acquisition into a guard, or `None` if it would block
+#[cfg(any(loom, not(feature = "parking_lot")))]
+fn attempt<G>(result: TryLockResult<G>) -> Option<LockResult<G>> {
+    match result {
+        Ok(guard) => Some(Ok(guard)),
+        Err(TryLockError::Poisoned(e)) => Some(Err(e)),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+#[cfg(all(not(loom), feature = "parking_lot"))]
+fn attempt<G>(guard: Option<G>) -> Option<G> {
+    guard
+}
+
+#[cfg(any(loom, not(feature = "parking_lot")))]
+fn checked<G>(result: LockResult<G>) -> Result<G, CacheError> {
+    result.map_err(|e| CacheError::LockError(e.to_string()))
+}
+
+#[cfg(all(not(loom), feature = "parking_lot"))]
+fn checked<G>(guard: G) -> Result<G, CacheError> {
+    Ok(guard)
+}
+
+#[cfg(any(loom, not(feature = "parking_lot")))]
+fn unpoisoned<G>(result: LockResult<G>) -> G {
+    result.unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(all(not(loom), feature = "parking_lot"))]
+fn unpoisoned<G>(guard: G) -> G {
+    guard
+}
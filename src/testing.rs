@@ -0,0 +1,243 @@
+//! Fixtures for testing code that embeds a cache.
+//!
+//! Enabled with the `testing` feature, typically from `[dev-dependencies]`.
+//! Together with [`MockClock`] these make tests deterministic without sleeps
+//! or hand-rolled doubles.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+pub use crate::clock::MockClock;
+use crate::eviction::EvictionPolicy;
+use crate::loader::CacheLoader;
+use crate::sieve::SieveCache;
+use crate::sync::Mutex;
+use crate::types::RemovalCause;
+
+/// A data source that answers from a script and counts how often each key
+/// was requested.
+///
+/// Clones share the script and the counters, so one handle can be moved into
+/// the code under test while the test keeps another.
+pub struct ScriptedLoader<K, V> {
+    script: Arc<Mutex<HashMap<K, V>>>,
+    calls: Arc<Mutex<HashMap<K, usize>>>,
+}
+
+impl<K, V> Clone for ScriptedLoader<K, V> {
+    fn clone(&self) -> Self {
+        ScriptedLoader {
+            script: self.script.clone(),
+            calls: self.calls.clone(),
+        }
+    }
+}
+
+impl<K, V> Default for ScriptedLoader<K, V> {
+    fn default() -> Self {
+        ScriptedLoader {
+            script: Arc::default(),
+            calls: Arc::default(),
+        }
+    }
+}
+
+impl<K, V> ScriptedLoader<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes future loads of `key` return `value`.
+    pub fn set(&self, key: K, value: V) {
+        self.script.lock_unpoisoned().insert(key, value);
+    }
+
+    /// Makes future loads of `key` find nothing.
+    pub fn unset(&self, key: &K) {
+        self.script.lock_unpoisoned().remove(key);
+    }
+
+    /// Looks `key` up in the script and counts the call.
+    pub fn load(&self, key: &K) -> Option<V> {
+        *self.calls.lock_unpoisoned().entry(key.clone()).or_default() += 1;
+        self.script.lock_unpoisoned().get(key).cloned()
+    }
+
+    /// How many times `key` was loaded.
+    pub fn calls(&self, key: &K) -> usize {
+        self.calls.lock_unpoisoned().get(key).copied().unwrap_or(0)
+    }
+
+    /// How many loads happened in total.
+    pub fn total_calls(&self) -> usize {
+        self.calls.lock_unpoisoned().values().sum()
+    }
+}
+
+impl<K, V> CacheLoader<K, V> for ScriptedLoader<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    fn load(&self, key: &K) -> Option<V> {
+        ScriptedLoader::load(self, key)
+    }
+}
+
+/// Records every entry that leaves a cache.
+///
+/// Pass [`listener`](Self::listener) to
+/// [`SieveCacheBuilder::eviction_listener`](crate::SieveCacheBuilder::eviction_listener)
+/// and inspect the events afterwards.
+pub struct EventCapture<K, V> {
+    events: Arc<Mutex<Vec<(K, V, RemovalCause)>>>,
+}
+
+impl<K, V> Clone for EventCapture<K, V> {
+    fn clone(&self) -> Self {
+        EventCapture {
+            events: self.events.clone(),
+        }
+    }
+}
+
+impl<K, V> Default for EventCapture<K, V> {
+    fn default() -> Self {
+        EventCapture {
+            events: Arc::default(),
+        }
+    }
+}
+
+impl<K, V> EventCapture<K, V>
+where
+    K: Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A listener that appends to this capture.
+    pub fn listener(&self) -> impl Fn(K, V, RemovalCause) + Send + Sync + 'static {
+        let events = self.events.clone();
+        move |key, value, cause| events.lock_unpoisoned().push((key, value, cause))
+    }
+
+    /// Every event so far, oldest first.
+    pub fn events(&self) -> Vec<(K, V, RemovalCause)> {
+        self.events.lock_unpoisoned().clone()
+    }
+
+    /// Keys removed for `cause`, oldest first.
+    pub fn keys(&self, cause: RemovalCause) -> Vec<K> {
+        self.events
+            .lock_unpoisoned()
+            .iter()
+            .filter(|(_, _, c)| *c == cause)
+            .map(|(key, _, _)| key.clone())
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.events.lock_unpoisoned().clear();
+    }
+}
+
+/// Checks the cache's internal bookkeeping, returning a description of the
+/// first inconsistency found.
+///
+/// Verifies that the size stays within capacity and that the map, the entry
+/// list and the sampling slots all hold the same entries with intact links.
+pub fn check_invariants<K, V, P, S>(cache: &SieveCache<K, V, P, S>) -> Result<(), String>
+where
+    K: Eq + Hash + Clone + std::fmt::Debug,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    if cache.size > cache.capacity {
+        return Err(format!(
+            "size {} exceeds capacity {}",
+            cache.size, cache.capacity
+        ));
+    }
+    if cache.cache.len() != cache.size {
+        return Err(format!(
+            "map holds {} entries but size is {}",
+            cache.cache.len(),
+            cache.size
+        ));
+    }
+    if cache.slots.len() != cache.size {
+        return Err(format!(
+            "slot list holds {} entries but size is {}",
+            cache.slots.len(),
+            cache.size
+        ));
+    }
+    if cache.nodes.len() != cache.size {
+        return Err(format!(
+            "slab holds {} entries but size is {}",
+            cache.nodes.len(),
+            cache.size
+        ));
+    }
+    for (index, &id) in cache.slots.iter().enumerate() {
+        let Some(node) = cache.nodes.get(id) else {
+            return Err(format!("slot {} refers to a vacant node", index));
+        };
+        if node.slot != index {
+            return Err(format!("node in slot {} records slot {}", index, node.slot));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut previous = None;
+    let mut current = cache.head;
+    while let Some(id) = current {
+        let Some(node) = cache.nodes.get(id) else {
+            return Err(format!("list links to vacant node {}", id));
+        };
+        if !seen.insert(node.key.clone()) {
+            return Err(format!("{:?} appears twice in the list", node.key));
+        }
+        if cache.cache.get(&node.key) != Some(&id) {
+            return Err(format!("{:?} is listed but not mapped", node.key));
+        }
+        if node.prev != previous {
+            return Err(format!("{:?} has a broken back link", node.key));
+        }
+        previous = Some(id);
+        current = node.next;
+    }
+    if seen.len() != cache.size {
+        return Err(format!(
+            "list holds {} entries but size is {}",
+            seen.len(),
+            cache.size
+        ));
+    }
+    if cache.tail != previous {
+        return Err("tail does not point at the last listed entry".to_string());
+    }
+    Ok(())
+}
+
+/// Panics with a description if [`check_invariants`] finds a problem.
+pub fn assert_invariants<K, V, P, S>(cache: &SieveCache<K, V, P, S>)
+where
+    K: Eq + Hash + Clone + std::fmt::Debug,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    if let Err(problem) = check_invariants(cache) {
+        panic!("cache invariant violated: {}", problem);
+    }
+}
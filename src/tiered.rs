@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+use crate::eviction::EvictionPolicy;
+use crate::sieve::SieveCache;
+use crate::types::{CacheError, InsertResult, TierStats};
+
+/// A cache that can serve as the second tier of a [`TieredCache`].
+///
+/// Implemented for [`SieveCache`] and, for an unbounded second tier, for
+/// `HashMap`.
+pub trait Cache<K, V> {
+    /// Looks up `key`, counting it as a use where the cache tracks uses.
+    fn get(&mut self, key: &K) -> Result<Option<V>, CacheError>;
+
+    /// Stores `value` for `key`, replacing any previous value.
+    fn insert(&mut self, key: K, value: V) -> Result<(), CacheError>;
+
+    /// Removes `key`, handing back its value.
+    fn remove(&mut self, key: &K) -> Result<Option<V>, CacheError>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V, P, S> Cache<K, V> for SieveCache<K, V, P, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: EvictionPolicy<K>,
+    S: BuildHasher,
+{
+    fn get(&mut self, key: &K) -> Result<Option<V>, CacheError> {
+        SieveCache::get(self, key)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Result<(), CacheError> {
+        self.add(key, value).map(drop)
+    }
+
+    fn remove(&mut self, key: &K) -> Result<Option<V>, CacheError> {
+        SieveCache::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        SieveCache::len(self)
+    }
+}
+
+impl<K, V, S> Cache<K, V> for HashMap<K, V, S>
+where
+    K: Eq + Hash,
+    V: Clone,
+    S: BuildHasher,
+{
+    fn get(&mut self, key: &K) -> Result<Option<V>, CacheError> {
+        Ok(HashMap::get(self, key).cloned())
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Result<(), CacheError> {
+        HashMap::insert(self, key, value);
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &K) -> Result<Option<V>, CacheError> {
+        Ok(HashMap::remove(self, key))
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+}
+
+// TieredCache layers a small SieveCache (L1) over a larger cache (L2). Each
+// key lives in at most one tier: a hit in L2 moves the entry up into L1, and
+// the entry L1 evicts to make room moves down into L2, so L1 holds the hot
+// keys and L2 catches what falls out of it.
+pub struct TieredCache<K, V, L2> {
+    l1: SieveCache<K, V>,
+    l2: L2,
+    stats: TierStats,
+}
+
+impl<K, V, L2> TieredCache<K, V, L2>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    L2: Cache<K, V>,
+{
+    /// Layers `l1` over `l2`.
+    ///
+    /// Only the entry an insert into `l1` evicts to make room is demoted;
+    /// entries `l1` expires are dropped, and so are the extra entries of an
+    /// [eviction batch](SieveCache::set_eviction_batch) larger than one.
+    pub fn new(l1: SieveCache<K, V>, l2: L2) -> Self {
+        TieredCache {
+            l1,
+            l2,
+            stats: TierStats::default(),
+        }
+    }
+
+    /// Retrieves a value from L1, or from L2 and promotes it into L1.
+    pub fn get(&mut self, key: &K) -> Result<Option<V>, CacheError> {
+        if let Some(value) = self.l1.get(key)? {
+            self.stats.l1_hits += 1;
+            return Ok(Some(value));
+        }
+        let Some(value) = self.l2.remove(key)? else {
+            self.stats.misses += 1;
+            return Ok(None);
+        };
+        self.stats.l2_hits += 1;
+        // Moving between tiers is not a write, so it skips any write backend
+        match self.l1.add_entry(key.clone(), value.clone(), None)? {
+            InsertResult::Rejected => self.l2.insert(key.clone(), value.clone())?,
+            InsertResult::Evicted(evicted, evicted_value) => {
+                self.stats.promotions += 1;
+                self.demote(evicted, evicted_value)?;
+            }
+            InsertResult::Inserted | InsertResult::Updated => self.stats.promotions += 1,
+        }
+        Ok(Some(value))
+    }
+
+    /// Adds a value to L1, dropping any older copy from L2.
+    ///
+    /// A new key that L1's admission filter turns away goes to L2 instead.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the key already existed in either tier
+    /// - `Ok(false)` if the key was newly inserted
+    /// - `Err(CacheError)` if either tier failed
+    #[must_use = "The returned value indicates whether the key already existed"]
+    pub fn add(&mut self, key: K, value: V) -> Result<bool, CacheError> {
+        let outcome = self.l1.put(key.clone(), value.clone())?;
+        let in_l2 = self.l2.remove(&key)?.is_some();
+        match outcome {
+            InsertResult::Rejected => self.l2.insert(key, value)?,
+            InsertResult::Evicted(evicted, evicted_value) => self.demote(evicted, evicted_value)?,
+            InsertResult::Updated => return Ok(true),
+            InsertResult::Inserted => {}
+        }
+        Ok(in_l2)
+    }
+
+    /// Removes `key` from both tiers.
+    ///
+    /// # Returns
+    /// Whether either tier held the key.
+    pub fn delete(&mut self, key: &K) -> Result<bool, CacheError> {
+        let in_l1 = self.l1.delete(key)?;
+        let in_l2 = self.l2.remove(key)?.is_some();
+        Ok(in_l1 || in_l2)
+    }
+
+    pub fn len(&self) -> usize {
+        self.l1.len() + self.l2.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Lookup counters per tier and how many entries moved between them.
+    pub fn stats(&self) -> &TierStats {
+        &self.stats
+    }
+
+    pub fn l1(&self) -> &SieveCache<K, V> {
+        &self.l1
+    }
+
+    pub fn l2(&self) -> &L2 {
+        &self.l2
+    }
+
+    fn demote(&mut self, key: K, value: V) -> Result<(), CacheError> {
+        self.stats.demotions += 1;
+        self.l2.insert(key, value)
+    }
+}
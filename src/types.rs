@@ -1,16 +1,233 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
+use std::sync::Arc;
+#[cfg(feature = "lock_stats")]
+use std::time::Duration;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct CacheStats {
     pub hits: usize,
     pub misses: usize,
+    /// Entries removed by the eviction policy to make room for new ones
+    pub evictions: usize,
+    /// Entries removed because their TTL or idle/write timeout elapsed, or
+    /// because they predate the current epoch
+    pub expirations: usize,
+    /// Clock readings that went backwards or jumped forward beyond the
+    /// configured limit
+    pub clock_anomalies: usize,
+    /// New entries turned away by the admission filter because they were
+    /// less popular than the entry they would have replaced
+    pub admission_rejections: usize,
+    /// Misses on keys that were evicted recently enough to still be on the
+    /// ghost list, i.e. lookups a larger cache would have served
+    pub ghost_hits: usize,
+    /// Steps the eviction policy took along the entry list while looking for
+    /// victims, i.e. how far the SIEVE hand travelled
+    pub hand_steps: usize,
+    /// Victims found at the first entry the policy looked at
+    pub first_probe_victims: usize,
+    /// Visited bits the policy cleared while looking for victims
+    pub visited_resets: usize,
+    /// Calls to the loader, see
+    /// [`SieveCache::get_or_load`](crate::SieveCache::get_or_load); a batch
+    /// fetched at once counts as one
+    pub loads: usize,
+    /// Calls to the loader that failed
+    pub load_errors: usize,
+    /// Background reloads started by refresh-ahead, see
+    /// [`SieveCache::set_refresh_ahead`](crate::SieveCache::set_refresh_ahead)
+    pub refreshes: usize,
+    /// Lookups through
+    /// [`SieveCache::get_cached`](crate::SieveCache::get_cached) answered by
+    /// a known-absent marker. These are not counted as misses.
+    pub negative_hits: usize,
+    /// Keys currently marked as absent, including expired markers not
+    /// dropped yet
+    pub negative_entries: usize,
+    /// Entries currently pinned, see [`SieveCache::pin`](crate::SieveCache::pin)
+    pub pinned: usize,
+    /// Lock acquisitions that had to wait for another thread. Only wrappers
+    /// that lock internally, such as
+    /// [`ConcurrentSieveCache`](crate::ConcurrentSieveCache), count these.
+    #[cfg(feature = "lock_stats")]
+    pub lock_contentions: usize,
+    /// Total time spent waiting in those contended acquisitions
+    #[cfg(feature = "lock_stats")]
+    pub lock_wait: Duration,
+    /// Times the key map grew its table, rehashing every key. The table is
+    /// sized for the capacity up front, so this only happens when the
+    /// capacity is raised.
+    #[cfg(feature = "lock_stats")]
+    pub map_resizes: usize,
 }
 
-#[derive(Debug)]
+impl CacheStats {
+    // Every victim search ends in an eviction or an admission rejection
+    fn victim_searches(&self) -> usize {
+        self.evictions + self.admission_rejections
+    }
+
+    /// Average number of list steps per victim search, or 0 without any.
+    pub fn avg_hand_travel(&self) -> f64 {
+        ratio(self.hand_steps, self.victim_searches())
+    }
+
+    /// Fraction of victim searches that settled on the first entry probed,
+    /// or 0 without any.
+    pub fn first_probe_rate(&self) -> f64 {
+        ratio(self.first_probe_victims, self.victim_searches())
+    }
+
+    /// Average number of visited bits cleared per victim search, or 0
+    /// without any.
+    pub fn visited_reset_rate(&self) -> f64 {
+        ratio(self.visited_resets, self.victim_searches())
+    }
+}
+
+fn ratio(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+/// Lookup counters for one class of keys, see
+/// [`SieveCacheBuilder::key_classifier`](crate::SieveCacheBuilder::key_classifier).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClassStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl ClassStats {
+    /// Fraction of lookups that were hits, or 0 without lookups.
+    pub fn hit_rate(&self) -> f64 {
+        ratio(self.hits, self.hits + self.misses)
+    }
+}
+
+/// Lookup counters of a [`TieredCache`](crate::TieredCache) and how many
+/// entries moved between its tiers.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TierStats {
+    pub l1_hits: usize,
+    pub l2_hits: usize,
+    pub misses: usize,
+    /// Entries moved from L2 into L1 on a hit
+    pub promotions: usize,
+    /// Entries moved from L1 into L2 on eviction
+    pub demotions: usize,
+}
+
+impl TierStats {
+    /// Fraction of lookups that were hits in either tier, or 0 without
+    /// lookups.
+    pub fn hit_rate(&self) -> f64 {
+        let hits = self.l1_hits + self.l2_hits;
+        ratio(hits, hits + self.misses)
+    }
+}
+
+/// Overall and per-class statistics taken at the same instant.
+#[derive(Debug, Default, Clone)]
+pub struct StatsSnapshot {
+    pub overall: CacheStats,
+    /// Empty unless a key classifier is configured
+    pub classes: HashMap<&'static str, ClassStats>,
+}
+
+/// Why an entry left the cache, as reported to an eviction listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RemovalCause {
+    /// Chosen by the eviction policy to make room for a new entry
+    Evicted,
+    /// Its TTL or idle/write timeout elapsed
+    Expired,
+    /// Removed by the caller through `delete`, `purge` and similar methods
+    Explicit,
+    /// Its value was overwritten by a later `add` for the same key
+    Replaced,
+}
+
+/// How expensive an entry is to lose, see
+/// [`SieveCache::add_with_priority`](crate::SieveCache::add_with_priority).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub(crate) const ALL: [Priority; 3] = [Priority::Low, Priority::Normal, Priority::High];
+}
+
+/// What [`SieveCache::get_cached`](crate::SieveCache::get_cached) knows about
+/// a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cached<V> {
+    /// The key is cached with this value
+    Present(V),
+    /// The key was marked as absent from the backing store and the marker
+    /// has not expired yet
+    Absent,
+    /// The cache knows nothing about the key
+    Unknown,
+}
+
+/// Which value wins when a [merged](crate::SieveCache::merge) key is in
+/// both caches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConflictPolicy {
+    /// The value already in the cache stays
+    KeepExisting,
+    /// The merged-in value replaces it
+    Overwrite,
+    /// Whichever value was written last stays
+    NewestWins,
+}
+
+/// What an insert did to the cache, as returned by
+/// [`SieveCache::put`](crate::SieveCache::put).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsertResult<K, V> {
+    /// The key was new and there was room for it
+    Inserted,
+    /// The key was already cached and its value was overwritten
+    Updated,
+    /// The key was new and this entry was evicted to make room for it
+    Evicted(K, V),
+    /// The admission filter turned the new key away, leaving the cache as it was
+    Rejected,
+}
+
+#[derive(Debug, Clone)]
 pub enum CacheError {
     LockError(String),
     CapacityError(String),
+    /// A [`TryCacheLoader`](crate::TryCacheLoader) failed to fetch a value
+    Load(Arc<dyn Error + Send + Sync>),
+    /// A [`WriteBackend`](crate::WriteBackend) failed to store a write
+    Backend(Arc<dyn Error + Send + Sync>),
+    /// A snapshot could not be written or read, see
+    /// [`SieveCache::save_to`](crate::SieveCache::save_to)
+    Persistence(Arc<dyn Error + Send + Sync>),
+    /// A value was larger than the configured maximum, see
+    /// [`SieveCache::set_max_value_size`](crate::SieveCache::set_max_value_size)
+    ValueTooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// The loader panicked while fetching a value that other callers were
+    /// waiting for, see
+    /// [`ConcurrentSieveCache::get_or_load`](crate::ConcurrentSieveCache::get_or_load)
+    LoaderPanicked,
     // Other error types as needed
 }
 
@@ -20,9 +237,27 @@ impl fmt::Display for CacheError {
         match self {
             CacheError::LockError(msg) => write!(f, "Lock error: {}", msg),
             CacheError::CapacityError(msg) => write!(f, "Capacity error: {}", msg),
+            CacheError::Load(e) => write!(f, "Load error: {}", e),
+            CacheError::Backend(e) => write!(f, "Backend error: {}", e),
+            CacheError::Persistence(e) => write!(f, "Persistence error: {}", e),
+            CacheError::ValueTooLarge { size, max } => {
+                write!(
+                    f,
+                    "Value too large: {} exceeds the maximum of {}",
+                    size, max
+                )
+            }
+            CacheError::LoaderPanicked => write!(f, "Loader panicked"),
         }
     }
 }
 
 // Implement Error trait for CacheError
-impl Error for CacheError {}
+impl Error for CacheError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CacheError::Load(e) | CacheError::Backend(e) | CacheError::Persistence(e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
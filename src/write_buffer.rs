@@ -0,0 +1,206 @@
+//! A bounded queue of pending inserts in front of a cache.
+//!
+//! Producers enqueue writes without touching the cache, which keeps bursts
+//! from contending on the cache lock; a consumer periodically drains the queue
+//! into the cache. Writes to a key that is still queued replace the queued
+//! value instead of taking another slot.
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+use crate::eviction::EvictionPolicy;
+use crate::sieve::SieveCache;
+use crate::sync::{Condvar, Mutex};
+use crate::types::CacheError;
+
+/// What [`WriteBuffer::push`] does when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until a drain frees up room.
+    Block,
+    /// Discard the new write and report it as dropped.
+    Drop,
+}
+
+/// Counters describing a [`WriteBuffer`] since it was created.
+#[derive(Debug, Default, Clone)]
+pub struct WriteBufferStats {
+    /// Writes currently waiting to be drained
+    pub depth: usize,
+    /// The largest depth the queue has reached
+    pub max_depth: usize,
+    /// Writes accepted by `push`, including coalesced ones
+    pub enqueued: usize,
+    /// Writes that replaced a value still waiting in the queue
+    pub coalesced: usize,
+    /// Writes discarded because the queue was full
+    pub dropped: usize,
+    /// Writes applied to the cache
+    pub drained: usize,
+}
+
+type FlushCallback = Box<dyn Fn(usize) + Send + Sync>;
+
+struct Pending<K, V> {
+    // Keys in the order they were first enqueued
+    order: Vec<K>,
+    values: HashMap<K, V>,
+    stats: WriteBufferStats,
+}
+
+struct Shared<K, V> {
+    pending: Mutex<Pending<K, V>>,
+    not_full: Condvar,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    on_flush: Option<FlushCallback>,
+}
+
+/// A bounded, coalescing write queue. Clones share the same queue, so each
+/// producer thread can hold its own handle.
+pub struct WriteBuffer<K, V> {
+    shared: Arc<Shared<K, V>>,
+}
+
+impl<K, V> Clone for WriteBuffer<K, V> {
+    fn clone(&self) -> Self {
+        WriteBuffer {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<K, V> WriteBuffer<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Creates a queue holding up to `capacity` distinct keys.
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Result<Self, CacheError> {
+        Self::build(capacity, overflow, None)
+    }
+
+    /// Like [`new`](Self::new), and calls `on_flush` with the number of writes
+    /// applied after every drain that applied any.
+    pub fn with_flush_callback<F>(
+        capacity: usize,
+        overflow: OverflowPolicy,
+        on_flush: F,
+    ) -> Result<Self, CacheError>
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        Self::build(capacity, overflow, Some(Box::new(on_flush)))
+    }
+
+    fn build(
+        capacity: usize,
+        overflow: OverflowPolicy,
+        on_flush: Option<FlushCallback>,
+    ) -> Result<Self, CacheError> {
+        if capacity < 1 {
+            return Err(CacheError::CapacityError(
+                "Write buffer capacity cannot be zero".to_string(),
+            ));
+        }
+        Ok(WriteBuffer {
+            shared: Arc::new(Shared {
+                pending: Mutex::new(Pending {
+                    order: Vec::with_capacity(capacity),
+                    values: HashMap::with_capacity(capacity),
+                    stats: WriteBufferStats::default(),
+                }),
+                not_full: Condvar::new(),
+                capacity,
+                overflow,
+                on_flush,
+            }),
+        })
+    }
+
+    /// Queues a write.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the write was queued or coalesced with a queued write
+    /// - `Ok(false)` if the queue was full and the write was dropped
+    /// - `Err(CacheError)` if there was a lock poisoning
+    pub fn push(&self, key: K, value: V) -> Result<bool, CacheError> {
+        let shared = &*self.shared;
+        let mut pending = shared.pending.lock()?;
+
+        if let Some(queued) = pending.values.get_mut(&key) {
+            *queued = value;
+            pending.stats.enqueued += 1;
+            pending.stats.coalesced += 1;
+            return Ok(true);
+        }
+
+        while pending.order.len() >= shared.capacity {
+            match shared.overflow {
+                OverflowPolicy::Drop => {
+                    pending.stats.dropped += 1;
+                    return Ok(false);
+                }
+                OverflowPolicy::Block => {
+                    pending = shared.not_full.wait(pending)?;
+                }
+            }
+        }
+
+        pending.order.push(key.clone());
+        pending.values.insert(key, value);
+        pending.stats.enqueued += 1;
+        pending.stats.depth = pending.order.len();
+        pending.stats.max_depth = pending.stats.max_depth.max(pending.stats.depth);
+        Ok(true)
+    }
+
+    /// Applies every queued write to `cache` in the order the keys were first
+    /// queued, and wakes producers blocked on a full queue.
+    ///
+    /// The queue lock is released before the cache is touched, so producers
+    /// can keep enqueuing while the drain runs.
+    ///
+    /// # Returns
+    /// The number of writes applied.
+    pub fn drain_into<P, S>(&self, cache: &mut SieveCache<K, V, P, S>) -> Result<usize, CacheError>
+    where
+        P: EvictionPolicy<K>,
+        S: BuildHasher,
+    {
+        let shared = &*self.shared;
+        let (order, mut values) = {
+            let mut pending = shared.pending.lock()?;
+            pending.stats.depth = 0;
+            let order = std::mem::take(&mut pending.order);
+            let values = std::mem::take(&mut pending.values);
+            (order, values)
+        };
+        shared.not_full.notify_all();
+
+        let count = order.len();
+        for key in order {
+            if let Some(value) = values.remove(&key) {
+                cache.add(key, value)?;
+            }
+        }
+
+        shared.pending.lock()?.stats.drained += count;
+        if count > 0 {
+            if let Some(on_flush) = &shared.on_flush {
+                on_flush(count);
+            }
+        }
+        Ok(count)
+    }
+
+    /// Number of writes waiting to be drained.
+    pub fn depth(&self) -> usize {
+        self.stats().depth
+    }
+
+    pub fn stats(&self) -> WriteBufferStats {
+        self.shared.pending.lock_unpoisoned().stats.clone()
+    }
+}
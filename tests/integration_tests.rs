@@ -1,4 +1,13 @@
-use nitro::SieveCache;
+#![allow(clippy::bool_assert_comparison)]
+
+use nitro::{
+    CacheError, ClassStats, EntryList, EvictionPolicy, MockClock, OverflowPolicy, RemovalCause,
+    SieveCache, SieveCacheBuilder, SizeClass, SizeTieredCache, StatsRecorder, TieredCache,
+    WriteBuffer,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 #[test]
 fn test_basic_integration() {
@@ -44,7 +53,933 @@ fn test_complex_operations() {
     assert_eq!(cache.get(&String::from("b")).unwrap(), None);
 
     // Check stats
-    let stats = cache.get_stats();
+    let stats = cache.stats();
     assert!(stats.hits > 0);
     assert!(stats.misses > 0);
 }
+
+#[test]
+fn test_size_tiered_routing() {
+    let mut cache: SizeTieredCache<String, Vec<u8>> =
+        SizeTieredCache::new(16, 1024, [4, 2, 1], |v: &Vec<u8>| v.len()).unwrap();
+
+    for i in 0..4 {
+        cache.add(format!("small{}", i), vec![0; 8]).unwrap();
+    }
+    // Large values only compete with each other
+    cache.add(String::from("big1"), vec![0; 4096]).unwrap();
+    cache.add(String::from("big2"), vec![0; 4096]).unwrap();
+
+    assert_eq!(cache.tier(SizeClass::Small).len(), 4);
+    assert_eq!(cache.tier(SizeClass::Large).len(), 1);
+    assert!(cache.get(&String::from("small0")).unwrap().is_some());
+    assert!(cache.get(&String::from("big1")).unwrap().is_none());
+
+    // Growing a value moves it to the matching tier
+    assert!(cache.add(String::from("small0"), vec![0; 100]).unwrap());
+    assert_eq!(cache.tier(SizeClass::Small).len(), 3);
+    assert_eq!(cache.tier(SizeClass::Medium).len(), 1);
+    assert_eq!(cache.len(), 5);
+}
+
+#[test]
+fn test_tiered_promotes_and_demotes() {
+    let l1 = SieveCache::new(2).unwrap();
+    let mut cache = TieredCache::new(l1, SieveCache::<u32, u32>::new(10).unwrap());
+
+    assert!(!cache.add(1, 10).unwrap());
+    assert!(!cache.add(2, 20).unwrap());
+    // A full L1 hands its victim down to L2
+    assert!(!cache.add(3, 30).unwrap());
+    assert_eq!((cache.l1().len(), cache.l2().len()), (2, 1));
+
+    let demoted = (1..=3).find(|key| !cache.l1().contains_key(key)).unwrap();
+    assert_eq!(cache.get(&demoted).unwrap(), Some(demoted * 10));
+    assert!(cache.l1().contains_key(&demoted));
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.get(&4).unwrap(), None);
+
+    let stats = cache.stats();
+    assert_eq!((stats.l1_hits, stats.l2_hits, stats.misses), (0, 1, 1));
+    assert_eq!((stats.promotions, stats.demotions), (1, 2));
+    assert!(cache.delete(&demoted).unwrap());
+    assert_eq!(cache.len(), 2);
+}
+
+#[cfg(feature = "global")]
+#[test]
+fn test_global_cache_handle() {
+    let first = nitro::global::<String, i32>("integration");
+    first
+        .with(|cache| cache.add(String::from("key1"), 1))
+        .unwrap()
+        .unwrap();
+
+    // Handles for the same name and types share one cache
+    let second = nitro::global::<String, i32>("integration");
+    assert_eq!(
+        second
+            .with(|cache| cache.get(&String::from("key1")))
+            .unwrap()
+            .unwrap(),
+        Some(1)
+    );
+
+    // Replacing the cache is visible through every handle
+    first.replace(SieveCache::new(8).unwrap());
+    assert_eq!(second.with(|cache| cache.capacity()).unwrap(), 8);
+    assert_eq!(second.with(|cache| cache.len()).unwrap(), 0);
+}
+
+#[cfg(feature = "global")]
+#[test]
+fn test_global_cache_rebuild() {
+    let handle = nitro::global::<u32, u32>("rebuild");
+    handle
+        .with(|cache| {
+            for key in 0..10 {
+                cache.add(key, key * 10).unwrap();
+            }
+        })
+        .unwrap();
+
+    // Shrinking keeps the newest entries
+    handle
+        .rebuild_with(SieveCache::builder().capacity(4))
+        .unwrap();
+    assert_eq!(handle.with(|cache| cache.capacity()).unwrap(), 4);
+    let mut entries: Vec<_> = handle.with(|cache| cache.iter().collect()).unwrap();
+    entries.sort();
+    assert_eq!(entries, vec![(6, 60), (7, 70), (8, 80), (9, 90)]);
+
+    // Growing again keeps everything
+    handle
+        .rebuild_with(SieveCache::builder().capacity(100))
+        .unwrap();
+    assert_eq!(handle.with(|cache| cache.len()).unwrap(), 4);
+    assert_eq!(
+        handle.with(|cache| cache.get(&9)).unwrap().unwrap(),
+        Some(90)
+    );
+}
+
+#[cfg(feature = "reaper")]
+#[test]
+fn test_reaper_removes_untouched_entries() {
+    let cache = Arc::new(Mutex::new(SieveCache::<String, i32>::new(4).unwrap()));
+    {
+        let mut guard = cache.lock().unwrap();
+        guard
+            .add_with_ttl(String::from("key1"), 1, Duration::from_millis(10))
+            .unwrap();
+        guard.add(String::from("key2"), 2).unwrap();
+    }
+
+    let reaper = nitro::start_reaper(&cache, Duration::from_millis(5));
+    std::thread::sleep(Duration::from_millis(100));
+    reaper.stop();
+
+    let guard = cache.lock().unwrap();
+    assert_eq!(guard.len(), 1);
+    assert_eq!(guard.get_stats().expirations, 1);
+}
+
+#[test]
+fn test_eviction_listener_causes() {
+    let removed = Arc::new(Mutex::new(Vec::new()));
+    let sink = removed.clone();
+    let clock = MockClock::new();
+    let mut cache: SieveCache<String, i32> = SieveCacheBuilder::new()
+        .capacity(2)
+        .clock(clock.clone())
+        .eviction_listener(move |key, value, cause| {
+            sink.lock().unwrap().push((key, value, cause));
+        })
+        .build()
+        .unwrap();
+
+    cache.add(String::from("a"), 1).unwrap();
+    cache.add(String::from("a"), 2).unwrap();
+    cache
+        .add_with_ttl(String::from("b"), 3, Duration::from_secs(1))
+        .unwrap();
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(cache.get(&String::from("b")).unwrap(), None);
+    cache.add(String::from("c"), 4).unwrap();
+    cache.add(String::from("d"), 5).unwrap();
+    cache.delete(&String::from("d")).unwrap();
+    cache.purge();
+
+    assert_eq!(
+        *removed.lock().unwrap(),
+        [
+            (String::from("a"), 1, RemovalCause::Replaced),
+            (String::from("b"), 3, RemovalCause::Expired),
+            // "a" was marked visited by its update, so "c" is the victim
+            (String::from("c"), 4, RemovalCause::Evicted),
+            (String::from("d"), 5, RemovalCause::Explicit),
+            (String::from("a"), 2, RemovalCause::Explicit),
+        ]
+    );
+}
+
+#[derive(Default)]
+struct CountingRecorder {
+    calls: Mutex<Vec<(&'static str, usize)>>,
+}
+
+impl StatsRecorder for CountingRecorder {
+    fn record_hits(&self, count: usize) {
+        self.calls.lock().unwrap().push(("hits", count));
+    }
+
+    fn record_misses(&self, count: usize) {
+        self.calls.lock().unwrap().push(("misses", count));
+    }
+
+    fn record_evictions(&self, count: usize) {
+        self.calls.lock().unwrap().push(("evictions", count));
+    }
+}
+
+#[test]
+fn test_stats_recorder_batches() {
+    let recorder = Arc::new(CountingRecorder::default());
+    let mut cache: SieveCache<i32, i32> = SieveCacheBuilder::new()
+        .capacity(1)
+        .stats_recorder(recorder.clone(), 3)
+        .build()
+        .unwrap();
+
+    cache.add(1, 1).unwrap();
+    cache.get(&1).unwrap();
+    cache.get(&2).unwrap();
+    assert!(recorder.calls.lock().unwrap().is_empty());
+
+    // The third event completes a batch
+    cache.add(2, 2).unwrap();
+    assert_eq!(
+        *recorder.calls.lock().unwrap(),
+        [("hits", 1), ("misses", 1), ("evictions", 1)]
+    );
+
+    cache.get(&2).unwrap();
+    drop(cache);
+    assert_eq!(recorder.calls.lock().unwrap().last(), Some(&("hits", 1)));
+}
+
+#[test]
+fn test_builder_configuration() {
+    let clock = MockClock::new();
+    let cache: SieveCache<String, i32> = SieveCache::builder()
+        .capacity(2)
+        .clock(clock.clone())
+        .expire_after_write(Duration::from_secs(10))
+        .eviction_interceptor(4, |key: &String, _: &i32| key != "pinned")
+        .initial_entries([
+            (String::from("pinned"), 1),
+            (String::from("a"), 2),
+            (String::from("b"), 3),
+        ])
+        .collect_stats(false)
+        .build()
+        .unwrap();
+
+    // The interceptor protected "pinned" while the initial entries went in
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&String::from("pinned")).unwrap(), Some(1));
+    assert_eq!(cache.get(&String::from("a")).unwrap(), None);
+
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(cache.get(&String::from("b")).unwrap(), None);
+    assert_eq!(cache.get_stats().hits, 0);
+    assert_eq!(cache.get_stats().expirations, 0);
+
+    assert!(SieveCache::<String, i32>::builder().build().is_err());
+}
+
+// Evicts the newest entry that was not read since it was inserted
+struct EvictNewest;
+
+impl EvictionPolicy<i32> for EvictNewest {
+    fn select_victim(
+        &mut self,
+        entries: &mut dyn EntryList<i32>,
+    ) -> Result<Option<i32>, CacheError> {
+        let mut candidate = entries.newest()?;
+        while let Some(key) = candidate {
+            if !entries.visited(&key)? {
+                return Ok(Some(key));
+            }
+            candidate = entries.older(&key)?;
+        }
+        entries.newest()
+    }
+}
+
+#[test]
+fn test_custom_eviction_policy() {
+    let mut cache = SieveCache::with_policy(3, EvictNewest).unwrap();
+    for key in 0..3 {
+        cache.add(key, key * 10).unwrap();
+    }
+    cache.get(&2).unwrap();
+    cache.add(3, 30).unwrap();
+    assert_eq!(cache.get(&1).unwrap(), None);
+    assert_eq!(cache.get(&0).unwrap(), Some(0));
+
+    // Policies can also be chosen at runtime
+    let policy: Box<dyn EvictionPolicy<i32>> = Box::new(EvictNewest);
+    let mut cache: SieveCache<i32, i32, _> = SieveCache::builder()
+        .capacity(1)
+        .policy(policy)
+        .build()
+        .unwrap();
+    cache.add(1, 1).unwrap();
+    cache.add(2, 2).unwrap();
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.get_stats().evictions, 1);
+}
+
+#[test]
+fn test_write_buffer_coalesces_and_drains() {
+    let flushed = Arc::new(Mutex::new(Vec::new()));
+    let sink = flushed.clone();
+    let buffer = WriteBuffer::with_flush_callback(2, OverflowPolicy::Drop, move |count| {
+        sink.lock().unwrap().push(count);
+    })
+    .unwrap();
+    let mut cache: SieveCache<String, i32> = SieveCache::new(4).unwrap();
+
+    assert!(buffer.push(String::from("a"), 1).unwrap());
+    assert!(buffer.push(String::from("b"), 2).unwrap());
+    // Rewriting a queued key does not take another slot
+    assert!(buffer.push(String::from("a"), 3).unwrap());
+    assert!(!buffer.push(String::from("c"), 4).unwrap());
+    assert_eq!(buffer.depth(), 2);
+
+    assert_eq!(buffer.drain_into(&mut cache).unwrap(), 2);
+    assert_eq!(cache.get(&String::from("a")).unwrap(), Some(3));
+    assert_eq!(cache.get(&String::from("c")).unwrap(), None);
+    assert_eq!(*flushed.lock().unwrap(), [2]);
+
+    let stats = buffer.stats();
+    assert_eq!(stats.depth, 0);
+    assert_eq!(stats.max_depth, 2);
+    assert_eq!(stats.coalesced, 1);
+    assert_eq!(stats.dropped, 1);
+    assert_eq!(stats.drained, 2);
+
+    // A blocked producer resumes once the queue is drained
+    let blocking = WriteBuffer::new(1, OverflowPolicy::Block).unwrap();
+    blocking.push(String::from("x"), 1).unwrap();
+    let producer = {
+        let blocking = blocking.clone();
+        std::thread::spawn(move || blocking.push(String::from("y"), 2).unwrap())
+    };
+    while blocking.depth() == 1 && !producer.is_finished() {
+        blocking.drain_into(&mut cache).unwrap();
+    }
+    assert!(producer.join().unwrap());
+    blocking.drain_into(&mut cache).unwrap();
+    assert_eq!(cache.get(&String::from("y")).unwrap(), Some(2));
+}
+
+#[test]
+fn test_warming_reports_misses_until_activated() {
+    let mut cache: SieveCache<String, i32> =
+        SieveCache::builder().capacity(2).warming().build().unwrap();
+    assert!(cache.is_warming());
+
+    cache.add(String::from("a"), 1).unwrap();
+    assert_eq!(cache.get(&String::from("a")).unwrap(), None);
+    assert_eq!(cache.get(&String::from("b")).unwrap(), None);
+    assert_eq!(cache.stats().hits, 0);
+    assert_eq!(cache.stats().misses, 0);
+
+    // Reads during warm-up still protect the entry from eviction
+    cache.add(String::from("b"), 2).unwrap();
+    cache.add(String::from("c"), 3).unwrap();
+
+    cache.activate();
+    assert_eq!(cache.get(&String::from("a")).unwrap(), Some(1));
+    assert_eq!(cache.get(&String::from("b")).unwrap(), None);
+    assert_eq!(cache.stats().hits, 1);
+    assert_eq!(cache.stats().misses, 1);
+}
+
+#[derive(Clone)]
+struct Token {
+    lifetime_secs: Option<u64>,
+}
+
+#[test]
+fn test_ttl_fn_derives_expiry_from_value() {
+    let clock = MockClock::new();
+    let mut cache: SieveCache<String, Token> = SieveCache::builder()
+        .capacity(4)
+        .clock(clock.clone())
+        .ttl_fn(|_, token: &Token| token.lifetime_secs.map(Duration::from_secs))
+        .build()
+        .unwrap();
+
+    cache
+        .add(
+            String::from("short"),
+            Token {
+                lifetime_secs: Some(5),
+            },
+        )
+        .unwrap();
+    cache
+        .add(
+            String::from("forever"),
+            Token {
+                lifetime_secs: None,
+            },
+        )
+        .unwrap();
+    cache
+        .add_with_ttl(
+            String::from("explicit"),
+            Token {
+                lifetime_secs: Some(5),
+            },
+            Duration::from_secs(60),
+        )
+        .unwrap();
+
+    clock.advance(Duration::from_secs(5));
+    assert!(cache.get(&String::from("short")).unwrap().is_none());
+    assert!(cache.get(&String::from("forever")).unwrap().is_some());
+    assert!(cache.get(&String::from("explicit")).unwrap().is_some());
+
+    // Modifying a value through the entry API re-derives its deadline
+    cache
+        .entry(String::from("forever"))
+        .unwrap()
+        .and_modify(|token| token.lifetime_secs = Some(1))
+        .unwrap();
+    clock.advance(Duration::from_secs(1));
+    assert!(cache.get(&String::from("forever")).unwrap().is_none());
+}
+
+#[test]
+fn test_per_class_stats() {
+    let mut cache: SieveCache<String, i32> = SieveCache::builder()
+        .capacity(4)
+        .key_classifier(|key: &String| {
+            if key.starts_with("user:") {
+                "users"
+            } else {
+                "feed"
+            }
+        })
+        .build()
+        .unwrap();
+
+    cache.add(String::from("user:1"), 1).unwrap();
+    cache.get(&String::from("user:1")).unwrap();
+    cache.get(&String::from("user:2")).unwrap();
+    cache.get(&String::from("feed:1")).unwrap();
+    // Per-class counts are applied by the next `&mut` call
+    cache.run_maintenance().unwrap();
+
+    let snapshot = cache.stats_snapshot();
+    assert_eq!(snapshot.overall.hits, 1);
+    assert_eq!(snapshot.overall.misses, 2);
+    assert_eq!(snapshot.classes["users"], ClassStats { hits: 1, misses: 1 });
+    assert_eq!(snapshot.classes["feed"].hit_rate(), 0.0);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_testing_fixtures() {
+    use nitro::testing::{assert_invariants, EventCapture, ScriptedLoader};
+
+    let clock = MockClock::new();
+    let loader = ScriptedLoader::new();
+    loader.set(String::from("a"), 1);
+    let capture = EventCapture::new();
+    let mut cache: SieveCache<String, i32> = SieveCache::builder()
+        .capacity(1)
+        .clock(clock.clone())
+        .eviction_listener(capture.listener())
+        .build()
+        .unwrap();
+
+    for _ in 0..2 {
+        cache
+            .get_or_insert_with(String::from("a"), || {
+                loader.load(&String::from("a")).unwrap()
+            })
+            .unwrap();
+    }
+    assert_eq!(loader.calls(&String::from("a")), 1);
+
+    cache
+        .add_with_ttl(String::from("b"), 2, Duration::from_secs(1))
+        .unwrap();
+    clock.advance(Duration::from_secs(1));
+    cache.remove_expired().unwrap();
+    assert_invariants(&cache);
+    assert_eq!(capture.keys(RemovalCause::Evicted), [String::from("a")]);
+    assert_eq!(capture.keys(RemovalCause::Expired), [String::from("b")]);
+}
+
+#[test]
+fn test_collect_and_extend() {
+    let source: HashMap<String, usize> = ["one", "two", "three"]
+        .into_iter()
+        .map(|word| (word.to_string(), word.len()))
+        .collect();
+    let mut cache: SieveCache<String, usize> = source.into_iter().collect();
+    assert_eq!(cache.capacity(), 3);
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.get(&"three".to_string()).unwrap(), Some(5));
+
+    // Extending a full cache evicts like repeated adds
+    cache.extend([("four".to_string(), 4), ("five".to_string(), 4)]);
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.get(&"five".to_string()).unwrap(), Some(4));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_parallel_scan() {
+    use rayon::prelude::*;
+
+    let mut cache = SieveCache::new(1000).unwrap();
+    for key in 0..1000u64 {
+        cache.add(key, vec![0u8; key as usize % 7]).unwrap();
+    }
+    let bytes: usize = cache
+        .par_iter()
+        .unwrap()
+        .map(|(_, value)| value.len())
+        .sum();
+    let expected: usize = (0..1000).map(|key| key % 7).sum();
+    assert_eq!(bytes, expected);
+}
+
+#[cfg(feature = "actor")]
+#[test]
+fn test_actor_handle() {
+    let cache = SieveCache::<u32, u32>::new(512).unwrap().spawn_actor(8);
+    let workers: Vec<_> = (0..4)
+        .map(|t| {
+            let cache = cache.clone();
+            std::thread::spawn(move || {
+                for i in 0..100 {
+                    let key = t * 100 + i;
+                    cache.add(key, key * 2).unwrap();
+                    assert_eq!(cache.get(key).unwrap(), Some(key * 2));
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    assert_eq!(cache.len().unwrap(), 400);
+    assert_eq!(cache.get_stats().unwrap().hits, 400);
+    assert_eq!(cache.remove(7).unwrap(), Some(14));
+    assert_eq!(cache.peek(7).unwrap(), None);
+    assert_eq!(cache.with(|cache| cache.capacity()).unwrap(), 512);
+
+    // A panic on the actor thread stops it for every handle
+    let other = cache.clone();
+    assert!(cache.with(|_| panic!("boom")).is_err());
+    assert!(matches!(other.get(1), Err(CacheError::LockError(_))));
+}
+
+#[cfg(feature = "split")]
+#[test]
+fn test_split_handles() {
+    let clock = MockClock::new();
+    let (mut writer, reader) = SieveCache::<u32, u32>::with_clock(2, clock.clone())
+        .unwrap()
+        .split();
+    writer.add(1, 10).unwrap();
+    writer.add(2, 20).unwrap();
+    assert!(reader.is_empty());
+
+    writer.publish();
+    let other = writer.reader();
+    let seen = std::thread::spawn(move || other.get(&1)).join().unwrap();
+    assert_eq!(seen, Some(10));
+    assert_eq!(reader.get(&3), None);
+
+    // The visit to key 1 reaches the cache on publish and protects it
+    writer.publish();
+    writer.add(3, 30).unwrap();
+    assert!(writer.contains_key(&1));
+    assert!(!writer.contains_key(&2));
+    assert_eq!(reader.get(&2), Some(20));
+
+    writer.add_with_ttl(4, 40, Duration::from_secs(1)).unwrap();
+    writer.publish();
+    assert_eq!(reader.get(&4), Some(40));
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(reader.get(&4), None);
+
+    let cache = writer.into_inner();
+    let stats = cache.get_stats();
+    assert_eq!((stats.hits, stats.misses), (3, 2));
+}
+
+#[cfg(feature = "lock_stats")]
+#[test]
+fn test_lock_stats() {
+    let cache = Arc::new(nitro::ConcurrentSieveCache::<u32, u32>::new(1000).unwrap());
+    for i in 0..100 {
+        cache.add(i, i).unwrap();
+    }
+    let stats = cache.get_stats();
+    assert_eq!(stats.lock_contentions, 0);
+    assert_eq!(stats.map_resizes, 0);
+    cache
+        .with(|cache| cache.set_capacity(10_000))
+        .unwrap()
+        .unwrap();
+    assert_eq!(cache.get_stats().map_resizes, 1);
+
+    let (locked, wait) = std::sync::mpsc::channel();
+    let holder = {
+        let cache = cache.clone();
+        std::thread::spawn(move || {
+            cache
+                .with(|_| {
+                    locked.send(()).unwrap();
+                    std::thread::sleep(Duration::from_millis(50));
+                })
+                .unwrap();
+        })
+    };
+    wait.recv().unwrap();
+    assert_eq!(cache.get(&1).unwrap(), Some(1));
+    holder.join().unwrap();
+
+    let stats = cache.get_stats();
+    assert_eq!(stats.lock_contentions, 1);
+    assert!(stats.lock_wait >= Duration::from_millis(10));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_async_insert_coalesces_callers() {
+    let cache = nitro::ConcurrentSieveCache::<u32, String>::new(16).unwrap();
+    let calls = Arc::new(Mutex::new(0));
+    let fetch = |calls: Arc<Mutex<u32>>| async move {
+        *calls.lock().unwrap() += 1;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        Ok::<_, std::io::Error>(String::from("loaded"))
+    };
+
+    let (a, b, c) = tokio::join!(
+        cache.get_or_try_insert_with_async(1, || fetch(calls.clone())),
+        cache.get_or_try_insert_with_async(1, || fetch(calls.clone())),
+        cache.get_or_try_insert_with_async(1, || fetch(calls.clone())),
+    );
+    for value in [a, b, c] {
+        assert_eq!(value.unwrap(), "loaded");
+    }
+    assert_eq!(*calls.lock().unwrap(), 1);
+    assert_eq!(cache.get(&1).unwrap(), Some(String::from("loaded")));
+
+    let failed = cache
+        .get_or_try_insert_with_async(2, || async {
+            Err::<String, _>(std::io::Error::other("unreachable"))
+        })
+        .await;
+    assert!(matches!(failed, Err(CacheError::Load(_))));
+    assert_eq!(cache.get_stats().load_errors, 1);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_maintenance_task_sweeps_until_dropped() {
+    let clock = MockClock::new();
+    let cache = SieveCache::<u32, u32>::with_clock(16, clock.clone()).unwrap();
+    let cache = Arc::new(nitro::ConcurrentSieveCache::from(cache));
+    cache.add_with_ttl(1, 10, Duration::from_secs(1)).unwrap();
+
+    let guard =
+        cache.spawn_maintenance(&tokio::runtime::Handle::current(), Duration::from_millis(5));
+    clock.advance(Duration::from_secs(1));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(cache.is_empty());
+
+    drop(guard);
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    cache.add_with_ttl(2, 20, Duration::from_secs(1)).unwrap();
+    clock.advance(Duration::from_secs(1));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(cache.len(), 1);
+}
+
+#[cfg(feature = "stream")]
+#[test]
+fn test_iter_stream_in_chunks() {
+    use futures::StreamExt;
+
+    let cache = nitro::ConcurrentSieveCache::<u32, u32>::new(100).unwrap();
+    for i in 0..10 {
+        cache.add(i, i * 10).unwrap();
+    }
+    futures::executor::block_on(async {
+        let mut stream = cache.iter_stream().chunk_size(3);
+        let mut seen = Vec::new();
+        while let Some((key, value)) = stream.next().await {
+            assert_eq!(value, key * 10);
+            seen.push(key);
+            // Writers get in between chunks
+            if seen.len() == 3 {
+                cache.delete(&9).unwrap();
+            }
+        }
+        seen.sort();
+        assert_eq!(seen, (0..9).collect::<Vec<_>>());
+    });
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip_keeps_eviction_order() {
+    let mut original = SieveCache::<String, u32>::new(4).unwrap();
+    for (i, key) in ["a", "b", "c", "d"].into_iter().enumerate() {
+        original.add(key.to_string(), i as u32).unwrap();
+    }
+    original.get("a").unwrap();
+    original.add(String::from("e"), 4).unwrap();
+    original.get("c").unwrap();
+    original.pin("d");
+
+    let json = serde_json::to_string(&original).unwrap();
+    let mut restored: SieveCache<String, u32> = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        restored.iter().collect::<Vec<_>>(),
+        original.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(restored.policy().hand(), original.policy().hand());
+
+    for i in 0..6 {
+        let key = format!("new{}", i);
+        let evicted = (
+            original.put(key.clone(), i).unwrap(),
+            restored.put(key, i).unwrap(),
+        );
+        assert_eq!(evicted.0, evicted.1);
+    }
+    assert!(restored.contains_key("d"));
+
+    let overfull = json.replace("\"capacity\":4", "\"capacity\":1");
+    assert!(serde_json::from_str::<SieveCache<String, u32>>(&overfull).is_err());
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+fn test_snapshot_save_and_load() {
+    let dir = std::env::temp_dir().join(format!("nitro-snapshot-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cache.snap");
+
+    let mut cache = SieveCache::<u64, String>::new(8).unwrap();
+    for i in 0..8 {
+        cache.add(i, format!("value{}", i)).unwrap();
+    }
+    cache.get(&3).unwrap();
+    cache.save_to(&path).unwrap();
+
+    let mut loaded = SieveCache::<u64, String>::load_from(&path).unwrap();
+    assert_eq!(loaded.capacity(), 8);
+    assert_eq!(
+        loaded.iter().collect::<Vec<_>>(),
+        cache.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        loaded.put(100, String::new()).unwrap(),
+        cache.put(100, String::new()).unwrap()
+    );
+
+    // A snapshot from another format version is refused
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[8] = 99;
+    std::fs::write(&path, bytes).unwrap();
+    let error = SieveCache::<u64, String>::load_from(&path).unwrap_err();
+    assert!(matches!(error, CacheError::Persistence(_)));
+    assert!(error.to_string().contains("version 99"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+fn test_auto_snapshot_from_maintenance() {
+    use nitro::AutoSnapshot;
+
+    let dir = std::env::temp_dir().join(format!("nitro-auto-snapshot-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cache.snap");
+    let clock = MockClock::new();
+    let mut cache = SieveCache::<u64, u64>::with_clock(8, clock.clone()).unwrap();
+    cache.set_auto_snapshot(Some(
+        AutoSnapshot::new(&path)
+            .every(Duration::from_secs(60))
+            .after_writes(3),
+    ));
+
+    cache.add(1, 1).unwrap();
+    cache.add(2, 2).unwrap();
+    cache.run_maintenance().unwrap();
+    assert!(!path.exists());
+
+    cache.add(3, 3).unwrap();
+    cache.run_maintenance().unwrap();
+    assert_eq!(SieveCache::<u64, u64>::load_from(&path).unwrap().len(), 3);
+
+    // Nothing changed, so the interval passing alone writes nothing new
+    clock.advance(Duration::from_secs(60));
+    std::fs::remove_file(&path).unwrap();
+    cache.run_maintenance().unwrap();
+    assert!(!path.exists());
+
+    cache.add(4, 4).unwrap();
+    cache.run_maintenance().unwrap();
+    assert_eq!(SieveCache::<u64, u64>::load_from(&path).unwrap().len(), 4);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+fn test_operation_log_replay() {
+    use nitro::{LogRecord, OperationLog};
+
+    let dir = std::env::temp_dir().join(format!("nitro-oplog-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cache.log");
+
+    let mut cache = SieveCache::<u64, String>::new(8).unwrap();
+    cache.add(0, "unlogged".to_string()).unwrap();
+    cache.set_operation_log(Some(OperationLog::open(&path).unwrap()));
+    cache.add(1, "one".to_string()).unwrap();
+    cache
+        .add_many(vec![(2, "two".to_string()), (3, "three".to_string())])
+        .unwrap();
+    cache.add(1, "uno".to_string()).unwrap();
+    assert!(cache.delete(&2).unwrap());
+    cache.set_operation_log(None);
+    cache.add(4, "after".to_string()).unwrap();
+
+    let records = OperationLog::read::<u64, String>(&path).unwrap();
+    assert_eq!(records.len(), 5);
+    assert_eq!(records[4], LogRecord::Delete { key: 2 });
+
+    // A crash mid-append leaves a record cut short, which replay skips
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes.extend_from_slice(&[0x81, 0xa3, b'A']);
+    std::fs::write(&path, bytes).unwrap();
+
+    let mut replayed = SieveCache::<u64, String>::new(8).unwrap();
+    assert_eq!(replayed.replay(&path).unwrap(), 5);
+    let mut entries = replayed.iter().collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(entries, [(1, "uno".to_string()), (3, "three".to_string())]);
+
+    // Reopening drops the cut short record and appends after the others
+    replayed.set_operation_log(Some(OperationLog::open(&path).unwrap()));
+    replayed.remove(&3).unwrap();
+    let records = OperationLog::read::<u64, String>(&path).unwrap();
+    assert_eq!(records.len(), 6);
+    assert_eq!(records[5], LogRecord::Delete { key: 3 });
+    assert!(OperationLog::open(dir.join("missing/cache.log")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "export")]
+#[test]
+fn test_export_json_and_csv() {
+    use nitro::Format;
+
+    let clock = MockClock::new();
+    let mut cache = SieveCache::<String, Vec<u32>>::with_clock(4, clock.clone()).unwrap();
+    cache.add("plain".to_string(), vec![1]).unwrap();
+    clock.advance(Duration::from_millis(1500));
+    cache.add("has,comma".to_string(), vec![2, 3]).unwrap();
+    cache.get(&"plain".to_string()).unwrap();
+
+    let mut json = Vec::new();
+    cache.export(&mut json, Format::Json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([
+            {"key": "plain", "value": [1], "visited": true, "age_ms": 1500},
+            {"key": "has,comma", "value": [2, 3], "visited": false, "age_ms": 0},
+        ])
+    );
+
+    let mut csv = Vec::new();
+    cache.export(&mut csv, Format::Csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "key,value,visited,age_ms\n\
+         plain,[1],true,1500\n\
+         \"has,comma\",\"[2,3]\",false,0\n"
+    );
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+fn test_operation_log_covers_every_write() {
+    use nitro::OperationLog;
+
+    let dir = std::env::temp_dir().join(format!("nitro-oplog-all-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cache.log");
+    let clock = MockClock::new();
+
+    let mut cache = SieveCache::<String, u32>::with_clock(10, clock.clone()).unwrap();
+    cache.set_operation_log(Some(OperationLog::open(&path).unwrap()));
+    cache.add("gone".to_string(), 0).unwrap();
+    cache.purge();
+    cache
+        .add_with_ttl("ttl".to_string(), 1, Duration::from_secs(1))
+        .unwrap();
+    cache.add("cas".to_string(), 2).unwrap();
+    assert!(cache.compare_and_swap(&"cas".to_string(), &2, 20).unwrap());
+    assert_eq!(cache.probe("probe".to_string(), 3).unwrap(), (3, false));
+    cache
+        .entry("entry".to_string())
+        .unwrap()
+        .or_insert(4)
+        .unwrap();
+    cache
+        .entry("entry".to_string())
+        .unwrap()
+        .and_modify(|value| *value += 40)
+        .unwrap();
+    *cache.get_mut(&"probe".to_string()).unwrap().unwrap() = 30;
+    cache.add("x:1".to_string(), 5).unwrap();
+    assert_eq!(cache.invalidate_prefix("x:").unwrap(), 1);
+    cache.prime([("primed".to_string(), 6)]).unwrap();
+    cache.add("doomed".to_string(), 7).unwrap();
+    assert_eq!(cache.retain(|key, _| key.as_str() != "doomed").unwrap(), 1);
+
+    let replay_clock = MockClock::new();
+    let mut replayed = SieveCache::<String, u32>::with_clock(10, replay_clock.clone()).unwrap();
+    replayed.replay(&path).unwrap();
+    let sorted = |cache: &SieveCache<String, u32>| {
+        let mut entries: Vec<_> = cache.iter().collect();
+        entries.sort();
+        entries
+    };
+    assert_eq!(sorted(&replayed), sorted(&cache));
+
+    // The TTL given with add_with_ttl survives the replay
+    clock.advance(Duration::from_secs(1));
+    replay_clock.advance(Duration::from_secs(1));
+    assert_eq!(cache.get(&"ttl".to_string()).unwrap(), None);
+    assert_eq!(replayed.get(&"ttl".to_string()).unwrap(), None);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,35 @@
+//! Model checks of the locks inside `ConcurrentSieveCache`, run with
+//! `RUSTFLAGS="--cfg loom" cargo test --release --test loom`.
+#![cfg(loom)]
+
+use nitro::ConcurrentSieveCache;
+use std::sync::Arc;
+
+#[test]
+fn test_get_during_insert() {
+    loom::model(|| {
+        let cache = Arc::new(ConcurrentSieveCache::new(1).unwrap());
+        cache.add(1, 10).unwrap();
+
+        let reader = {
+            let cache = Arc::clone(&cache);
+            loom::thread::spawn(move || (cache.get(&1).unwrap(), cache.get(&2).unwrap()))
+        };
+        // Evicts 1 to make room, in any order with the reader's lookups
+        cache.add(2, 20).unwrap();
+        let (first, second) = reader.join().unwrap();
+
+        // Each lookup sees the cache before or after the insert, never in
+        // between, and a lookup of 2 that came too early means the earlier
+        // lookup of 1 did as well
+        assert!(matches!(first, None | Some(10)));
+        assert!(matches!(second, None | Some(20)));
+        if second.is_none() {
+            assert_eq!(first, Some(10));
+        }
+        assert_eq!(cache.get(&2).unwrap(), Some(20));
+        assert_eq!(cache.len(), 1);
+        let stats = cache.get_stats();
+        assert_eq!(stats.hits + stats.misses, 3);
+    });
+}